@@ -0,0 +1,17 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BufferMessage } from "./BufferMessage";
+import type { ClientHelloMessage } from "./ClientHelloMessage";
+import type { ExecuteCommandMessage } from "./ExecuteCommandMessage";
+import type { FocusFloatMessage } from "./FocusFloatMessage";
+import type { OpenFloatMessage } from "./OpenFloatMessage";
+import type { PluginBufferMessage } from "./PluginBufferMessage";
+import type { RegisterCommandMessage } from "./RegisterCommandMessage";
+import type { RegisterKeymapMessage } from "./RegisterKeymapMessage";
+import type { SetColorsMessage } from "./SetColorsMessage";
+import type { SetContentMessage } from "./SetContentMessage";
+import type { SetCursorsMessage } from "./SetCursorsMessage";
+import type { SetTextMessage } from "./SetTextMessage";
+import type { ShowMessageMessage } from "./ShowMessageMessage";
+import type { SubscribeMessage } from "./SubscribeMessage";
+
+export type ClientMessages = { "message_type": "hello", "info": ClientHelloMessage } | { "message_type": "setContent", "info": SetContentMessage } | { "message_type": "setText", "info": SetTextMessage } | { "message_type": "setColors", "info": SetColorsMessage } | { "message_type": "setCursors", "info": SetCursorsMessage } | { "message_type": "executeCommand", "info": ExecuteCommandMessage } | { "message_type": "registerCommand", "info": RegisterCommandMessage } | { "message_type": "registerKeymap", "info": RegisterKeymapMessage } | { "message_type": "showMessage", "info": ShowMessageMessage } | { "message_type": "subscribe", "info": SubscribeMessage } | { "message_type": "unsubscribe", "info": SubscribeMessage } | { "message_type": "buffer", "info": BufferMessage } | { "message_type": "destroyBuffer", "info": PluginBufferMessage } | { "message_type": "openFloat", "info": OpenFloatMessage } | { "message_type": "closeFloat", "info": PluginBufferMessage } | { "message_type": "focusFloat", "info": FocusFloatMessage };
@@ -2,5 +2,6 @@
 import type { ColorRange } from "./ColorRange";
 import type { EditorCursorContext } from "./EditorCursorContext";
 import type { EditorWindow } from "./EditorWindow";
+import type { Register } from "./Register";
 
-export type EditorBufferContext = { cursors: Array<EditorCursorContext>, content: Array<string>, colors: Array<Array<ColorRange>>, memory: Array<string>, bufferWindow: EditorWindow, tabWidth: number, };
+export type EditorBufferContext = { cursors: Array<EditorCursorContext>, content: Array<string>, colors: Array<Array<ColorRange>>, registers: { [key in string]?: Register }, bufferWindow: EditorWindow, tabWidth: number, };
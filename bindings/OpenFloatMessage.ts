@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { EditorWindow } from "./EditorWindow";
+
+export type OpenFloatMessage = { bufferName: string, window: EditorWindow, zIndex: number, border: boolean, title: string | null, focus: boolean, };
@@ -1,6 +1,16 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BufferEventMessage } from "./BufferEventMessage";
+import type { BufferInputMessage } from "./BufferInputMessage";
 import type { BufferMessage } from "./BufferMessage";
+import type { CommandMessage } from "./CommandMessage";
 import type { ContextMessage } from "./ContextMessage";
+import type { CursorMovedMessage } from "./CursorMovedMessage";
+import type { HelloMessage } from "./HelloMessage";
 import type { InputMessage } from "./InputMessage";
+import type { KeymapMessage } from "./KeymapMessage";
+import type { ModeChangedMessage } from "./ModeChangedMessage";
+import type { ResizedMessage } from "./ResizedMessage";
+import type { ResponseMessage } from "./ResponseMessage";
+import type { TextChangedMessage } from "./TextChangedMessage";
 
-export type ServerMessages = { "message_type": "context", "info": ContextMessage } | { "message_type": "input", "info": InputMessage } | { "message_type": "buffer", "info": BufferMessage };
+export type ServerMessages = { "message_type": "hello", "info": HelloMessage } | { "message_type": "context", "info": ContextMessage } | { "message_type": "input", "info": InputMessage } | { "message_type": "buffer", "info": BufferMessage } | { "message_type": "response", "info": ResponseMessage } | { "message_type": "command", "info": CommandMessage } | { "message_type": "keymap", "info": KeymapMessage } | { "message_type": "bufferInput", "info": BufferInputMessage } | { "message_type": "modeChanged", "info": ModeChangedMessage } | { "message_type": "bufferOpened", "info": BufferEventMessage } | { "message_type": "bufferSaved", "info": BufferEventMessage } | { "message_type": "bufferClosed", "info": BufferEventMessage } | { "message_type": "textChanged", "info": TextChangedMessage } | { "message_type": "cursorMoved", "info": CursorMovedMessage } | { "message_type": "resized", "info": ResizedMessage };
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type SetTextMessage = { bufferName: string | null, start: [number, number], end: [number, number], text: string, };
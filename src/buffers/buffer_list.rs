@@ -0,0 +1,182 @@
+use std::path::Path;
+
+use crate::{
+    command::recovery::check_swap_file,
+    editor::Editor,
+    files::normalize_path,
+    layout::{replace_buffer, show_buffer},
+    server::{events::buffer_event, server_messages::ServerMessages},
+};
+
+use super::editor_buffer::init_file_buffer;
+
+#[derive(Clone, Debug)]
+pub struct BufferEntry {
+    pub number: usize,
+    pub key: String,
+}
+
+// File buffers in the order they were opened. Numbers are never reused, so
+// `:b N` keeps pointing at the same buffer after others are deleted.
+#[derive(Clone, Default)]
+pub struct BufferList {
+    entries: Vec<BufferEntry>,
+    last_number: usize,
+}
+
+impl BufferList {
+    // Lists a new buffer and returns the key it is stored under.
+    pub fn add(&mut self) -> String {
+        self.last_number += 1;
+        let key = format!("buffer{}", self.last_number);
+        self.entries.push(BufferEntry {
+            number: self.last_number,
+            key: key.clone(),
+        });
+        key
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.entries.retain(|entry| entry.key != key);
+    }
+
+    pub fn entries(&self) -> &[BufferEntry] {
+        &self.entries
+    }
+
+    pub fn key_of(&self, number: usize) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|entry| entry.number == number)
+            .map(|entry| entry.key.as_str())
+    }
+
+    pub fn number_of(&self, key: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .find(|entry| entry.key == key)
+            .map(|entry| entry.number)
+    }
+
+    // The buffer `offset` places away from `key`, wrapping around the list.
+    pub fn cycle(&self, key: &str, offset: isize) -> Option<&str> {
+        let len = self.entries.len() as isize;
+        if len == 0 {
+            return None;
+        }
+
+        let idx = self
+            .entries
+            .iter()
+            .position(|entry| entry.key == key)
+            .map(|idx| idx as isize)
+            .unwrap_or(if offset > 0 { -1 } else { 0 });
+
+        let next = (idx + offset).rem_euclid(len) as usize;
+        Some(self.entries[next].key.as_str())
+    }
+}
+
+// Returns the key of the buffer editing `file_path`, loading it if needed.
+pub fn open_file_buffer(editor: &mut Editor, file_path: Option<&str>) -> String {
+    let root = Path::new(&editor.root);
+    let target = file_path.map(|file_path| normalize_path(root, file_path));
+    let existing = editor.buffer_list.entries().iter().find(|entry| {
+        target.is_some()
+            && editor
+                .get_buffer(&entry.key)
+                .and_then(|buffer| buffer.file_name.as_deref())
+                .map(|file_name| normalize_path(root, file_name))
+                == target
+    });
+    if let Some(entry) = existing {
+        return entry.key.clone();
+    }
+
+    let tab_width = editor.config.editor.tab_width;
+    // Loads resolve against `root` like the lookup above, not the working directory.
+    let file_path = target.map(|target| target.to_string_lossy().to_string());
+    let mut buffer = init_file_buffer(file_path.as_deref(), editor.terminal_size, tab_width);
+    buffer.expand_tab = editor.config.editor.expand_tab;
+    let key = editor.buffer_list.add();
+    editor.add_buffer(key.clone(), buffer);
+    let opened = buffer_event(editor, &key);
+    editor.plugins.publish(ServerMessages::BufferOpened(opened));
+    check_swap_file(editor, &key);
+    key
+}
+
+pub fn focus_buffer(editor: &mut Editor, key: &str) {
+    show_buffer(editor, key);
+}
+
+pub fn close_buffer(editor: &mut Editor, key: &str, force: bool) -> Result<(), String> {
+    let number = editor
+        .buffer_list
+        .number_of(key)
+        .ok_or("Cannot delete a buffer that is not listed")?;
+    if !force
+        && editor
+            .get_buffer(key)
+            .is_some_and(|buffer| buffer.is_modified)
+    {
+        return Err(format!(
+            "No write since last change for buffer {} (add ! to override)",
+            number
+        ));
+    }
+
+    // Panes showing the buffer move on to the next one instead of closing.
+    if editor.layout.panes.values().any(|pane| pane.buffer == key) {
+        let next = match editor.buffer_list.cycle(key, 1) {
+            Some(next) if next != key => next.to_string(),
+            _ => open_file_buffer(editor, None),
+        };
+        replace_buffer(editor, key, &next);
+    }
+
+    let closed = buffer_event(editor, key);
+    editor.buffer_list.remove(key);
+    if let Some(buffer) = editor.buffers.remove(key) {
+        buffer.remove_swap();
+    }
+    editor.plugins.publish(ServerMessages::BufferClosed(closed));
+    editor.buffers_to_show.retain(|shown| shown != key);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::{temp_dir, test_editor};
+
+    #[test]
+    fn one_buffer_per_file() {
+        let dir = temp_dir("one_buffer_per_file");
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.rs"), "fn main() {}\n").unwrap();
+        let (mut editor, _) = test_editor(dir.to_str().unwrap(), (80, 24));
+
+        let absolute = dir.join("a.rs").to_string_lossy().to_string();
+        let key = open_file_buffer(&mut editor, Some(&absolute));
+        for name in ["a.rs", "./a.rs", "sub/../a.rs"] {
+            assert_eq!(open_file_buffer(&mut editor, Some(name)), key);
+        }
+        assert_eq!(editor.buffer_list.entries().len(), 1);
+
+        let buffer = editor.get_buffer(&key).unwrap();
+        assert_eq!(buffer.file_name.as_deref(), Some(absolute.as_str()));
+        assert_eq!(buffer.content.to_lines().join("\n"), "fn main() {}");
+
+        let other = open_file_buffer(&mut editor, Some("sub/new.rs"));
+        assert_ne!(other, key);
+        assert_eq!(open_file_buffer(&mut editor, Some("./sub/new.rs")), other);
+        let new_file = dir.join("sub/new.rs").to_string_lossy().to_string();
+        assert_eq!(
+            editor.get_buffer(&other).unwrap().file_name.as_deref(),
+            Some(new_file.as_str())
+        );
+    }
+}
@@ -1,266 +1,213 @@
+use std::sync::Arc;
+
 use crate::{
-    editor::{ColorRange, Editor, Rgb},
+    command::CommandPrompt,
+    editor::{Editor, EditorBuffer, EditorWindow, HandleKeysFn},
     editor_modes::EditorMode,
     insert::insert_chars_to_buffer,
-    normal::{move_cursors, CursorDirections},
+    normal::pending::handle_pending_key,
+    search::{repeat_search, search_word_under_cursor, SearchDirection},
+    text::{to_coord, to_cursor},
+    visual::{match_keys_visual, start_visual, VisualKind},
 };
-use std::{char, usize};
 
-use super::adapt_pivot_from_cursor;
+use super::sync_focus_buffer_lines;
 
 pub fn match_keys_insert(editor: &mut Editor, key: &str) {
-    let mut binding = editor.buffers.clone();
-    let buffer = binding.get_mut(&editor.focus_buffer).unwrap();
-
-    let mut binding = editor.buffers.clone();
-    let numeration_buffer = binding.get_mut("numerate_lines").unwrap();
+    let focus_buffer = editor.focus_buffer.clone();
+    let Some(mut focused) = editor.buffers.remove(&focus_buffer) else {
+        return;
+    };
+    let buffer = &mut focused;
 
     match key {
-        "<C-c>" | "<Esc>" => editor.editor_mode = EditorMode::Normal,
+        "<C-c>" | "<Esc>" => {
+            buffer.commit_undo();
+            editor.registers.finish_insert();
+            editor.editor_mode = EditorMode::Normal;
+        }
         "<Enter>" => {
-            for cursor in buffer.cursors.iter_mut() {
-                let mut string_to_insert = String::new();
-
-                if let Some(line) = buffer.content.get_mut(cursor.position.1 as usize) {
-                    let mut chars: Vec<char> = line.chars().collect();
-                    let remaining_chars = chars.split_off(cursor.position.0 as usize);
-
-                    string_to_insert = remaining_chars.iter().collect();
-
-                    *line = chars.iter().collect();
-                }
+            editor.registers.record_insert("\n");
+            for idx in 0..buffer.cursors.len() {
+                let (x, y) = buffer.cursors[idx].position;
+                let end = buffer.insert_text((y as usize, x as usize), "\n");
 
-                if cursor.position.1 as usize + 1 <= buffer.content.len() {
-                    buffer
-                        .content
-                        .insert(cursor.position.1 as usize + 1, string_to_insert);
-                } else {
-                    buffer.content.push(string_to_insert);
-                }
-
-                cursor.position.0 = 0;
-                cursor.position.1 += 1;
+                buffer.cursors[idx].position = to_cursor(end);
             }
         }
         "<Space>" => {
+            editor.registers.record_insert(" ");
             insert_chars_to_buffer(buffer, " ".chars().collect());
         }
         "<BS>" => {
-            for cursor in buffer.cursors.iter_mut() {
-                if cursor.position.0 > 0 {
-                    if let Some(line) = buffer.content.get_mut(cursor.position.1 as usize) {
-                        let mut chars: Vec<char> = line.chars().collect();
-
-                        if cursor.position.0 as usize <= chars.len() {
-                            chars.remove((cursor.position.0 - 1) as usize);
-                        } else {
-                            chars.pop();
-                        }
-                        *line = chars.into_iter().collect();
-                        cursor.position.0 -= 1;
-                    }
-                } else if cursor.position.1 > 0 {
-                    let current_line = buffer.content.remove(cursor.position.1 as usize);
-
-                    cursor.position.1 -= 1;
-                    cursor.position.0 = buffer.content[cursor.position.1 as usize].len() as u16;
-
-                    if let Some(prev_line) = buffer.content.get_mut(cursor.position.1 as usize) {
-                        prev_line.push_str(&current_line);
-                    }
+            editor.registers.record_backspace();
+            for idx in 0..buffer.cursors.len() {
+                let (x, y) = buffer.cursors[idx].position;
+                let (x, y) = (x as usize, y as usize);
+
+                if x > 0 {
+                    let x = x.min(buffer.line_len(y));
+                    let start = buffer.content.prev_grapheme(y, x);
+                    buffer.delete_text((y, start), (y, x));
+                    buffer.cursors[idx].position.0 = to_coord(start);
+                } else if y > 0 {
+                    let prev_len = buffer.line_len(y - 1);
+                    buffer.delete_text((y - 1, prev_len), (y, 0));
+                    buffer.cursors[idx].position = to_cursor((y - 1, prev_len));
                 }
             }
         }
         "<C-h>" => {}
-        _ if key.len() == 3 => {
-            insert_chars_to_buffer(buffer, vec![key.chars().nth(1).unwrap()]);
+        _ if key.chars().count() == 3 => {
+            let ch = key.chars().nth(1).unwrap();
+            editor.registers.record_insert(&ch.to_string());
+            insert_chars_to_buffer(buffer, vec![ch]);
         }
         "<A-BS>" => {
-            for cursor in buffer.cursors.iter_mut() {
-                if cursor.position.0 > 0 {
-                    if let Some(line) = buffer.content.get_mut(cursor.position.1 as usize) {
-                        let mut chars: Vec<char> = line.chars().collect();
-
-                        let remaining_chars = chars.split_off(cursor.position.0 as usize);
-
-                        chars.pop();
-
-                        if let Some(cut_idx) = chars.iter().rposition(|&c| !c.is_alphanumeric()) {
-                            let _ = chars.split_off(cut_idx + 1);
-                            chars.extend(remaining_chars);
-                            cursor.position.0 = (cut_idx + 1) as u16;
-                            *line = chars.into_iter().collect();
-                        } else {
-                            *line = remaining_chars.into_iter().collect();
-                            cursor.position.0 = 0;
-                        }
-                    }
-                } else if cursor.position.1 > 0 {
-                    let current_line = buffer.content.remove(cursor.position.1 as usize);
-
-                    cursor.position.1 -= 1;
-                    cursor.position.0 = buffer.content[cursor.position.1 as usize].len() as u16;
-
-                    if let Some(prev_line) = buffer.content.get_mut(cursor.position.1 as usize) {
-                        prev_line.push_str(&current_line);
-                    }
+            for idx in 0..buffer.cursors.len() {
+                let (x, y) = buffer.cursors[idx].position;
+                let (x, y) = (x as usize, y as usize);
+
+                if x > 0 {
+                    let mut chars: Vec<char> =
+                        buffer.content.line_string(y).chars().take(x).collect();
+                    chars.pop();
+
+                    let cut_idx = chars
+                        .iter()
+                        .rposition(|&c| !c.is_alphanumeric())
+                        .map(|idx| idx + 1)
+                        .unwrap_or(0);
+
+                    buffer.delete_text((y, cut_idx), (y, x));
+                    buffer.cursors[idx].position.0 = to_coord(cut_idx);
+                } else if y > 0 {
+                    let prev_len = buffer.line_len(y - 1);
+                    buffer.delete_text((y - 1, prev_len), (y, 0));
+                    buffer.cursors[idx].position = to_cursor((y - 1, prev_len));
                 }
             }
         }
         "<Tab>" => {
-            insert_chars_to_buffer(
-                buffer,
-                " ".repeat(buffer.tab_width as usize)
-                    .chars()
-                    .into_iter()
-                    .collect(),
-            );
+            let indent = match buffer.expand_tab {
+                true => " ".repeat(buffer.tab_width as usize),
+                false => "\t".to_string(),
+            };
+            editor.registers.record_insert(&indent);
+            insert_chars_to_buffer(buffer, indent.chars().collect());
         }
         _ => {}
     }
 
-    let current_lines = numeration_buffer.content.len();
-    let new_lines = buffer.content.len();
-
-    if current_lines < new_lines {
-        for i in current_lines..new_lines {
-            numeration_buffer.content.push(format!("{:>6} ", i + 1));
-            numeration_buffer.colors.push(vec![ColorRange {
-                range: (0, 7),
-                bg_color: Some(Rgb(42, 42, 55)),
-                fg_color: Some(Rgb(84, 83, 108)),
-            }]);
-        }
-    } else if current_lines > new_lines {
-        let _ = numeration_buffer.content.truncate(new_lines);
-    }
-
-    adapt_pivot_from_cursor(&buffer.clone().cursors[0], buffer);
-
-    numeration_buffer.pivot.1 = buffer.pivot.1;
-
-    let _ = editor
-        .buffers
-        .insert("main".to_string(), buffer.clone())
-        .unwrap();
-    let _ = editor
-        .buffers
-        .insert("numerate_lines".to_string(), numeration_buffer.clone())
-        .unwrap();
+    editor.buffers.insert(focus_buffer, focused);
+    sync_focus_buffer_lines(editor);
 }
 
 pub fn match_keys_normal(editor: &mut Editor, key: &str) {
-    let mut binding = editor.buffers.clone();
-    let buffer = binding.get_mut(&editor.focus_buffer).unwrap();
+    let focus_buffer = editor.focus_buffer.clone();
+    let Some(mut focused) = editor.buffers.remove(&focus_buffer) else {
+        return;
+    };
+    let buffer = &mut focused;
 
-    let mut binding = editor.buffers.clone();
-    let numeration_buffer = binding.get_mut("numerate_lines").unwrap();
+    let consumed = handle_pending_key(editor, buffer, key);
     match key {
+        _ if consumed => {}
         "<i>" => editor.editor_mode = EditorMode::Insert,
-        "<:>" => {
-            editor.editor_mode = EditorMode::Command;
-            editor.commands_hist.push(String::new());
+        "<v>" | "<V>" | "<C-v>" => {
+            if let Some(kind) = VisualKind::from_key(key) {
+                start_visual(editor, buffer, kind);
+            }
         }
-        "<h>" => move_cursors(buffer, CursorDirections::Left),
-        "<j>" => move_cursors(buffer, CursorDirections::Down),
-        "<k>" => move_cursors(buffer, CursorDirections::Up),
-        "<l>" => move_cursors(buffer, CursorDirections::Right),
-        "<w>" => {
-            for cursor in buffer.cursors.iter_mut() {
-                if let Some(line) = buffer.content.get_mut(cursor.position.1 as usize) {
-                    if cursor.position.0 + 1 < line.len() as u16 {
-                        let chars: Vec<char> = line.chars().collect();
-
-                        let flag = chars[cursor.position.0 as usize].is_alphanumeric();
-
-                        if chars[cursor.position.0 as usize] == ' ' {
-                            while cursor.position.0 + 1 < chars.len() as u16
-                                && chars[cursor.position.0 as usize] == ' '
-                            {
-                                cursor.position.0 += 1;
-                            }
-                        }
-
-                        while cursor.position.0 + 1 < chars.len() as u16
-                            && flag == chars[cursor.position.0 as usize].is_alphanumeric()
-                        {
-                            cursor.position.0 += 1;
-                        }
-
-                        while cursor.position.0 + 1 < chars.len() as u16
-                            && chars[cursor.position.0 as usize] == ' '
-                        {
-                            cursor.position.0 += 1;
-                        }
-                    } else if cursor.position.1 + 1 < buffer.content.len() as u16 {
-                        cursor.position.0 = 0;
-                        cursor.position.1 += 1;
-                    }
-                }
+        "<u>" => {
+            let undone = buffer.undo();
+            if !undone {
+                editor
+                    .commands_hist
+                    .push("Already at oldest change".to_string());
+                editor.commands_hist.push(String::new());
             }
         }
-        "<b>" => {
-            for cursor in buffer.cursors.iter_mut() {
-                if let Some(line) = buffer.content.get_mut(cursor.position.1 as usize) {
-                    if cursor.position.0 > 0 {
-                        let chars: Vec<char> = line.chars().collect();
-
-                        if cursor.position.0 == chars.len() as u16 {
-                            cursor.position.0 -= 1;
-                        }
-
-                        let flag = chars[cursor.position.0 as usize].is_alphanumeric();
-
-                        if chars[cursor.position.0 as usize] == ' ' {
-                            while cursor.position.0 > 0 && chars[cursor.position.0 as usize] == ' '
-                            {
-                                cursor.position.0 -= 1;
-                            }
-                        }
-
-                        while cursor.position.0 > 0
-                            && flag == chars[cursor.position.0 as usize].is_alphanumeric()
-                        {
-                            cursor.position.0 -= 1;
-                        }
-
-                        while cursor.position.0 > 0 && chars[cursor.position.0 as usize] == ' ' {
-                            cursor.position.0 -= 1;
-                        }
-                    } else if cursor.position.1 > 0 {
-                        cursor.position.1 -= 1;
-                        if let Some(prev_line) = buffer.content.get(cursor.position.1 as usize) {
-                            cursor.position.0 = if prev_line.len() > 0 {
-                                (prev_line.len() - 1) as u16
-                            } else {
-                                0
-                            };
-                        }
-                    }
-                }
+        "<C-r>" => {
+            let redone = buffer.redo();
+            if !redone {
+                editor
+                    .commands_hist
+                    .push("Already at newest change".to_string());
+                editor.commands_hist.push(String::new());
             }
         }
+        "<:>" => {
+            editor.editor_mode = EditorMode::Command;
+            editor.command_prompt = CommandPrompt::Ex;
+            editor.commands_hist.push(String::new());
+        }
+        "</>" | "<?>" => {
+            let direction = if key == "</>" {
+                SearchDirection::Forward
+            } else {
+                SearchDirection::Backward
+            };
+            editor.editor_mode = EditorMode::Command;
+            editor.command_prompt = CommandPrompt::Search(direction);
+            editor.search.origin = buffer.cursors.first().map(|cursor| cursor.position);
+            editor.commands_hist.push(String::new());
+        }
+        "<n>" => repeat_search(editor, buffer, false),
+        "<N>" => repeat_search(editor, buffer, true),
+        "<*>" => search_word_under_cursor(editor, buffer, SearchDirection::Forward),
+        "<#>" => search_word_under_cursor(editor, buffer, SearchDirection::Backward),
         _ => {}
     }
 
-    adapt_pivot_from_cursor(&buffer.clone().cursors[0], buffer);
+    // A change operator leaves its deletion pending so the text typed in the
+    // following Insert session joins the same undo step.
+    if editor.editor_mode != EditorMode::Insert {
+        buffer.commit_undo();
+    }
 
-    numeration_buffer.pivot.1 = buffer.pivot.1;
+    editor.buffers.insert(focus_buffer, focused);
+    sync_focus_buffer_lines(editor);
+}
 
-    let _ = editor
-        .buffers
-        .insert("main".to_string(), buffer.clone())
-        .unwrap();
-    let _ = editor
-        .buffers
-        .insert("numerate_lines".to_string(), numeration_buffer.clone())
-        .unwrap();
+pub fn match_keys_visual_mode(editor: &mut Editor, key: &str) {
+    let focus_buffer = editor.focus_buffer.clone();
+    let Some(mut focused) = editor.buffers.remove(&focus_buffer) else {
+        return;
+    };
+
+    match_keys_visual(editor, &mut focused, key);
+
+    editor.buffers.insert(focus_buffer, focused);
+    sync_focus_buffer_lines(editor);
 }
 
 pub fn match_editor_mode(editor: &mut Editor, key: &str) {
     match editor.editor_mode {
         EditorMode::Insert => match_keys_insert(editor, key),
         EditorMode::Normal => match_keys_normal(editor, key),
+        EditorMode::Visual => match_keys_visual_mode(editor, key),
         _ => {}
     }
 }
+
+pub fn init_file_buffer(
+    file_path: Option<&str>,
+    terminal_size: (u16, u16),
+    tab_width: u16,
+) -> EditorBuffer {
+    let handle_keys: HandleKeysFn = Arc::new(|editor: &mut Editor, key: &str| {
+        match_editor_mode(editor, key);
+    });
+    let buffer_window = EditorWindow {
+        start: (9, 1),
+        end: (terminal_size.0, terminal_size.1 - 1),
+    };
+
+    match file_path {
+        Some(file_path) => {
+            EditorBuffer::from_file(file_path, handle_keys, buffer_window, tab_width)
+        }
+        None => EditorBuffer::new(handle_keys, buffer_window, tab_width),
+    }
+}
@@ -1,9 +1,11 @@
 use std::sync::Arc;
 
 use crate::{
+    command::CommandPrompt,
     editor::{Editor, EditorBuffer, EditorWindow},
     editor_modes::EditorMode,
     normal::{move_cursors, CursorDirections},
+    text::TextRope,
 };
 
 pub fn match_keys_normal(editor: &mut Editor, key: &str) {
@@ -11,6 +13,7 @@ pub fn match_keys_normal(editor: &mut Editor, key: &str) {
     match key {
         "<:>" => {
             editor.editor_mode = EditorMode::Command;
+            editor.command_prompt = CommandPrompt::Ex;
             editor.commands_hist.push(String::new());
         }
         "<h>" => move_cursors(buffer, CursorDirections::Left),
@@ -23,13 +26,12 @@ pub fn match_keys_normal(editor: &mut Editor, key: &str) {
 }
 
 pub fn match_editor_mode(editor: &mut Editor, key: &str) {
-    match editor.editor_mode {
-        EditorMode::Normal => match_keys_normal(editor, key),
-        _ => {}
+    if editor.editor_mode == EditorMode::Normal {
+        match_keys_normal(editor, key)
     }
 }
 
-pub fn init_explorer_buffer(root: &str, terminal_size: (u16, u16)) -> EditorBuffer {
+pub fn init_explorer_buffer(root: &str, terminal_size: (u16, u16), tab_width: u16) -> EditorBuffer {
     let mut buffer = EditorBuffer::new(
         Arc::new(|editor: &mut Editor, key: &str| {
             match_editor_mode(editor, key);
@@ -38,7 +40,7 @@ pub fn init_explorer_buffer(root: &str, terminal_size: (u16, u16)) -> EditorBuff
             start: (8, 1),
             end: (terminal_size.0, terminal_size.1 - 1),
         },
-        4,
+        tab_width,
     );
 
     let mut paths = vec![
@@ -50,14 +52,14 @@ pub fn init_explorer_buffer(root: &str, terminal_size: (u16, u16)) -> EditorBuff
         "../".to_string(),
     ];
 
-    for entry in std::fs::read_dir(&root).unwrap() {
+    for entry in std::fs::read_dir(root).unwrap() {
         let entry = entry.unwrap();
         let path = entry.path();
         let path = path.strip_prefix(root).unwrap();
         paths.push(path.to_str().unwrap().to_string());
     }
 
-    buffer.content = paths;
+    buffer.content = TextRope::from_lines(paths);
 
     buffer
 }
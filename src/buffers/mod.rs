@@ -1,5 +1,6 @@
-use crate::editor::{EditorBuffer, EditorCursor};
+use crate::editor::{Editor, EditorBuffer, EditorCursor};
 
+pub mod buffer_list;
 pub mod editor_buffer;
 pub mod explorer_buffer;
 
@@ -13,9 +14,22 @@ pub fn adapt_pivot_from_cursor(cursor: &EditorCursor, buffer: &mut EditorBuffer)
         buffer.pivot.1 = cursor.position.1;
     }
 
-    if cursor.position.0 > window_width + buffer.pivot.0 {
-        buffer.pivot.0 = cursor.position.0 - window_width;
-    } else if cursor.position.0 < buffer.pivot.0 {
-        buffer.pivot.0 = cursor.position.0;
+    let cursor_x = buffer.display_col(cursor.position);
+    if cursor_x > window_width + buffer.pivot.0 {
+        buffer.pivot.0 = cursor_x - window_width;
+    } else if cursor_x < buffer.pivot.0 {
+        buffer.pivot.0 = cursor_x;
+    }
+}
+
+// Keeps the focused pane scrolled so that its first cursor stays visible.
+pub fn sync_focus_buffer_lines(editor: &mut Editor) {
+    let focus_buffer = editor.focus_buffer.clone();
+    let Some(buffer) = editor.buffers.get_mut(&focus_buffer) else {
+        return;
+    };
+
+    if let Some(cursor) = buffer.cursors.first().cloned() {
+        adapt_pivot_from_cursor(&cursor, buffer);
     }
 }
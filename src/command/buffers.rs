@@ -0,0 +1,228 @@
+use crate::{
+    buffers::buffer_list::{close_buffer, focus_buffer, open_file_buffer},
+    command::{parser::ExCommand, recovery::recovery_prompt_open},
+    editor::Editor,
+};
+
+pub fn edit_command(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    let Some(file_path) = command.args.first() else {
+        return reload_command(editor, command);
+    };
+    let key = open_file_buffer(editor, Some(file_path));
+    focus_buffer(editor, &key);
+    if !recovery_prompt_open(editor) {
+        editor.commands_hist.push(format!("\"{}\"", file_path));
+    }
+    Ok(())
+}
+
+// `:e` without a file reads the current one again, `:e!` drops unsaved changes.
+fn reload_command(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    let focus_buffer = editor.focus_buffer.clone();
+    let buffer = editor
+        .get_buffer_mut(&focus_buffer)
+        .ok_or("No buffer to edit")?;
+    if buffer.is_modified && !command.bang {
+        return Err("No write since last change (add ! to override)".to_string());
+    }
+
+    buffer
+        .reload()
+        .map_err(|e| format!("Failed to reload file: {}", e))?;
+    let message = format!("\"{}\"", buffer.file_name.as_deref().unwrap_or_default());
+    editor.commands_hist.push(message);
+    Ok(())
+}
+
+pub fn cycle_buffer_command(
+    editor: &mut Editor,
+    command: &ExCommand,
+    forward: bool,
+) -> Result<(), String> {
+    let count = match command.args.first() {
+        Some(count) => count
+            .parse::<isize>()
+            .map_err(|_| format!("Trailing characters: {}", count))?,
+        None => 1,
+    };
+
+    let key = editor
+        .buffer_list
+        .cycle(&editor.focus_buffer, if forward { count } else { -count })
+        .ok_or("There is no listed buffer")?
+        .to_string();
+    focus_buffer(editor, &key);
+    Ok(())
+}
+
+// `:b N` selects by number, any other argument by a unique part of the name.
+fn find_buffer(editor: &Editor, target: &str) -> Result<String, String> {
+    if let Ok(number) = target.parse::<usize>() {
+        return editor
+            .buffer_list
+            .key_of(number)
+            .map(String::from)
+            .ok_or(format!("Buffer {} does not exist", number));
+    }
+
+    let matches: Vec<&str> = editor
+        .buffer_list
+        .entries()
+        .iter()
+        .filter(|entry| {
+            editor
+                .get_buffer(&entry.key)
+                .and_then(|buffer| buffer.file_name.as_deref())
+                .is_some_and(|file_name| file_name.contains(target))
+        })
+        .map(|entry| entry.key.as_str())
+        .collect();
+
+    match matches.as_slice() {
+        [key] => Ok(key.to_string()),
+        [] => Err(format!("No matching buffer for {}", target)),
+        _ => Err(format!("More than one match for {}", target)),
+    }
+}
+
+pub fn buffer_command(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    let Some(target) = command.args.first() else {
+        return Ok(());
+    };
+    let key = find_buffer(editor, target)?;
+    focus_buffer(editor, &key);
+    Ok(())
+}
+
+pub fn list_buffers_command(editor: &mut Editor, _command: &ExCommand) -> Result<(), String> {
+    let listed: Vec<String> = editor
+        .buffer_list
+        .entries()
+        .iter()
+        .filter_map(|entry| {
+            let buffer = editor.get_buffer(&entry.key)?;
+            Some(format!(
+                "{}{}{} \"{}\"",
+                entry.number,
+                if entry.key == editor.focus_buffer {
+                    " %a"
+                } else {
+                    ""
+                },
+                if buffer.is_modified { " +" } else { "" },
+                buffer.file_name.as_deref().unwrap_or("[No Name]")
+            ))
+        })
+        .collect();
+
+    editor.commands_hist.push(listed.join("  "));
+    Ok(())
+}
+
+pub fn delete_buffer_command(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    let key = match command.args.first() {
+        Some(target) => find_buffer(editor, target)?,
+        None => editor.focus_buffer.clone(),
+    };
+    close_buffer(editor, &key, command.bang)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::command::CommandPrompt;
+    use crate::editor_modes::EditorMode;
+    use crate::files::swap::{swap_path, SwapFile};
+    use crate::files::FileStamp;
+    use crate::testing::{buffer_text, editor_with_text, temp_dir, type_keys};
+    use crate::undo::EditOperation;
+
+    fn leave_swap_file(file_name: &str) {
+        let journal = [EditOperation::Insert {
+            position: (0, 0),
+            text: "x".to_string(),
+        }];
+        let swap = SwapFile::new(file_name, FileStamp::of(file_name), &journal);
+        swap.write(&swap_path(file_name).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn edit_offers_to_recover_every_swap_file() {
+        let dir = temp_dir("edit_offers_to_recover_every_swap_file");
+        let names: Vec<String> = ["a.txt", "b.txt"]
+            .iter()
+            .map(|name| dir.join(name).to_string_lossy().to_string())
+            .collect();
+        for name in names.iter() {
+            fs::write(name, "text\n").unwrap();
+            leave_swap_file(name);
+        }
+
+        let (mut editor, _) = editor_with_text("edit_offers_to_recover", "other\n");
+        for (name, answer) in names.iter().zip(["r", "d"]) {
+            type_keys(&mut editor, &format!(":e {}<Enter>", name));
+            assert_eq!(editor.editor_mode, EditorMode::Command);
+            assert_eq!(editor.command_prompt, CommandPrompt::Recover);
+            assert!(editor
+                .commands_hist
+                .last()
+                .unwrap()
+                .starts_with("Swap file found"));
+            type_keys(&mut editor, answer);
+            assert_eq!(editor.editor_mode, EditorMode::Normal);
+            assert!(editor.pending_recoveries.is_empty());
+        }
+        type_keys(&mut editor, &format!(":e {}<Enter>", names[0]));
+        assert_eq!(buffer_text(&editor), "xtext");
+
+        for name in names.iter() {
+            let _ = fs::remove_file(swap_path(name).unwrap());
+        }
+    }
+
+    #[test]
+    fn recovery_refuses_files_newer_than_the_swap() {
+        let dir = temp_dir("recovery_refuses_files_newer");
+        let name = dir.join("a.txt").to_string_lossy().to_string();
+        fs::write(&name, "text\n").unwrap();
+        leave_swap_file(&name);
+        // Someone wrote the file after the swap was made.
+        fs::write(&name, "newer text\n").unwrap();
+
+        let (mut editor, _) = editor_with_text("recovery_refuses_newer", "other\n");
+        type_keys(&mut editor, &format!(":e {}<Enter>", name));
+        let prompt = editor.commands_hist.last().unwrap().clone();
+        assert!(prompt.contains("file is newer") && !prompt.contains("[r]ecover"));
+
+        type_keys(&mut editor, "r");
+        assert_eq!(editor.command_prompt, CommandPrompt::Recover);
+        assert!(editor
+            .commands_hist
+            .iter()
+            .any(|entry| entry.contains("cannot recover")));
+        type_keys(&mut editor, "d");
+        assert_eq!(editor.editor_mode, EditorMode::Normal);
+        assert_eq!(buffer_text(&editor), "newer text");
+        assert!(!swap_path(&name).unwrap().exists());
+    }
+
+    #[test]
+    fn recovery_starts_from_the_snapshot() {
+        let dir = temp_dir("recovery_starts_from_the_snapshot");
+        let name = dir.join("a.txt").to_string_lossy().to_string();
+        fs::write(&name, "text\n").unwrap();
+        let journal = [EditOperation::Insert {
+            position: (0, 0),
+            text: "x".to_string(),
+        }];
+        let mut swap = SwapFile::new(&name, FileStamp::of(&name), &journal);
+        swap.snapshot = Some("one\ntwo".to_string());
+        swap.write(&swap_path(&name).unwrap()).unwrap();
+
+        let (mut editor, _) = editor_with_text("recovery_from_snapshot", "other\n");
+        type_keys(&mut editor, &format!(":e {}<Enter>r", name));
+        assert_eq!(buffer_text(&editor), "xone\ntwo");
+        let _ = fs::remove_file(swap_path(&name).unwrap());
+    }
+}
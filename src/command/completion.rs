@@ -0,0 +1,108 @@
+use std::fs;
+
+use crate::{command::EDITOR_COMMANDS, editor::Editor};
+
+// Candidates offered by the last <Tab>; pressing it again cycles through them.
+#[derive(Clone, Debug)]
+pub struct Completion {
+    base: String,
+    candidates: Vec<String>,
+    idx: usize,
+}
+
+fn command_candidates(prefix: &str) -> Vec<String> {
+    let mut candidates: Vec<String> = EDITOR_COMMANDS
+        .iter()
+        .filter(|command| command.name.starts_with(prefix))
+        .map(|command| command.name.to_string())
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+fn path_candidates(token: &str) -> Vec<String> {
+    let (dir, prefix) = match token.rfind('/') {
+        Some(idx) => (&token[..=idx], &token[idx + 1..]),
+        None => ("", token),
+    };
+
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return vec![];
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let suffix = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, suffix))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+// The first word is completed as a command name and later ones as paths.
+fn new_completion(entry: &str) -> Option<Completion> {
+    let (base, candidates) = match entry.rfind(char::is_whitespace) {
+        Some(idx) => (
+            entry[..=idx].to_string(),
+            path_candidates(&entry[idx + 1..]),
+        ),
+        None => {
+            let name_start = entry
+                .rfind(|ch: char| !ch.is_ascii_alphabetic())
+                .map(|idx| idx + 1)
+                .unwrap_or(0);
+            (
+                entry[..name_start].to_string(),
+                command_candidates(&entry[name_start..]),
+            )
+        }
+    };
+
+    if candidates.is_empty() {
+        return None;
+    }
+    Some(Completion {
+        base,
+        candidates,
+        idx: 0,
+    })
+}
+
+pub fn complete_command_line(editor: &mut Editor, forward: bool) {
+    let completion = match editor.completion.take() {
+        Some(mut completion) => {
+            let len = completion.candidates.len();
+            completion.idx = if forward {
+                (completion.idx + 1) % len
+            } else {
+                (completion.idx + len - 1) % len
+            };
+            completion
+        }
+        None => {
+            let entry = editor.commands_hist.last().cloned().unwrap_or_default();
+            let Some(completion) = new_completion(&entry) else {
+                return;
+            };
+            completion
+        }
+    };
+
+    if let Some(last_entry) = editor.commands_hist.last_mut() {
+        *last_entry = format!(
+            "{}{}",
+            completion.base, completion.candidates[completion.idx]
+        );
+    }
+
+    // A single candidate is final, so the next <Tab> can descend into it.
+    if completion.candidates.len() > 1 {
+        editor.completion = Some(completion);
+    }
+}
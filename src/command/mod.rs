@@ -1,67 +1,507 @@
-use std::collections::HashMap;
+use std::path::PathBuf;
 
 use lazy_static::lazy_static;
 use termion::color::{self};
 
 use crate::{
+    buffers::sync_focus_buffer_lines,
     editor::{Editor, EditorFunctions},
     editor_modes::EditorMode,
+    files::normalize_path,
+    layout::SplitAxis,
+    operator::{apply_operator, Operator, OperatorRange},
+    search::{browse_history, cancel_search, execute_search, preview_search, SearchDirection},
+    server::{
+        hosts::{messages_command, plugin_restart_command},
+        plugins::{list_plugins_command, run_plugin_command},
+    },
+    text::to_coord,
 };
 
-lazy_static! {
-    pub static ref EDITOR_COMMANDS: HashMap<&'static str, EditorFunctions> = {
-        let mut m: HashMap<&'static str, EditorFunctions> = HashMap::new();
-        m.insert(
-            "q",
-            Box::new(|editor: &mut Editor| {
-                editor.close_editor();
-            }),
-        );
+use self::{
+    buffers::{
+        buffer_command, cycle_buffer_command, delete_buffer_command, edit_command,
+        list_buffers_command,
+    },
+    completion::complete_command_line,
+    options::{colorscheme_command, set_command},
+    parser::{parse_ex_command, ExCommand},
+    recovery::match_keys_recovery,
+    substitute::{execute_substitute, match_keys_substitute},
+    windows::{close_command, only_command, resize_command, split_command},
+};
 
-        m.insert(
-            "w",
-            Box::new(|editor: &mut Editor| {
-                if let Some(buffer) = editor.get_buffer(&editor.focus_buffer) {
-                    match buffer.write_file() {
-                        Ok(_) => editor.commands_hist.push(format!(
-                            "File saved: {}",
-                            buffer.file_name.as_ref().unwrap()
-                        )),
-                        Err(e) => editor
-                            .commands_hist
-                            .push(format!("Failed to save file: {}", e)),
-                    }
+pub mod buffers;
+pub mod completion;
+pub mod options;
+pub mod parser;
+pub mod recovery;
+pub mod substitute;
+pub mod windows;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CommandPrompt {
+    Ex,
+    Search(SearchDirection),
+    Substitute,
+    Recover,
+}
+
+impl CommandPrompt {
+    pub fn label(&self) -> String {
+        match self {
+            CommandPrompt::Ex => "Command: ".to_string(),
+            CommandPrompt::Search(direction) => direction.symbol().to_string(),
+            CommandPrompt::Substitute | CommandPrompt::Recover => String::new(),
+        }
+    }
+}
 
-                    editor.commands_hist.push(String::new());
-                    editor.editor_mode = EditorMode::Normal;
+pub struct EditorCommand {
+    pub name: &'static str,
+    // Shortest prefix of `name` that still selects this command.
+    pub abbreviation: &'static str,
+    pub function: EditorFunctions,
+}
+
+lazy_static! {
+    pub static ref EDITOR_COMMANDS: Vec<EditorCommand> = vec![
+        EditorCommand {
+            name: "quit",
+            abbreviation: "q",
+            function: Box::new(quit_command),
+        },
+        EditorCommand {
+            name: "write",
+            abbreviation: "w",
+            function: Box::new(write_command),
+        },
+        EditorCommand {
+            name: "wq",
+            abbreviation: "wq",
+            function: Box::new(|editor: &mut Editor, command: &ExCommand| {
+                write_command(editor, command)?;
+                quit_command(editor, command)
+            }),
+        },
+        EditorCommand {
+            name: "undo",
+            abbreviation: "u",
+            // `:undo N` goes to the state after change N, on whichever branch.
+            function: Box::new(|editor: &mut Editor, command: &ExCommand| {
+                let focus_buffer = editor.focus_buffer.clone();
+                let Some(buffer) = editor.get_buffer_mut(&focus_buffer) else {
+                    return Ok(());
+                };
+                if let Some(arg) = command.args.first() {
+                    let change = arg
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid change number: {}", arg))?;
+                    return buffer.undo_to(change);
+                }
+                match buffer.undo() {
+                    true => Ok(()),
+                    false => Err("Already at oldest change".to_string()),
+                }
+            }),
+        },
+        EditorCommand {
+            name: "redo",
+            abbreviation: "red",
+            function: Box::new(|editor: &mut Editor, _command: &ExCommand| {
+                let focus_buffer = editor.focus_buffer.clone();
+                match editor
+                    .get_buffer_mut(&focus_buffer)
+                    .map(|buffer| buffer.redo())
+                {
+                    Some(false) => Err("Already at newest change".to_string()),
+                    _ => Ok(()),
                 }
             }),
+        },
+        EditorCommand {
+            name: "substitute",
+            abbreviation: "s",
+            function: Box::new(execute_substitute),
+        },
+        EditorCommand {
+            name: "delete",
+            abbreviation: "d",
+            function: Box::new(|editor: &mut Editor, command: &ExCommand| {
+                lines_operator_command(editor, command, Operator::Delete)
+            }),
+        },
+        EditorCommand {
+            name: "yank",
+            abbreviation: "y",
+            function: Box::new(|editor: &mut Editor, command: &ExCommand| {
+                lines_operator_command(editor, command, Operator::Yank)
+            }),
+        },
+        EditorCommand {
+            name: "edit",
+            abbreviation: "e",
+            function: Box::new(edit_command),
+        },
+        EditorCommand {
+            name: "bnext",
+            abbreviation: "bn",
+            function: Box::new(|editor: &mut Editor, command: &ExCommand| {
+                cycle_buffer_command(editor, command, true)
+            }),
+        },
+        EditorCommand {
+            name: "bprevious",
+            abbreviation: "bp",
+            function: Box::new(|editor: &mut Editor, command: &ExCommand| {
+                cycle_buffer_command(editor, command, false)
+            }),
+        },
+        EditorCommand {
+            name: "buffer",
+            abbreviation: "b",
+            function: Box::new(buffer_command),
+        },
+        EditorCommand {
+            name: "ls",
+            abbreviation: "ls",
+            function: Box::new(list_buffers_command),
+        },
+        EditorCommand {
+            name: "buffers",
+            abbreviation: "buffers",
+            function: Box::new(list_buffers_command),
+        },
+        EditorCommand {
+            name: "bdelete",
+            abbreviation: "bd",
+            function: Box::new(delete_buffer_command),
+        },
+        EditorCommand {
+            name: "split",
+            abbreviation: "sp",
+            function: Box::new(|editor: &mut Editor, command: &ExCommand| {
+                split_command(editor, command, SplitAxis::Horizontal)
+            }),
+        },
+        EditorCommand {
+            name: "vsplit",
+            abbreviation: "vs",
+            function: Box::new(|editor: &mut Editor, command: &ExCommand| {
+                split_command(editor, command, SplitAxis::Vertical)
+            }),
+        },
+        EditorCommand {
+            name: "close",
+            abbreviation: "clo",
+            function: Box::new(close_command),
+        },
+        EditorCommand {
+            name: "only",
+            abbreviation: "on",
+            function: Box::new(only_command),
+        },
+        EditorCommand {
+            name: "resize",
+            abbreviation: "res",
+            function: Box::new(resize_command),
+        },
+        EditorCommand {
+            name: "set",
+            abbreviation: "se",
+            function: Box::new(set_command),
+        },
+        EditorCommand {
+            name: "colorscheme",
+            abbreviation: "colo",
+            function: Box::new(colorscheme_command),
+        },
+        EditorCommand {
+            name: "plugins",
+            abbreviation: "plugins",
+            function: Box::new(list_plugins_command),
+        },
+        EditorCommand {
+            name: "PluginRestart",
+            abbreviation: "PluginRestart",
+            function: Box::new(plugin_restart_command),
+        },
+        EditorCommand {
+            name: "messages",
+            abbreviation: "mes",
+            function: Box::new(messages_command),
+        },
+    ];
+}
+
+// `:wri` selects `write` because it lies between the abbreviation and the
+// full name. An exact name always wins, and a name that could mean several
+// commands selects none of them.
+fn lookup_command<'a>(
+    commands: &'a [EditorCommand],
+    name: &str,
+) -> Result<Option<&'a EditorCommand>, String> {
+    if let Some(command) = commands.iter().find(|command| command.name == name) {
+        return Ok(Some(command));
+    }
+
+    let abbreviated: Vec<&EditorCommand> = commands
+        .iter()
+        .filter(|command| command.name.starts_with(name) && name.starts_with(command.abbreviation))
+        .collect();
+    let prefixed = commands
+        .iter()
+        .filter(|command| command.name.starts_with(name))
+        .count();
+    match abbreviated.as_slice() {
+        [command] => Ok(Some(command)),
+        [] if prefixed < 2 => Ok(None),
+        _ => Err(format!("Ambiguous command: {}", name)),
+    }
+}
+
+pub fn find_command(name: &str) -> Result<Option<&'static EditorCommand>, String> {
+    lookup_command(&EDITOR_COMMANDS, name)
+}
+
+fn quit_command(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    if editor.layout.pane_count() > 1 {
+        return close_command(editor, command);
+    }
+
+    let modified = editor.buffers.values().any(|buffer| buffer.is_modified);
+    if modified && !command.bang {
+        return Err("No write since last change (add ! to override)".to_string());
+    }
+
+    editor.close_editor();
+    Ok(())
+}
+
+fn write_command(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    let root = PathBuf::from(&editor.root);
+    let focus_buffer = editor.focus_buffer.clone();
+    let buffer = editor
+        .get_buffer_mut(&focus_buffer)
+        .ok_or("No buffer to write")?;
+
+    // Naming the buffer's own file is a plain write.
+    let own_file = buffer
+        .file_name
+        .as_deref()
+        .map(|file_name| normalize_path(&root, file_name));
+    let target = command
+        .args
+        .first()
+        .map(|file_name| normalize_path(&root, file_name))
+        .filter(|target| own_file.as_ref() != Some(target));
+
+    if target.as_ref().is_some_and(|target| target.exists()) && !command.bang {
+        return Err("File exists (add ! to override)".to_string());
+    }
+    if target.is_none() && buffer.read_only && !command.bang {
+        return Err("'readonly' option is set (add ! to override)".to_string());
+    }
+    if target.is_none() && buffer.changed_on_disk() && !command.bang {
+        return Err(
+            "WARNING: The file has been changed since reading it (add ! to override)".to_string(),
         );
-        m
+    }
+
+    // Writing to another name only saves a copy unless the buffer had none.
+    let target = target.map(|target| target.to_string_lossy().to_string());
+    let message = match target {
+        Some(file_name) if buffer.file_name.is_some() => buffer
+            .write_to(&file_name)
+            .map(|_| format!("File saved: {}", file_name)),
+        target => {
+            let saved = match target {
+                Some(file_name) => buffer.save_as(file_name),
+                None => buffer.write_file(),
+            };
+            saved.map(|_| {
+                buffer.mark_saved();
+                buffer.read_only = false;
+                format!(
+                    "File saved: {}",
+                    buffer.file_name.as_deref().unwrap_or_default()
+                )
+            })
+        }
+    }
+    .map_err(|e| format!("Failed to save file: {}", e))?;
+
+    editor.commands_hist.push(message);
+    Ok(())
+}
+
+// `:[range]d [x] [count]` and `:[range]y [x] [count]`, where a count
+// extends the range from its last line.
+fn lines_operator_command(
+    editor: &mut Editor,
+    command: &ExCommand,
+    operator: Operator,
+) -> Result<(), String> {
+    let focus_buffer = editor.focus_buffer.clone();
+    let buffer = editor
+        .buffers
+        .get_mut(&focus_buffer)
+        .ok_or("No buffer to edit")?;
+
+    let mut args = command.args.iter().peekable();
+    let register = match args.peek().map(|arg| arg.chars().collect::<Vec<char>>()) {
+        Some(chars) if chars.len() == 1 && !chars[0].is_ascii_digit() => {
+            args.next();
+            Some(chars[0])
+        }
+        _ => None,
+    };
+
+    let count = match args.next() {
+        Some(arg) => Some(
+            arg.parse::<usize>()
+                .ok()
+                .filter(|count| *count > 0)
+                .ok_or_else(|| format!("Trailing characters: {}", arg))?,
+        ),
+        None => None,
     };
+
+    let (mut start, mut end) = command.lines(buffer)?;
+    if let Some(count) = count {
+        start = end;
+        end = (end + count - 1).min(buffer.content.len_lines() - 1);
+    }
+
+    apply_operator(
+        buffer,
+        &mut editor.registers,
+        register,
+        operator,
+        OperatorRange::Lines { start, end },
+    )?;
+    Ok(())
 }
 
-pub fn execute_assignated_command(editor: &mut Editor, command: &str) {
-    if let Some(command_fn) = EDITOR_COMMANDS.get(command) {
-        command_fn(editor);
+pub fn error_message(message: &str) -> String {
+    format!(
+        "{}{}{}",
+        color::Fg(color::Red),
+        message,
+        color::Fg(color::Reset)
+    )
+}
+
+pub fn push_error(editor: &mut Editor, message: &str) {
+    editor.commands_hist.push(error_message(message));
+}
+
+// For messages that don't come from a command: the line being typed stays
+// the entry being edited.
+pub fn push_message(editor: &mut Editor, message: String) {
+    if editor.editor_mode == EditorMode::Command {
+        let at = editor.commands_hist.len().saturating_sub(1);
+        editor.commands_hist.insert(at, message);
     } else {
-        editor.commands_hist.push(format!(
-            "{}Command \"{}\" not found.{}",
-            color::Fg(color::Red),
-            command,
-            color::Fg(color::Reset)
-        ));
+        editor.commands_hist.push(message);
+        editor.commands_hist.push(String::new());
+    }
+}
+
+// A bare range such as `:42` or `:$` jumps to the last line it addresses.
+fn goto_line(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    let focus_buffer = editor.focus_buffer.clone();
+    let Some(buffer) = editor.get_buffer_mut(&focus_buffer) else {
+        return Ok(());
+    };
+
+    let (_, line) = command.lines(buffer)?;
+    if let Some(cursor) = buffer.cursors.first_mut() {
+        cursor.position = (0, to_coord(line));
+    }
+    Ok(())
+}
+
+pub fn run_ex_command(editor: &mut Editor, input: &str) -> Result<(), String> {
+    let command = parse_ex_command(input)?;
+
+    if command.name.is_empty() {
+        return match command.range {
+            Some(_) => goto_line(editor, &command),
+            None => Ok(()),
+        };
+    }
+
+    if let Some(definition) = find_command(&command.name)? {
+        return (definition.function)(editor, &command);
+    }
+    run_plugin_command(editor, &command)
+        .unwrap_or_else(|| Err(format!("Not an editor command: {}", input)))
+}
+
+// For commands that weren't typed on the command line, like those of a
+// keymap or a plugin. Their messages show up the same way.
+pub fn run_command_from(editor: &mut Editor, command: &str) -> Result<(), String> {
+    // The command line and its prompts belong to the user until they are done.
+    if editor.editor_mode == EditorMode::Command {
+        return Err("The command line is in use".to_string());
+    }
+    let hist_len = editor.commands_hist.len();
+    let result = run_ex_command(editor, command);
+    if editor.commands_hist.len() > hist_len && editor.editor_mode != EditorMode::Command {
+        editor.commands_hist.push(String::new());
+    }
+    result
+}
+
+pub fn execute_assignated_command(editor: &mut Editor, command: &str) {
+    editor.registers.set_last_command(command);
+    editor.editor_mode = EditorMode::Normal;
+
+    if let Err(message) = run_ex_command(editor, command) {
+        push_error(editor, &message);
+    }
+
+    // Commands such as `:s///c` keep prompting in Command mode.
+    if editor.editor_mode != EditorMode::Command {
         editor.commands_hist.push(String::new());
-        editor.editor_mode = EditorMode::Normal;
     }
+    sync_focus_buffer_lines(editor);
 }
 
 pub fn match_keys_command(editor: &mut Editor, key: &str) {
+    match editor.command_prompt {
+        CommandPrompt::Substitute => return match_keys_substitute(editor, key),
+        CommandPrompt::Recover => return match_keys_recovery(editor, key),
+        _ => {}
+    }
+
+    if key != "<Tab>" && key != "<S-Tab>" {
+        editor.completion = None;
+    }
+
+    if let CommandPrompt::Search(direction) = editor.command_prompt {
+        if key == "<Up>" || key == "<Down>" {
+            browse_history(editor, key == "<Up>");
+            preview_search(editor, direction);
+            return;
+        }
+    }
+
     if let Some(last_entry) = editor.commands_hist.last_mut() {
         match key {
             "<Enter>" => {
                 let last_entry_clone = last_entry.clone();
-                execute_assignated_command(editor, &last_entry_clone);
+                match editor.command_prompt {
+                    CommandPrompt::Ex => execute_assignated_command(editor, &last_entry_clone),
+                    CommandPrompt::Search(direction) => {
+                        execute_search(editor, &last_entry_clone, direction)
+                    }
+                    CommandPrompt::Substitute | CommandPrompt::Recover => {}
+                }
+            }
+            "<Tab>" | "<S-Tab>" if editor.command_prompt == CommandPrompt::Ex => {
+                complete_command_line(editor, key == "<Tab>");
             }
             "<BS>" => {
                 last_entry.pop();
@@ -71,7 +511,7 @@ pub fn match_keys_command(editor: &mut Editor, key: &str) {
                 splitted.pop();
                 *last_entry = splitted.join(".");
             }
-            _ if key.len() == 3 => {
+            _ if key.chars().count() == 3 => {
                 let ch = key.chars().nth(1).unwrap();
                 let _ = &last_entry.push(ch);
             }
@@ -84,4 +524,210 @@ pub fn match_keys_command(editor: &mut Editor, key: &str) {
             }
         }
     }
+
+    match editor.command_prompt {
+        CommandPrompt::Search(_) if editor.editor_mode != EditorMode::Command => {
+            cancel_search(editor)
+        }
+        CommandPrompt::Search(direction) => preview_search(editor, direction),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::buffers::buffer_list::open_file_buffer;
+    use crate::layout::{sync_layout, Layout};
+    use crate::testing::{buffer_text, cursor, editor_with_text, temp_dir, test_editor, type_keys};
+
+    fn command(name: &'static str, abbreviation: &'static str) -> EditorCommand {
+        EditorCommand {
+            name,
+            abbreviation,
+            function: Box::new(|_: &mut Editor, _: &ExCommand| Ok(())),
+        }
+    }
+
+    // What the status line shows.
+    fn message(editor: &Editor) -> &str {
+        editor.commands_hist.iter().rev().nth(1).unwrap()
+    }
+
+    fn found(commands: &[EditorCommand], name: &str) -> Result<Option<&'static str>, String> {
+        lookup_command(commands, name).map(|command| command.map(|command| command.name))
+    }
+
+    #[test]
+    fn abbreviations_select_one_command() {
+        assert_eq!(found(&EDITOR_COMMANDS, "w"), Ok(Some("write")));
+        assert_eq!(found(&EDITOR_COMMANDS, "wri"), Ok(Some("write")));
+        assert_eq!(found(&EDITOR_COMMANDS, "writ"), Ok(Some("write")));
+        assert_eq!(found(&EDITOR_COMMANDS, "writes"), Ok(None));
+        assert_eq!(found(&EDITOR_COMMANDS, "wq"), Ok(Some("wq")));
+        assert_eq!(found(&EDITOR_COMMANDS, "colo"), Ok(Some("colorscheme")));
+        assert_eq!(found(&EDITOR_COMMANDS, "col"), Ok(None));
+        assert_eq!(found(&EDITOR_COMMANDS, "red"), Ok(Some("redo")));
+        assert_eq!(found(&EDITOR_COMMANDS, "res"), Ok(Some("resize")));
+        assert!(found(&EDITOR_COMMANDS, "re").is_err());
+    }
+
+    #[test]
+    fn exact_names_win_and_overlaps_are_ambiguous() {
+        let commands = [
+            command("split", "sp"),
+            command("spell", "spe"),
+            command("sp", "sp"),
+            command("delete", "d"),
+            command("delmarks", "d"),
+        ];
+        assert_eq!(found(&commands, "sp"), Ok(Some("sp")));
+        assert_eq!(found(&commands, "spl"), Ok(Some("split")));
+        assert_eq!(found(&commands, "spe"), Ok(Some("spell")));
+        assert_eq!(
+            found(&commands, "del"),
+            Err("Ambiguous command: del".to_string())
+        );
+        assert_eq!(found(&commands, "dele"), Ok(Some("delete")));
+
+        let (mut editor, _) = editor_with_text("ambiguous_command", "text");
+        type_keys(&mut editor, ":re<Enter>");
+        assert!(editor
+            .commands_hist
+            .iter()
+            .any(|entry| entry.contains("Ambiguous command: re")));
+    }
+
+    #[test]
+    fn tab_completes_command_names() {
+        let (mut editor, _) = editor_with_text("tab_completes_command_names", "text");
+        type_keys(&mut editor, ":bu<Tab>");
+        assert_eq!(editor.commands_hist.last().unwrap(), "buffer");
+        type_keys(&mut editor, "<Tab>");
+        assert_eq!(editor.commands_hist.last().unwrap(), "buffers");
+        type_keys(&mut editor, "<Tab>");
+        assert_eq!(editor.commands_hist.last().unwrap(), "buffer");
+        type_keys(&mut editor, "<S-Tab>");
+        assert_eq!(editor.commands_hist.last().unwrap(), "buffers");
+
+        // A range stays in front of the name, and one candidate is final.
+        type_keys(&mut editor, "<Esc>:1,2subs<Tab><Tab>");
+        assert_eq!(editor.commands_hist.last().unwrap(), "1,2substitute");
+        type_keys(&mut editor, "<Esc>:zz<Tab>");
+        assert_eq!(editor.commands_hist.last().unwrap(), "zz");
+    }
+
+    #[test]
+    fn tab_completes_paths() {
+        let dir = crate::testing::temp_dir("tab_completes_paths");
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("some.txt"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+        let base = format!("e {}/", dir.display());
+
+        let (mut editor, _) = editor_with_text("tab_completes_paths_editor", "text");
+        type_keys(&mut editor, &format!(":{}s<Tab>", base));
+        assert_eq!(
+            *editor.commands_hist.last().unwrap(),
+            format!("{}some.txt", base)
+        );
+        type_keys(&mut editor, "<Tab>");
+        assert_eq!(
+            *editor.commands_hist.last().unwrap(),
+            format!("{}sub/", base)
+        );
+        type_keys(&mut editor, "<BS><BS><BS><BS><Tab>");
+        assert_eq!(
+            *editor.commands_hist.last().unwrap(),
+            format!("{}some.txt", base)
+        );
+    }
+
+    #[test]
+    fn prompts_take_non_ascii_text() {
+        let (mut editor, _) = editor_with_text("prompts_take_non_ascii_text", "über café\nñ");
+        type_keys(&mut editor, "/é");
+        assert_eq!(editor.editor_mode, EditorMode::Command);
+        assert_eq!(editor.commands_hist.last().unwrap(), "é");
+        type_keys(&mut editor, "<BS>é<Enter>");
+        assert_eq!(cursor(&editor), (8, 0));
+
+        type_keys(&mut editor, ":%s/ü|ñ/u/g<Enter>");
+        assert_eq!(editor.editor_mode, EditorMode::Normal);
+        assert_eq!(buffer_text(&editor), "uber café\nu");
+    }
+
+    #[test]
+    fn write_refuses_files_changed_on_disk() {
+        let (mut editor, _) = editor_with_text("write_refuses_files_changed_on_disk", "one\n");
+        let path = editor
+            .get_buffer(&editor.focus_buffer)
+            .and_then(|buffer| buffer.file_name.clone())
+            .unwrap();
+        fs::write(&path, "changed elsewhere\n").unwrap();
+
+        type_keys(&mut editor, "itwo <Esc>:w<Enter>");
+        assert!(message(&editor).contains("changed since reading"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "changed elsewhere\n");
+
+        type_keys(&mut editor, ":w!<Enter>");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two one\n");
+        // Saving took the new stamp, a plain :w works again.
+        type_keys(&mut editor, "ithree <Esc>:w<Enter>");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two three one\n");
+    }
+
+    #[test]
+    fn write_to_another_name_needs_bang_to_overwrite() {
+        let (mut editor, _) = editor_with_text("write_to_another_name", "one\n");
+        let dir = PathBuf::from(&editor.root);
+        fs::write(dir.join("other.txt"), "keep\n").unwrap();
+
+        type_keys(&mut editor, ":w other.txt<Enter>");
+        assert!(message(&editor).contains("File exists"));
+        assert_eq!(fs::read_to_string(dir.join("other.txt")).unwrap(), "keep\n");
+
+        type_keys(&mut editor, ":w! other.txt<Enter>");
+        assert_eq!(fs::read_to_string(dir.join("other.txt")).unwrap(), "one\n");
+        // A copy, the buffer still edits its own file.
+        let file_name = editor
+            .get_buffer(&editor.focus_buffer)
+            .and_then(|buffer| buffer.file_name.clone())
+            .unwrap();
+        assert!(file_name.ends_with("file.txt"));
+
+        // Naming the buffer's own file is not a copy.
+        type_keys(&mut editor, "itwo <Esc>:w file.txt<Enter>");
+        assert_eq!(
+            fs::read_to_string(dir.join("file.txt")).unwrap(),
+            "two one\n"
+        );
+    }
+
+    #[test]
+    fn unnamed_buffers_take_the_name_once_written() {
+        let dir = temp_dir("unnamed_buffers_take_the_name");
+        fs::write(dir.join("taken.txt"), "keep\n").unwrap();
+        let (mut editor, _) = test_editor(dir.to_str().unwrap(), (80, 24));
+        let key = open_file_buffer(&mut editor, None);
+        editor.layout = Layout::new(&key);
+        editor.focus_buffer = key.clone();
+        sync_layout(&mut editor);
+        let file_name = |editor: &Editor| editor.get_buffer(&key).unwrap().file_name.clone();
+
+        type_keys(&mut editor, "ihello<Esc>:w taken.txt<Enter>");
+        assert!(message(&editor).contains("File exists"));
+        assert_eq!(file_name(&editor), None);
+
+        type_keys(&mut editor, ":w missing/new.txt<Enter>");
+        assert!(message(&editor).contains("Failed to save"));
+        assert_eq!(file_name(&editor), None);
+
+        type_keys(&mut editor, ":w new.txt<Enter>");
+        assert_eq!(fs::read_to_string(dir.join("new.txt")).unwrap(), "hello\n");
+        let new_file = dir.join("new.txt").to_string_lossy().to_string();
+        assert_eq!(file_name(&editor), Some(new_file));
+    }
 }
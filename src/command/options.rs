@@ -0,0 +1,56 @@
+use crate::{command::parser::ExCommand, editor::Editor, theme::Theme};
+
+// `:set` lists the options, `:set name?` shows one, and `:set name=value`,
+// `:set name` or `:set noname` change them.
+pub fn set_command(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    if command.args.is_empty() {
+        let options = editor.config.editor.describe();
+        editor.commands_hist.push(options);
+        return Ok(());
+    }
+
+    let mut shown = vec![];
+    for arg in command.args.iter() {
+        if let Some(name) = arg.strip_suffix('?') {
+            let value = editor
+                .config
+                .editor
+                .get(name)
+                .ok_or(format!("Unknown option: {}", name))?;
+            shown.push(value);
+            continue;
+        }
+
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        editor.config.editor.set(name, value)?;
+
+        // Like any buffer opened from now on, the focused one follows.
+        let options = editor.config.editor.clone();
+        let focus_buffer = editor.focus_buffer.clone();
+        if let Some(buffer) = editor.get_buffer_mut(&focus_buffer) {
+            buffer.tab_width = options.tab_width;
+            buffer.expand_tab = options.expand_tab;
+        }
+    }
+
+    if !shown.is_empty() {
+        editor.commands_hist.push(shown.join("  "));
+    }
+    Ok(())
+}
+
+// `:colorscheme` shows the one in use, `:colorscheme name` switches to it. The
+// file is read again, so edits to it show up too.
+pub fn colorscheme_command(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    let Some(name) = command.args.first() else {
+        let name = editor.theme.name.clone();
+        editor.commands_hist.push(name);
+        return Ok(());
+    };
+    editor.theme = Theme::load(name, &editor.config.theme.groups)?;
+    editor.config.theme.colorscheme = name.clone();
+    Ok(())
+}
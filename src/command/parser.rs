@@ -0,0 +1,275 @@
+use crate::editor::EditorBuffer;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AddressBase {
+    Current,
+    Last,
+    Line(usize),
+    Mark(char),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LineAddress {
+    pub base: AddressBase,
+    pub offset: isize,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LineRange {
+    pub start: LineAddress,
+    pub end: LineAddress,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExCommand {
+    pub range: Option<LineRange>,
+    pub name: String,
+    pub bang: bool,
+    pub args: Vec<String>,
+    pub raw_args: String,
+}
+
+impl LineAddress {
+    fn resolve(&self, buffer: &EditorBuffer) -> Result<usize, String> {
+        let last_line = buffer.content.len_lines().saturating_sub(1);
+        let line = match self.base {
+            AddressBase::Current => buffer
+                .cursors
+                .first()
+                .map(|cursor| cursor.position.1 as usize)
+                .unwrap_or(0),
+            AddressBase::Last => last_line,
+            AddressBase::Line(line) => line.saturating_sub(1),
+            AddressBase::Mark('<') => buffer.visual_marks.ok_or("Mark not set")?.0,
+            AddressBase::Mark('>') => buffer.visual_marks.ok_or("Mark not set")?.1,
+            AddressBase::Mark(mark) => return Err(format!("Unknown mark: '{}", mark)),
+        };
+
+        let line = line as isize + self.offset;
+        if line < 0 || line as usize > last_line {
+            return Err("Invalid range".to_string());
+        }
+        Ok(line as usize)
+    }
+}
+
+impl ExCommand {
+    // Zero-based inclusive lines the command applies to; without a range
+    // that is the cursor line.
+    pub fn lines(&self, buffer: &EditorBuffer) -> Result<(usize, usize), String> {
+        let current = LineAddress {
+            base: AddressBase::Current,
+            offset: 0,
+        };
+        let range = self.range.unwrap_or(LineRange {
+            start: current,
+            end: current,
+        });
+
+        let start = range.start.resolve(buffer)?;
+        let end = range.end.resolve(buffer)?;
+        if start > end {
+            return Err("Backwards range given".to_string());
+        }
+        Ok((start, end))
+    }
+}
+
+fn parse_number(chars: &[char], idx: &mut usize) -> Option<usize> {
+    let start = *idx;
+    while *idx < chars.len() && chars[*idx].is_ascii_digit() {
+        *idx += 1;
+    }
+    chars[start..*idx].iter().collect::<String>().parse().ok()
+}
+
+fn parse_address(chars: &[char], idx: &mut usize) -> Result<Option<LineAddress>, String> {
+    let base = match chars.get(*idx) {
+        Some('.') => {
+            *idx += 1;
+            Some(AddressBase::Current)
+        }
+        Some('$') => {
+            *idx += 1;
+            Some(AddressBase::Last)
+        }
+        Some('\'') => {
+            let mark = *chars.get(*idx + 1).ok_or("Missing mark name")?;
+            *idx += 2;
+            Some(AddressBase::Mark(mark))
+        }
+        Some(ch) if ch.is_ascii_digit() => parse_number(chars, idx).map(AddressBase::Line),
+        _ => None,
+    };
+
+    let mut offset = 0;
+    while let Some(sign @ ('+' | '-')) = chars.get(*idx) {
+        let sign = if *sign == '+' { 1 } else { -1 };
+        *idx += 1;
+        offset += sign * parse_number(chars, idx).unwrap_or(1) as isize;
+    }
+
+    Ok(match (base, offset) {
+        (None, 0) => None,
+        (base, offset) => Some(LineAddress {
+            base: base.unwrap_or(AddressBase::Current),
+            offset,
+        }),
+    })
+}
+
+fn parse_range(chars: &[char], idx: &mut usize) -> Result<Option<LineRange>, String> {
+    if chars.get(*idx) == Some(&'%') {
+        *idx += 1;
+        return Ok(Some(LineRange {
+            start: LineAddress {
+                base: AddressBase::Line(1),
+                offset: 0,
+            },
+            end: LineAddress {
+                base: AddressBase::Last,
+                offset: 0,
+            },
+        }));
+    }
+
+    let Some(start) = parse_address(chars, idx)? else {
+        return Ok(None);
+    };
+
+    let end = if chars.get(*idx) == Some(&',') {
+        *idx += 1;
+        parse_address(chars, idx)?.ok_or("Invalid range")?
+    } else {
+        start
+    };
+
+    Ok(Some(LineRange { start, end }))
+}
+
+// Splits `[range]name[!][args]`, e.g. `'<,'>s/foo/bar/g` or `w! file.txt`.
+pub fn parse_ex_command(input: &str) -> Result<ExCommand, String> {
+    let chars: Vec<char> = input.trim_start().chars().collect();
+    let mut idx = 0;
+
+    let range = parse_range(&chars, &mut idx)?;
+
+    let name_start = idx;
+    while idx < chars.len() && chars[idx].is_ascii_alphabetic() {
+        idx += 1;
+    }
+    let name: String = chars[name_start..idx].iter().collect();
+
+    let bang = !name.is_empty() && chars.get(idx) == Some(&'!');
+    if bang {
+        idx += 1;
+    }
+
+    let raw_args: String = chars[idx..].iter().collect();
+    let raw_args = raw_args.trim_start().to_string();
+
+    Ok(ExCommand {
+        range,
+        name,
+        bang,
+        args: raw_args.split_whitespace().map(String::from).collect(),
+        raw_args,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::editor_with_text;
+
+    fn address(base: AddressBase, offset: isize) -> LineAddress {
+        LineAddress { base, offset }
+    }
+
+    fn range(input: &str) -> Option<LineRange> {
+        parse_ex_command(input).unwrap().range
+    }
+
+    #[test]
+    fn parses_ranges() {
+        use AddressBase::*;
+        assert_eq!(range("d"), None);
+        assert_eq!(
+            range(".,$d"),
+            Some(LineRange {
+                start: address(Current, 0),
+                end: address(Last, 0),
+            })
+        );
+        assert_eq!(
+            range("'<,'>s/a/b/"),
+            Some(LineRange {
+                start: address(Mark('<'), 0),
+                end: address(Mark('>'), 0),
+            })
+        );
+        assert_eq!(
+            range("3,15y"),
+            Some(LineRange {
+                start: address(Line(3), 0),
+                end: address(Line(15), 0),
+            })
+        );
+        assert_eq!(
+            range("%s/a/b/"),
+            Some(LineRange {
+                start: address(Line(1), 0),
+                end: address(Last, 0),
+            })
+        );
+        assert_eq!(
+            range(".+2,$-1d"),
+            Some(LineRange {
+                start: address(Current, 2),
+                end: address(Last, -1),
+            })
+        );
+        assert_eq!(range("-d"), range(".-1d"));
+        assert!(parse_ex_command("3,d").is_err());
+    }
+
+    #[test]
+    fn parses_name_bang_and_args() {
+        let command = parse_ex_command("'<,'>s/a b/c/g").unwrap();
+        assert_eq!(command.name, "s");
+        assert_eq!(command.raw_args, "/a b/c/g");
+
+        let command = parse_ex_command("w! some file.txt").unwrap();
+        assert_eq!((command.name.as_str(), command.bang), ("w", true));
+        assert_eq!(command.args, ["some", "file.txt"]);
+
+        let command = parse_ex_command("42").unwrap();
+        assert_eq!(command.name, "");
+        assert!(!command.bang);
+    }
+
+    #[test]
+    fn resolves_ranges_against_the_buffer() {
+        let text = "1\n2\n3\n4\n5\n6";
+        let (mut editor, _) = editor_with_text("resolves_ranges_against_the_buffer", text);
+        let key = editor.focus_buffer.clone();
+        let buffer = editor.get_buffer_mut(&key).unwrap();
+        buffer.cursors[0].position = (0, 2);
+        let lines = |input: &str, buffer: &EditorBuffer| parse_ex_command(input)?.lines(buffer);
+
+        assert_eq!(lines("d", buffer), Ok((2, 2)));
+        assert_eq!(lines(".,$d", buffer), Ok((2, 5)));
+        assert_eq!(lines("2,4d", buffer), Ok((1, 3)));
+        assert_eq!(lines("%d", buffer), Ok((0, 5)));
+        assert_eq!(lines(".-1,.+1d", buffer), Ok((1, 3)));
+        assert_eq!(lines("'<,'>d", buffer), Err("Mark not set".to_string()));
+        assert_eq!(
+            lines("4,2d", buffer),
+            Err("Backwards range given".to_string())
+        );
+        assert_eq!(lines("7d", buffer), Err("Invalid range".to_string()));
+
+        buffer.visual_marks = Some((3, 4));
+        assert_eq!(lines("'<,'>d", buffer), Ok((3, 4)));
+    }
+}
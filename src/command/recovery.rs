@@ -0,0 +1,157 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{
+    buffers::sync_focus_buffer_lines,
+    command::{error_message, CommandPrompt},
+    editor::Editor,
+    editor_modes::EditorMode,
+    files::swap::{swap_path, SwapFile},
+    text::TextRope,
+    undo::EditOperation,
+};
+
+// A swap file found when opening a buffer, waiting for the user to decide.
+pub struct PendingRecovery {
+    key: String,
+    path: PathBuf,
+    swap: SwapFile,
+}
+
+impl PendingRecovery {
+    fn prompt(&self) -> String {
+        // Edits made to an older version of the file would land in the wrong places.
+        let recover = if self.swap.is_file_newer() {
+            ""
+        } else {
+            "[r]ecover, "
+        };
+        format!(
+            "Swap file found for \"{}\" ({}): {}[o]pen read-only, [d]elete it?",
+            self.swap.file_name,
+            self.swap.describe(),
+            recover
+        )
+    }
+}
+
+// Queues a prompt if the file of buffer `key` has a swap left by another session.
+pub fn check_swap_file(editor: &mut Editor, key: &str) {
+    let Some(path) = editor
+        .get_buffer(key)
+        .and_then(|buffer| buffer.file_name.as_deref())
+        .and_then(swap_path)
+    else {
+        return;
+    };
+    let Ok(swap) = SwapFile::read(&path) else {
+        return;
+    };
+
+    editor.pending_recoveries.push(PendingRecovery {
+        key: key.to_string(),
+        path,
+        swap,
+    });
+    if !recovery_prompt_open(editor) {
+        show_next_prompt(editor);
+    }
+}
+
+// Keys go to the prompt until every queued swap file is answered.
+pub fn recovery_prompt_open(editor: &Editor) -> bool {
+    editor.editor_mode == EditorMode::Command && editor.command_prompt == CommandPrompt::Recover
+}
+
+fn show_next_prompt(editor: &mut Editor) {
+    if let Some(recovery) = editor.pending_recoveries.first() {
+        let prompt = recovery.prompt();
+        editor.commands_hist.push(prompt);
+        editor.command_prompt = CommandPrompt::Recover;
+        editor.editor_mode = EditorMode::Command;
+    }
+}
+
+fn recover(editor: &mut Editor, recovery: &PendingRecovery) -> String {
+    let Some(buffer) = editor.get_buffer_mut(&recovery.key) else {
+        return "Buffer to recover is gone".to_string();
+    };
+
+    if let Some(snapshot) = &recovery.swap.snapshot {
+        let last_line = buffer.content.len_lines().saturating_sub(1);
+        let end = (last_line, buffer.line_len(last_line));
+        buffer.delete_text((0, 0), end);
+        buffer.insert_text((0, 0), snapshot);
+    }
+    for operation in &recovery.swap.journal {
+        match operation {
+            EditOperation::Insert { position, text } => {
+                buffer.insert_text(*position, text);
+            }
+            EditOperation::Delete { position, text } => {
+                let end = TextRope::end_of_text(*position, text);
+                buffer.delete_text(*position, end);
+            }
+        }
+    }
+    buffer.commit_undo();
+    // Our own swap now holds the recovered edits and replaces the old one.
+    buffer.sync_swap(true);
+
+    format!(
+        "Recovered {} change{}, write the buffer to keep them",
+        recovery.swap.journal.len(),
+        if recovery.swap.journal.len() == 1 {
+            ""
+        } else {
+            "s"
+        }
+    )
+}
+
+pub fn match_keys_recovery(editor: &mut Editor, key: &str) {
+    if editor.pending_recoveries.is_empty() {
+        editor.command_prompt = CommandPrompt::Ex;
+        editor.editor_mode = EditorMode::Normal;
+        return;
+    }
+
+    let message = match key {
+        "<r>" if editor.pending_recoveries[0].swap.is_file_newer() => {
+            editor.commands_hist.push(error_message(
+                "The file changed after the swap file was written, cannot recover",
+            ));
+            show_next_prompt(editor);
+            return;
+        }
+        "<r>" => {
+            let recovery = editor.pending_recoveries.remove(0);
+            recover(editor, &recovery)
+        }
+        "<o>" | "<Esc>" | "<C-c>" => {
+            let recovery = editor.pending_recoveries.remove(0);
+            if let Some(buffer) = editor.get_buffer_mut(&recovery.key) {
+                buffer.read_only = true;
+            }
+            format!("\"{}\" opened read-only", recovery.swap.file_name)
+        }
+        "<d>" => {
+            let recovery = editor.pending_recoveries.remove(0);
+            match fs::remove_file(&recovery.path) {
+                Ok(()) => "Swap file deleted".to_string(),
+                Err(e) => format!("Failed to delete swap file: {}", e),
+            }
+        }
+        _ => return,
+    };
+
+    editor.commands_hist.push(message);
+    if editor.pending_recoveries.is_empty() {
+        editor.commands_hist.push(String::new());
+        editor.command_prompt = CommandPrompt::Ex;
+        editor.editor_mode = EditorMode::Normal;
+    } else {
+        show_next_prompt(editor);
+    }
+    sync_focus_buffer_lines(editor);
+}
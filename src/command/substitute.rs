@@ -0,0 +1,468 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    buffers::sync_focus_buffer_lines,
+    command::{parser::ExCommand, CommandPrompt},
+    editor::{Editor, EditorBuffer},
+    editor_modes::EditorMode,
+    search::smartcase,
+    text::to_cursor,
+    undo::TextPosition,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Substitution {
+    pub pattern: String,
+    pub replacement: String,
+    pub global: bool,
+    pub confirm: bool,
+    pub ignore_case: Option<bool>,
+}
+
+struct SubstituteMatch {
+    line: usize,
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+// State of a `:s` run; kept on the editor while a `c` flag waits for answers.
+pub struct PendingSubstitution {
+    pub regex: Regex,
+    replacement: String,
+    end_line: usize,
+    position: TextPosition,
+    global: bool,
+    current: Option<SubstituteMatch>,
+    substitutions: usize,
+    lines: usize,
+    last_line: Option<usize>,
+}
+
+// Splits `/pattern/replacement/flags`, where any punctuation character can be
+// used as the delimiter and `\<delimiter>` escapes it.
+pub fn parse_substitution(args: &str) -> Result<Substitution, String> {
+    let mut chars = args.chars();
+    let delimiter = match chars.next() {
+        Some(ch) if !ch.is_alphanumeric() && !ch.is_whitespace() && ch != '\\' => ch,
+        Some(_) => return Err("Regular expression can't be delimited by letters".to_string()),
+        None => return Err("Missing pattern".to_string()),
+    };
+
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for ch in chars {
+        if ch == delimiter && !escaped && parts.len() < 3 {
+            parts.push(String::new());
+            continue;
+        }
+
+        let part = parts.last_mut().unwrap();
+        if escaped {
+            if ch != delimiter {
+                part.push('\\');
+            }
+            part.push(ch);
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else {
+            part.push(ch);
+        }
+    }
+    if escaped {
+        parts.last_mut().unwrap().push('\\');
+    }
+
+    let mut parts = parts.into_iter();
+    let pattern = parts.next().unwrap_or_default();
+    let replacement = parts.next().unwrap_or_default();
+    let flags = parts.next().unwrap_or_default();
+
+    let mut substitution = Substitution {
+        pattern,
+        replacement,
+        global: false,
+        confirm: false,
+        ignore_case: None,
+    };
+    for flag in flags.trim().chars() {
+        match flag {
+            'g' => substitution.global = true,
+            'c' => substitution.confirm = true,
+            'i' => substitution.ignore_case = Some(true),
+            'I' => substitution.ignore_case = Some(false),
+            _ => return Err(format!("Trailing characters: {}", flag)),
+        }
+    }
+
+    Ok(substitution)
+}
+
+// Translates vim replacement syntax (`&`, `\1`, `\n`) into the one `regex`
+// expands (`${0}`, `${1}`, a literal newline).
+fn expand_replacement(replacement: &str) -> String {
+    let mut expanded = String::new();
+    let mut chars = replacement.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(digit) if digit.is_ascii_digit() => {
+                    expanded.push_str(&format!("${{{}}}", digit))
+                }
+                Some('n') | Some('r') => expanded.push('\n'),
+                Some('t') => expanded.push('\t'),
+                Some('$') => expanded.push_str("$$"),
+                Some(other) => expanded.push(other),
+                None => expanded.push('\\'),
+            },
+            '&' => expanded.push_str("${0}"),
+            '$' => expanded.push_str("$$"),
+            _ => expanded.push(ch),
+        }
+    }
+
+    expanded
+}
+
+fn char_to_byte(text: &str, col: usize) -> usize {
+    text.char_indices()
+        .nth(col)
+        .map(|(byte, _)| byte)
+        .unwrap_or(text.len())
+}
+
+impl PendingSubstitution {
+    fn new(
+        substitution: &Substitution,
+        lines: (usize, usize),
+        smart_case: bool,
+    ) -> Result<PendingSubstitution, String> {
+        let ignore_case = substitution
+            .ignore_case
+            .unwrap_or_else(|| smartcase(&substitution.pattern, smart_case));
+        let regex = RegexBuilder::new(&substitution.pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| format!("Invalid pattern: {}", e))?;
+
+        Ok(PendingSubstitution {
+            regex,
+            replacement: expand_replacement(&substitution.replacement),
+            end_line: lines.1,
+            position: (lines.0, 0),
+            global: substitution.global,
+            current: None,
+            substitutions: 0,
+            lines: 0,
+            last_line: None,
+        })
+    }
+
+    fn find_next(&mut self, buffer: &EditorBuffer) {
+        self.current = None;
+
+        let last_line = self
+            .end_line
+            .min(buffer.content.len_lines().saturating_sub(1));
+        for line in self.position.0..=last_line {
+            let text = buffer.content.line_string(line);
+            let col = if line == self.position.0 {
+                self.position.1
+            } else {
+                0
+            };
+            if col > text.chars().count() {
+                continue;
+            }
+
+            if let Some(captures) = self.regex.captures_at(&text, char_to_byte(&text, col)) {
+                let found = captures.get(0).unwrap();
+                let mut replaced = String::new();
+                captures.expand(&self.replacement, &mut replaced);
+
+                let start = text[..found.start()].chars().count();
+                self.current = Some(SubstituteMatch {
+                    line,
+                    start,
+                    end: start + found.as_str().chars().count(),
+                    text: replaced,
+                });
+                return;
+            }
+        }
+    }
+
+    fn advance(&mut self, line: usize, col: usize, empty_match: bool) {
+        self.position = if self.global {
+            (line, if empty_match { col + 1 } else { col })
+        } else {
+            (line + 1, 0)
+        };
+    }
+
+    fn skip(&mut self, buffer: &EditorBuffer) {
+        if let Some(found) = self.current.take() {
+            self.advance(found.line, found.end, found.start == found.end);
+        }
+        self.find_next(buffer);
+    }
+
+    fn replace(&mut self, buffer: &mut EditorBuffer) {
+        let Some(found) = self.current.take() else {
+            return;
+        };
+
+        buffer.delete_text((found.line, found.start), (found.line, found.end));
+        let end = if found.text.is_empty() {
+            (found.line, found.start)
+        } else {
+            buffer.insert_text((found.line, found.start), &found.text)
+        };
+
+        self.end_line += end.0 - found.line;
+        self.substitutions += 1;
+        if self.last_line != Some(found.line) {
+            self.lines += 1;
+        }
+        self.last_line = Some(end.0);
+
+        self.advance(end.0, end.1, found.start == found.end);
+        self.find_next(buffer);
+    }
+
+    fn replace_all(&mut self, buffer: &mut EditorBuffer) {
+        while self.current.is_some() {
+            self.replace(buffer);
+        }
+    }
+
+    fn move_cursor(&self, buffer: &mut EditorBuffer) {
+        let position = match (&self.current, self.last_line) {
+            (Some(found), _) => (found.start, found.line),
+            (None, Some(line)) => (0, line),
+            (None, None) => return,
+        };
+        if let Some(cursor) = buffer.cursors.first_mut() {
+            cursor.position = to_cursor((position.1, position.0));
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{} substitution{} on {} line{}",
+            self.substitutions,
+            if self.substitutions == 1 { "" } else { "s" },
+            self.lines,
+            if self.lines == 1 { "" } else { "s" },
+        )
+    }
+}
+
+fn finish_substitution(editor: &mut Editor, pending: PendingSubstitution) {
+    let focus_buffer = editor.focus_buffer.clone();
+    if let Some(buffer) = editor.get_buffer_mut(&focus_buffer) {
+        pending.move_cursor(buffer);
+        buffer.commit_undo();
+    }
+
+    editor.commands_hist.push(pending.summary());
+    editor.command_prompt = CommandPrompt::Ex;
+}
+
+pub fn execute_substitute(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    let focus_buffer = editor.focus_buffer.clone();
+    let buffer = editor
+        .buffers
+        .get_mut(&focus_buffer)
+        .ok_or("No buffer to substitute in")?;
+
+    let mut substitution = parse_substitution(&command.raw_args)?;
+    if substitution.pattern.is_empty() {
+        substitution.pattern = editor
+            .search
+            .last_pattern
+            .clone()
+            .ok_or("No previous regular expression")?;
+    }
+
+    let lines = command.lines(buffer)?;
+    let smart_case = editor.config.editor.smart_case;
+    let mut pending = PendingSubstitution::new(&substitution, lines, smart_case)?;
+    pending.find_next(buffer);
+    if pending.current.is_none() {
+        return Err(format!("Pattern not found: {}", substitution.pattern));
+    }
+
+    if !substitution.confirm {
+        pending.replace_all(buffer);
+    }
+    pending.move_cursor(buffer);
+
+    editor
+        .search
+        .remember(&substitution.pattern, editor.search.last_direction);
+    editor.registers.set_last_search(&substitution.pattern);
+
+    if substitution.confirm {
+        editor.commands_hist.push(format!(
+            "replace with {} (y/n/a/q/l)?",
+            substitution.replacement
+        ));
+        editor.command_prompt = CommandPrompt::Substitute;
+        editor.editor_mode = EditorMode::Command;
+        editor.pending_substitution = Some(pending);
+    } else {
+        finish_substitution(editor, pending);
+    }
+
+    Ok(())
+}
+
+pub fn match_keys_substitute(editor: &mut Editor, key: &str) {
+    let Some(mut pending) = editor.pending_substitution.take() else {
+        editor.editor_mode = EditorMode::Normal;
+        return;
+    };
+    let focus_buffer = editor.focus_buffer.clone();
+    let Some(buffer) = editor.buffers.get_mut(&focus_buffer) else {
+        return;
+    };
+
+    let done = match key {
+        "<y>" => {
+            pending.replace(buffer);
+            false
+        }
+        "<l>" => {
+            pending.replace(buffer);
+            true
+        }
+        "<n>" => {
+            pending.skip(buffer);
+            false
+        }
+        "<a>" => {
+            pending.replace_all(buffer);
+            true
+        }
+        "<q>" | "<Esc>" | "<C-c>" => true,
+        _ => false,
+    };
+
+    if done || pending.current.is_none() {
+        pending.current = None;
+        finish_substitution(editor, pending);
+        editor.commands_hist.push(String::new());
+        editor.editor_mode = EditorMode::Normal;
+        sync_focus_buffer_lines(editor);
+    } else {
+        pending.move_cursor(buffer);
+        editor.pending_substitution = Some(pending);
+        sync_focus_buffer_lines(editor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{buffer_text, cursor, editor_with_text, type_keys};
+
+    fn substitution(pattern: &str, replacement: &str) -> Substitution {
+        Substitution {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            global: false,
+            confirm: false,
+            ignore_case: None,
+        }
+    }
+
+    #[test]
+    fn parses_patterns_and_flags() {
+        assert_eq!(parse_substitution("/a/b/"), Ok(substitution("a", "b")));
+        assert_eq!(parse_substitution("/a"), Ok(substitution("a", "")));
+        assert_eq!(parse_substitution("#a\\#/b#"), Ok(substitution("a#/b", "")));
+        assert_eq!(
+            parse_substitution("/a\\/b/\\1/gci"),
+            Ok(Substitution {
+                global: true,
+                confirm: true,
+                ignore_case: Some(true),
+                ..substitution("a/b", "\\1")
+            })
+        );
+        assert_eq!(
+            parse_substitution("/a/b/I").map(|s| s.ignore_case),
+            Ok(Some(false))
+        );
+        assert!(parse_substitution("/a/b/x").is_err());
+        assert!(parse_substitution("xaxbx").is_err());
+    }
+
+    #[test]
+    fn global_flag_replaces_every_match() {
+        let text = "foo foo\nfoo";
+        let (mut editor, _) = editor_with_text("global_flag_replaces_every_match", text);
+        type_keys(&mut editor, ":s/foo/bar/<Enter>");
+        assert_eq!(buffer_text(&editor), "bar foo\nfoo");
+        type_keys(&mut editor, ":%s/foo/baz/g<Enter>");
+        assert_eq!(buffer_text(&editor), "bar baz\nbaz");
+        assert_eq!(
+            editor.commands_hist.iter().rev().nth(1).unwrap(),
+            "2 substitutions on 2 lines"
+        );
+        assert_eq!(cursor(&editor), (0, 1));
+
+        type_keys(&mut editor, "u");
+        assert_eq!(buffer_text(&editor), "bar foo\nfoo");
+    }
+
+    #[test]
+    fn ignore_case_flags() {
+        let (mut editor, _) = editor_with_text("substitute_ignore_case_flags", "Foo foo FOO");
+        type_keys(&mut editor, ":s/FOO/x/gI<Enter>");
+        assert_eq!(buffer_text(&editor), "Foo foo x");
+        type_keys(&mut editor, ":s/FOO/y/gi<Enter>");
+        assert_eq!(buffer_text(&editor), "y y x");
+    }
+
+    #[test]
+    fn replacements_use_capture_groups() {
+        let text = "alice@example\nbob@test";
+        let (mut editor, _) = editor_with_text("replacements_use_capture_groups", text);
+        type_keys(&mut editor, ":%s/(\\w+)@(\\w+)/\\2: \\1 [&]/<Enter>");
+        assert_eq!(
+            buffer_text(&editor),
+            "example: alice [alice@example]\ntest: bob [bob@test]"
+        );
+        // Like vim, `$1` is taken literally.
+        type_keys(&mut editor, ":s/(\\w+):/$1/<Enter>");
+        assert_eq!(
+            buffer_text(&editor),
+            "example: alice [alice@example]\n$1 bob [bob@test]"
+        );
+    }
+
+    #[test]
+    fn confirm_flag_asks_for_each_match() {
+        let (mut editor, _) = editor_with_text("confirm_flag_asks_for_each_match", "a a a\na a");
+        type_keys(&mut editor, ":%s/a/b/gc<Enter>");
+        assert_eq!(editor.editor_mode, EditorMode::Command);
+        assert_eq!(editor.command_prompt, CommandPrompt::Substitute);
+        type_keys(&mut editor, "yn");
+        assert_eq!(buffer_text(&editor), "b a a\na a");
+        assert_eq!(cursor(&editor), (4, 0));
+        type_keys(&mut editor, "l");
+        assert_eq!(editor.editor_mode, EditorMode::Normal);
+        assert_eq!(buffer_text(&editor), "b a b\na a");
+
+        type_keys(&mut editor, ":%s/a/c/gc<Enter>nq");
+        assert_eq!(buffer_text(&editor), "b a b\na a");
+        type_keys(&mut editor, ":%s/a/c/gc<Enter>na");
+        assert_eq!(buffer_text(&editor), "b a b\nc c");
+        type_keys(&mut editor, "u");
+        assert_eq!(buffer_text(&editor), "b a b\na a");
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{
+    buffers::buffer_list::open_file_buffer,
+    command::parser::ExCommand,
+    editor::Editor,
+    layout::{close_pane, only_pane, resize_pane, show_buffer, split_pane, SplitAxis},
+};
+
+pub fn split_command(
+    editor: &mut Editor,
+    command: &ExCommand,
+    axis: SplitAxis,
+) -> Result<(), String> {
+    split_pane(editor, axis)?;
+    if let Some(file_path) = command.args.first() {
+        let key = open_file_buffer(editor, Some(file_path));
+        show_buffer(editor, &key);
+    }
+    Ok(())
+}
+
+pub fn close_command(editor: &mut Editor, _command: &ExCommand) -> Result<(), String> {
+    let focus = editor.layout.focus;
+    close_pane(editor, focus)
+}
+
+pub fn only_command(editor: &mut Editor, _command: &ExCommand) -> Result<(), String> {
+    only_pane(editor);
+    Ok(())
+}
+
+// `:resize +N` and `:resize -N` change the height of the focused pane by N
+// rows, `:resize N` sets it.
+pub fn resize_command(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    let arg = command.args.first().ok_or("Argument required")?;
+    let amount: i32 = arg
+        .trim_start_matches('+')
+        .parse()
+        .map_err(|_| format!("Invalid argument: {}", arg))?;
+
+    let delta = if arg.starts_with('+') || arg.starts_with('-') {
+        amount
+    } else {
+        let height = editor
+            .layout
+            .pane_window(editor.layout.focus, editor.terminal_size)
+            .map(|window| window.end.1 as i32 - window.start.1 as i32)
+            .unwrap_or(amount);
+        amount - height
+    };
+
+    resize_pane(editor, SplitAxis::Horizontal, delta);
+    Ok(())
+}
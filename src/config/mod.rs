@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use toml::Table;
+
+use crate::{
+    editor_modes::EditorMode,
+    is_single_key,
+    server::hosts::PluginHostConfig,
+    theme::{Highlight, DEFAULT_COLORSCHEME},
+};
+
+pub const CONFIG_FILE: &str = "config.toml";
+// Read from the directory the editor starts in, over the user config.
+pub const PROJECT_CONFIG_FILE: &str = ".rustpen.toml";
+
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rustpen"))
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub editor: EditorOptions,
+    pub theme: ThemeConfig,
+    pub keymaps: Keymaps,
+    pub plugins: Vec<PluginHostConfig>,
+}
+
+// Everything `:set` can change while the editor runs.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EditorOptions {
+    pub tab_width: u16,
+    // <Tab> in Insert mode inserts spaces instead of a tab.
+    pub expand_tab: bool,
+    // A search without uppercase letters ignores case.
+    pub smart_case: bool,
+    pub swap_file: bool,
+}
+
+impl Default for EditorOptions {
+    fn default() -> Self {
+        EditorOptions {
+            tab_width: 4,
+            expand_tab: true,
+            smart_case: true,
+            swap_file: true,
+        }
+    }
+}
+
+const OPTION_NAMES: [&str; 4] = ["tab_width", "expand_tab", "smart_case", "swap_file"];
+
+impl EditorOptions {
+    fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "expand_tab" => Some(&mut self.expand_tab),
+            "smart_case" => Some(&mut self.smart_case),
+            "swap_file" => Some(&mut self.swap_file),
+            _ => None,
+        }
+    }
+
+    // Flags show as `name` or `noname`, like `:set` takes them.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let flag = |name: &str, value: bool| {
+            if value {
+                name.to_string()
+            } else {
+                format!("no{}", name)
+            }
+        };
+        match name {
+            "tab_width" => Some(format!("tab_width={}", self.tab_width)),
+            "expand_tab" => Some(flag(name, self.expand_tab)),
+            "smart_case" => Some(flag(name, self.smart_case)),
+            "swap_file" => Some(flag(name, self.swap_file)),
+            _ => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        OPTION_NAMES
+            .iter()
+            .filter_map(|name| self.get(name))
+            .collect::<Vec<String>>()
+            .join("  ")
+    }
+
+    // `name=value`, `name` to turn a flag on, `noname` to turn it off.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        if let Some(value) = value {
+            if name == "tab_width" {
+                self.tab_width = match value.parse::<u16>() {
+                    Ok(width) if width > 0 => width,
+                    _ => return Err(format!("Invalid value for tab_width: {}", value)),
+                };
+                return Ok(());
+            }
+            let flag = self.flag(name).ok_or(format!("Unknown option: {}", name))?;
+            *flag = match value {
+                "true" => true,
+                "false" => false,
+                _ => return Err(format!("Invalid value for {}: {}", name, value)),
+            };
+            return Ok(());
+        }
+
+        if let Some(flag) = self.flag(name) {
+            *flag = true;
+            return Ok(());
+        }
+        match name.strip_prefix("no").and_then(|name| self.flag(name)) {
+            Some(flag) => {
+                *flag = false;
+                Ok(())
+            }
+            None if name == "tab_width" => Err("tab_width needs a value".to_string()),
+            None => Err(format!("Unknown option: {}", name)),
+        }
+    }
+}
+
+// `colorscheme` names the theme file, `groups` replace some of its highlight
+// groups.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub colorscheme: String,
+    pub groups: HashMap<String, Highlight>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        ThemeConfig {
+            colorscheme: DEFAULT_COLORSCHEME.to_string(),
+            groups: HashMap::new(),
+        }
+    }
+}
+
+// Keys, like `"<C-s>" = "write"`, that run an ex command.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keymaps {
+    pub normal: HashMap<String, String>,
+    pub insert: HashMap<String, String>,
+    pub visual: HashMap<String, String>,
+}
+
+impl Keymaps {
+    pub fn get(&self, mode: EditorMode, keys: &str) -> Option<&String> {
+        match mode {
+            EditorMode::Normal => self.normal.get(keys),
+            EditorMode::Insert => self.insert.get(keys),
+            EditorMode::Visual => self.visual.get(keys),
+            EditorMode::Command => None,
+        }
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &String> {
+        self.normal
+            .keys()
+            .chain(self.insert.keys())
+            .chain(self.visual.keys())
+    }
+}
+
+// The status line shows one line, so the message is joined into it.
+pub fn error_at(path: &Path, text: &str, e: &toml::de::Error) -> String {
+    let message = e.message().lines().collect::<Vec<&str>>().join(": ");
+    match e.span() {
+        Some(span) => {
+            let line = text[..span.start].matches('\n').count() + 1;
+            format!("{}:{}: {}", path.display(), line, message)
+        }
+        None => format!("{}: {}", path.display(), message),
+    }
+}
+
+// A file that doesn't exist reads as empty. One with errors is left out as a
+// whole, so a typo never half-applies.
+fn read_table(path: &Path) -> Result<Table, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) if !path.exists() => return Ok(Table::new()),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    let config = toml::from_str::<Config>(&text).map_err(|e| error_at(path, &text, &e))?;
+    validate(&config).map_err(|e| format!("{}: {}", path.display(), e))?;
+    toml::from_str::<Table>(&text).map_err(|e| error_at(path, &text, &e))
+}
+
+// Tables are merged key by key, anything else is replaced.
+fn merge(base: &mut Table, over: Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn validate(config: &Config) -> Result<(), String> {
+    if let Some(host) = config.plugins.iter().find(|host| host.command.is_empty()) {
+        return Err(format!("Plugin host \"{}\" has no command", host.name));
+    }
+    if config.editor.tab_width == 0 {
+        return Err("tab_width must be at least 1".to_string());
+    }
+    // Sequences like "gd" would never fire, see `Editor::execute_key`.
+    if let Some(keys) = config.keymaps.keys().find(|keys| !is_single_key(keys)) {
+        return Err(format!(
+            "Keymap \"{}\" is not a single key, like \"<C-s>\" or \"<g>\"",
+            keys
+        ));
+    }
+    Ok(())
+}
+
+pub fn read_config(path: &Path) -> Result<Config, String> {
+    toml::Value::Table(read_table(path)?)
+        .try_into()
+        .map_err(|e: toml::de::Error| format!("{}: {}", path.display(), e.message()))
+}
+
+// Never fails: whatever can't be read is reported and left at its default.
+pub fn load_config(root: &Path) -> (Config, Vec<String>) {
+    let user_config = config_dir().map(|dir| dir.join(CONFIG_FILE));
+    load_config_files(user_config.as_deref(), root)
+}
+
+fn load_config_files(user_config: Option<&Path>, root: &Path) -> (Config, Vec<String>) {
+    let mut errors = vec![];
+    let mut table = Table::new();
+    if let Some(path) = user_config {
+        match read_table(path) {
+            Ok(user) => table = user,
+            Err(e) => errors.push(e),
+        }
+    }
+
+    let project_path = root.join(PROJECT_CONFIG_FILE);
+    match read_table(&project_path) {
+        Ok(mut project) => {
+            // Opening a file in a directory must not run what it names.
+            if project.remove("plugins").is_some() {
+                errors.push(format!(
+                    "{}: plugins are only read from the user config",
+                    project_path.display()
+                ));
+            }
+            merge(&mut table, project);
+        }
+        Err(e) => errors.push(e),
+    }
+
+    // Each file on its own was valid, so their merge is too.
+    match toml::Value::Table(table).try_into::<Config>() {
+        Ok(config) => (config, errors),
+        Err(e) => {
+            errors.push(e.message().to_string());
+            (Config::default(), errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    #[test]
+    fn set_changes_options() {
+        let mut options = EditorOptions::default();
+        options.set("tab_width", Some("8")).unwrap();
+        options.set("noexpand_tab", None).unwrap();
+        options.set("smart_case", Some("false")).unwrap();
+        options.set("swap_file", None).unwrap();
+        assert_eq!(
+            options.describe(),
+            "tab_width=8  noexpand_tab  nosmart_case  swap_file"
+        );
+
+        let errors = [
+            ("tab_width", Some("0"), "Invalid value for tab_width: 0"),
+            ("tab_width", Some("x"), "Invalid value for tab_width: x"),
+            ("tab_width", None, "tab_width needs a value"),
+            ("notab_width", None, "Unknown option: notab_width"),
+            (
+                "expand_tab",
+                Some("yes"),
+                "Invalid value for expand_tab: yes",
+            ),
+            ("wrap", None, "Unknown option: wrap"),
+            ("nowrap", None, "Unknown option: nowrap"),
+            ("wrap", Some("true"), "Unknown option: wrap"),
+        ];
+        for (name, value, error) in errors {
+            assert_eq!(options.set(name, value), Err(error.to_string()));
+        }
+        assert_eq!(options.get("tab_width"), Some("tab_width=8".to_string()));
+    }
+
+    #[test]
+    fn project_config_goes_over_the_user_config() {
+        let dir = temp_dir("project_config_goes_over");
+        let user = dir.join(CONFIG_FILE);
+        fs::write(
+            &user,
+            "[editor]\ntab_width = 2\nsmart_case = false\n\n\
+             [keymaps.normal]\n\"<C-s>\" = \"write\"\n\n\
+             [[plugins]]\nname = \"mine\"\ncommand = [\"mine\"]\n",
+        )
+        .unwrap();
+        let project = dir.join("project");
+        fs::create_dir(&project).unwrap();
+        fs::write(
+            project.join(PROJECT_CONFIG_FILE),
+            "[editor]\ntab_width = 8\n\n\
+             [keymaps.normal]\n\"<C-q>\" = \"quit\"\n\n\
+             [[plugins]]\nname = \"theirs\"\ncommand = [\"curl\"]\n",
+        )
+        .unwrap();
+
+        let (config, errors) = load_config_files(Some(&user), &project);
+        assert_eq!(config.editor.tab_width, 8);
+        assert!(!config.editor.smart_case);
+        assert_eq!(config.keymaps.normal.len(), 2);
+        let names: Vec<&str> = config
+            .plugins
+            .iter()
+            .map(|host| host.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["mine"]);
+        assert_eq!(
+            errors,
+            vec![format!(
+                "{}: plugins are only read from the user config",
+                project.join(PROJECT_CONFIG_FILE).display()
+            )]
+        );
+
+        // A project file with errors is left out, the user config still applies.
+        fs::write(
+            project.join(PROJECT_CONFIG_FILE),
+            "[editor]\ntab_width = \"8\"\n",
+        )
+        .unwrap();
+        let (config, errors) = load_config_files(Some(&user), &project);
+        assert_eq!(config.editor.tab_width, 2);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn errors_point_at_their_line() {
+        let dir = temp_dir("errors_point_at_their_line");
+        let path = dir.join(CONFIG_FILE);
+        let error = |text: &str| {
+            fs::write(&path, text).unwrap();
+            read_config(&path).unwrap_err()
+        };
+        let at = |line: usize| format!("{}:{}: ", path.display(), line);
+
+        assert!(error("[editor]\n\ntab_width = \"4\"\n").starts_with(&at(3)));
+        assert!(error("[editor]\nwrap = true\n").starts_with(&at(2)));
+        assert!(error("\n\n\n[editor\n").starts_with(&at(4)));
+        assert!(!error("[editor]\ntab_width = \"4\"\n").contains('\n'));
+        assert_eq!(
+            error("[editor]\ntab_width = 0\n"),
+            format!("{}: tab_width must be at least 1", path.display())
+        );
+    }
+
+    #[test]
+    fn keymaps_take_one_key() {
+        let dir = temp_dir("keymaps_take_one_key");
+        let path = dir.join(CONFIG_FILE);
+        for keys in ["<C-s>", "<g>", "<<>", "<>>", "<F5>"] {
+            fs::write(
+                &path,
+                format!("[keymaps.normal]\n\"{}\" = \"write\"\n", keys),
+            )
+            .unwrap();
+            assert!(read_config(&path).is_ok(), "{}", keys);
+        }
+        for keys in ["gd", "<leader>w", "<g><d>", "<>", "x"] {
+            fs::write(
+                &path,
+                format!("[keymaps.visual]\n\"{}\" = \"write\"\n", keys),
+            )
+            .unwrap();
+            assert_eq!(
+                read_config(&path).unwrap_err(),
+                format!(
+                    "{}: Keymap \"{}\" is not a single key, like \"<C-s>\" or \"<g>\"",
+                    path.display(),
+                    keys
+                )
+            );
+        }
+    }
+}
@@ -1,23 +1,42 @@
 use serde::{Deserialize, Serialize};
-use std::fmt::Write as FmtWrite;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write as IoWrite};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
 use std::sync::Arc;
 use std::time::Instant;
-use std::usize;
-use std::{collections::HashMap, io::Stdout};
-use termion::raw::RawTerminal;
-use termion::screen::AlternateScreen;
-use termion::{
-    clear,
-    color::{self, Rgb as TermionRgb},
-    cursor,
-};
+use termion::{color::Rgb as TermionRgb, cursor};
 use ts_rs::TS;
+use unicode_segmentation::UnicodeSegmentation;
 
+use crate::buffers::buffer_list::BufferList;
+use crate::buffers::sync_focus_buffer_lines;
+use crate::command::completion::Completion;
+use crate::command::parser::ExCommand;
+use crate::command::recovery::PendingRecovery;
+use crate::command::substitute::PendingSubstitution;
+use crate::command::{error_message, push_message, run_command_from, CommandPrompt};
+use crate::config::Config;
 use crate::editor_modes::{EditorMode, ExecuteKey};
+use crate::files::swap::{swap_path, Journal};
+use crate::files::{write_atomic, FileStamp};
+use crate::layout::floating::{FloatingWindows, UNFOCUS_KEY};
+use crate::layout::{sync_layout, text_window, Layout, SplitAxis};
+use crate::normal::pending::PendingCommand;
+use crate::registers::{Register, Registers};
+use crate::screen::backend::Backend;
+use crate::screen::{Cell, CellColor, CellStyle, Grid, Screen, TerminalCursor};
+use crate::search::{compile_pattern, search_color_ranges, SearchState};
+use crate::server::events::{publish_events, text_change, BufferNotice};
+use crate::server::hosts::PluginHosts;
+use crate::server::plugins::Plugins;
+use crate::server::server_messages::{InputMessage, ResizedMessage, ServerMessages, TextChange};
+use crate::text::file_format::FileFormat;
+use crate::text::{cell_width, grapheme_columns, grapheme_width, to_cursor, TextRope};
+use crate::theme::Theme;
+use crate::undo::{EditOperation, TextPosition, UndoTree};
+use crate::visual::{selection_color_ranges, Selection};
 
-#[derive(Deserialize, TS, Clone, Copy)]
+#[derive(Deserialize, TS, Clone, Copy, PartialEq, Debug)]
 #[ts(export)]
 pub struct Rgb(pub u8, pub u8, pub u8);
 
@@ -27,7 +46,7 @@ impl Rgb {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum CursorForm {
     SteadyBar,
     SteadyBlock,
@@ -42,13 +61,6 @@ impl CursorForm {
             CursorForm::SteadyUnderline => Box::new(cursor::SteadyUnderline),
         }
     }
-    pub fn to_char(&self) -> char {
-        match self {
-            CursorForm::SteadyBar => '|',       // Representación de barra
-            CursorForm::SteadyBlock => '█',     // Representación de bloque
-            CursorForm::SteadyUnderline => '_', // Representación de subrayado
-        }
-    }
 }
 
 #[derive(Clone)]
@@ -57,7 +69,7 @@ pub struct EditorCursor {
     pub form: CursorForm,
 }
 
-#[derive(Serialize, Deserialize, TS)]
+#[derive(Clone, Serialize, Deserialize, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
 pub struct EditorCursorContext {
@@ -72,7 +84,7 @@ impl EditorCursor {
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, TS)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
 pub struct EditorWindow {
@@ -82,13 +94,18 @@ pub struct EditorWindow {
 
 pub type HandleKeysFn = Arc<dyn Fn(&mut Editor, &str) + Send + Sync>;
 
-#[derive(Clone, Copy, Serialize, Deserialize, TS)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
 pub struct ColorRange {
     pub range: (u16, u16),
     pub bg_color: Option<Rgb>,
     pub fg_color: Option<Rgb>,
+    // A highlight group of the theme, like `Search` or `keyword`. The colors,
+    // when set, are drawn over it.
+    #[serde(default)]
+    #[ts(optional = nullable)]
+    pub group: Option<String>,
 }
 
 impl Serialize for Rgb {
@@ -104,26 +121,35 @@ impl Serialize for Rgb {
 #[derive(Clone)]
 pub struct EditorBuffer {
     pub cursors: Vec<EditorCursor>,
-    pub content: Vec<String>,
+    pub content: TextRope,
     pub colors: Vec<Vec<ColorRange>>,
     pub file_name: Option<String>,
+    pub file_format: FileFormat,
+    pub disk_stamp: Option<FileStamp>,
     pub is_modified: bool,
-    pub memory: Vec<String>,
     pub last_input: Instant,
     pub buffer_window: EditorWindow,
     pub handle_keys: HandleKeysFn,
     pub pivot: (u16, u16),
     pub tab_width: u16,
+    // Indent with spaces instead of tabs.
+    pub expand_tab: bool,
+    pub history: UndoTree,
+    pub journal: Journal,
+    pub notices: Vec<BufferNotice>,
+    pub read_only: bool,
+    pub selection: Option<Selection>,
+    pub visual_marks: Option<(usize, usize)>,
 }
 
-#[derive(Serialize, Deserialize, TS)]
+#[derive(Clone, Serialize, Deserialize, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
 pub struct EditorBufferContext {
     pub cursors: Vec<EditorCursorContext>,
     pub content: Vec<String>,
     pub colors: Vec<Vec<ColorRange>>,
-    pub memory: Vec<String>,
+    pub registers: HashMap<String, Register>,
     pub buffer_window: EditorWindow,
     pub tab_width: u16,
 }
@@ -135,16 +161,24 @@ impl EditorBuffer {
                 position: (0, 0),
                 form: CursorForm::SteadyBlock,
             }],
-            content: vec![String::new()],
+            content: TextRope::new(),
             colors: vec![],
             file_name: None,
+            file_format: FileFormat::default(),
+            disk_stamp: None,
             is_modified: false,
-            memory: vec!["".to_string()],
             last_input: Instant::now(),
             pivot: (0, 0),
             buffer_window,
             handle_keys,
             tab_width,
+            expand_tab: true,
+            history: UndoTree::new(),
+            journal: Journal::default(),
+            notices: vec![],
+            read_only: false,
+            selection: None,
+            visual_marks: None,
         }
     }
 
@@ -154,71 +188,289 @@ impl EditorBuffer {
         buffer_window: EditorWindow,
         tab_width: u16,
     ) -> Self {
-        let file = File::open(file_path);
-
-        let mut content: Vec<String> = Vec::new();
-
-        match file {
-            Ok(file) => {
-                let reader = BufReader::new(file);
-                for line in reader.lines() {
-                    if let Ok(line) = line {
-                        let processed_line =
-                            line.clone().replace('\t', &" ".repeat(tab_width as usize));
-                        content.push(processed_line);
-                    }
-                }
-            }
-            Err(_) => {
-                content.push(String::new());
-            }
-        }
+        let (text, file_format) = match fs::read(file_path) {
+            Ok(bytes) => FileFormat::decode(&bytes),
+            Err(_) => (String::new(), FileFormat::default()),
+        };
 
         EditorBuffer {
             cursors: vec![EditorCursor {
                 position: (0, 0),
                 form: CursorForm::SteadyBlock,
             }],
-            content,
+            content: TextRope::from_text(&text),
             colors: vec![],
             file_name: Some(file_path.to_string()),
+            file_format,
+            disk_stamp: FileStamp::of(file_path),
             is_modified: false,
-            memory: vec!["".to_string()],
             last_input: Instant::now(),
             pivot: (0, 0),
             buffer_window,
             handle_keys,
             tab_width,
+            expand_tab: true,
+            history: UndoTree::new(),
+            journal: Journal::default(),
+            notices: vec![],
+            read_only: false,
+            selection: None,
+            visual_marks: None,
         }
     }
 
-    pub fn write_file(&self) -> io::Result<()> {
-        if let Some(file_name) = &self.file_name {
-            let mut file = File::create(file_name)?;
-
-            for line in &self.content {
-                writeln!(file, "{}", line)?;
-            }
-            Ok(())
-        } else {
-            Err(io::Error::new(
+    pub fn write_file(&mut self) -> io::Result<()> {
+        match self.file_name.clone() {
+            Some(file_name) => self.save_as(file_name),
+            None => Err(io::Error::new(
                 io::ErrorKind::NotFound,
                 "No file name specified",
-            ))
+            )),
+        }
+    }
+
+    // Writes the buffer to `file_name`, which only becomes its name once the
+    // write went through.
+    pub fn save_as(&mut self, file_name: String) -> io::Result<()> {
+        self.write_to(&file_name)?;
+        self.disk_stamp = FileStamp::of(&file_name);
+        self.file_name = Some(file_name);
+        self.journal.clear();
+        self.remove_swap();
+        self.notices.push(BufferNotice::Saved);
+        Ok(())
+    }
+
+    pub fn write_to(&self, file_name: &str) -> io::Result<()> {
+        let bytes = self
+            .file_format
+            .encode(self.content.lines().map(String::from))?;
+        write_atomic(file_name, &bytes)
+    }
+
+    // True when someone else wrote the file after we last read or wrote it.
+    pub fn changed_on_disk(&self) -> bool {
+        match (&self.file_name, self.disk_stamp) {
+            (Some(file_name), Some(stamp)) => FileStamp::of(file_name) != Some(stamp),
+            _ => false,
+        }
+    }
+
+    // Writes the journal to the swap file once it has been pending for a while,
+    // or right away with `force`.
+    pub fn sync_swap(&mut self, force: bool) {
+        if self.read_only || !self.journal.needs_sync(force) {
+            return;
+        }
+        let Some(path) = self.file_name.as_deref().and_then(swap_path) else {
+            return;
+        };
+
+        if self.journal.needs_compaction() {
+            self.journal.compact(self.content.to_lines().join("\n"));
+        }
+        let file_name = self.file_name.clone().unwrap_or_default();
+        self.journal.sync(&path, &file_name, self.disk_stamp);
+    }
+
+    // Read-only buffers never wrote a swap, the one there belongs to someone else.
+    pub fn remove_swap(&self) {
+        if self.read_only {
+            return;
+        }
+        if let Some(path) = self.file_name.as_deref().and_then(swap_path) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    // Discards every change and reads the file again.
+    pub fn reload(&mut self) -> io::Result<()> {
+        let file_name = self.file_name.clone().ok_or(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No file name specified",
+        ))?;
+        let (text, file_format) = FileFormat::decode(&fs::read(&file_name)?);
+
+        let last_line = self.content.len_lines().saturating_sub(1);
+        self.notices.push(BufferNotice::Changed(TextChange {
+            start: (0, 0),
+            end: to_cursor((last_line, self.line_len(last_line))),
+            text: text.clone(),
+        }));
+        self.content = TextRope::from_text(&text);
+        self.file_format = file_format;
+        self.disk_stamp = FileStamp::of(&file_name);
+        self.history = UndoTree::new();
+        self.journal.clear();
+        self.remove_swap();
+        self.is_modified = false;
+        self.selection = None;
+        self.visual_marks = None;
+        self.clamp_cursors();
+        Ok(())
+    }
+
+    // Moves cursors left behind by a change of content back into the text.
+    pub fn clamp_cursors(&mut self) {
+        let last_line = self.content.len_lines().saturating_sub(1);
+        for cursor in self.cursors.iter_mut() {
+            let line = (cursor.position.1 as usize).min(last_line);
+            let col = self
+                .content
+                .grapheme_start(line, cursor.position.0 as usize);
+            cursor.position = to_cursor((line, col));
+        }
+    }
+
+    pub fn cursor_positions(&self) -> Vec<(u16, u16)> {
+        self.cursors.iter().map(|cursor| cursor.position).collect()
+    }
+
+    fn set_cursor_positions(&mut self, positions: &[(u16, u16)]) {
+        for (cursor, position) in self.cursors.iter_mut().zip(positions) {
+            cursor.position = *position;
+        }
+    }
+
+    pub fn line_len(&self, line: usize) -> usize {
+        self.content.line_len(line)
+    }
+
+    // Screen column of a cursor position, relative to the start of its line.
+    pub fn display_col(&self, position: (u16, u16)) -> u16 {
+        self.content
+            .display_col(position.1 as usize, position.0 as usize, self.tab_width)
+            .try_into()
+            .unwrap_or(u16::MAX)
+    }
+
+    // Every change goes to the swap journal and to the plugins.
+    fn record(&mut self, operation: &EditOperation) {
+        self.journal.push(operation.clone());
+        self.notices
+            .push(BufferNotice::Changed(text_change(operation)));
+    }
+
+    fn apply_operation(&mut self, operation: &EditOperation) {
+        self.record(operation);
+        match operation {
+            EditOperation::Insert { position, text } => {
+                self.content.insert(*position, text);
+            }
+            EditOperation::Delete { position, text } => {
+                let end = TextRope::end_of_text(*position, text);
+                self.content.remove(*position, end);
+            }
+        }
+    }
+
+    pub fn insert_text(&mut self, position: TextPosition, text: &str) -> TextPosition {
+        if text.is_empty() {
+            return position;
         }
+        let cursors = self.cursor_positions();
+        let end = self.content.insert(position, text);
+        let operation = EditOperation::Insert {
+            position,
+            text: text.to_string(),
+        };
+        self.record(&operation);
+        self.history.record(operation, cursors);
+        self.is_modified = true;
+        end
     }
 
-    pub fn to_buffer_context(self) -> EditorBufferContext {
+    pub fn delete_text(&mut self, start: TextPosition, end: TextPosition) -> String {
+        let cursors = self.cursor_positions();
+        let removed = self.content.remove(start, end);
+        if removed.is_empty() {
+            return removed;
+        }
+        let operation = EditOperation::Delete {
+            position: start,
+            text: removed.clone(),
+        };
+        self.record(&operation);
+        self.history.record(operation, cursors);
+        self.is_modified = true;
+        removed
+    }
+
+    pub fn commit_undo(&mut self) {
+        let cursors = self.cursor_positions();
+        self.history.commit(cursors);
+        self.is_modified = self.history.is_modified();
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.commit_undo();
+        match self.history.undo() {
+            Some(transaction) => {
+                for operation in transaction.operations.iter().rev() {
+                    self.apply_operation(&operation.inverse());
+                }
+                self.set_cursor_positions(&transaction.cursors_before);
+                self.is_modified = self.history.is_modified();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.commit_undo();
+        match self.history.redo() {
+            Some(transaction) => {
+                for operation in transaction.operations.iter() {
+                    self.apply_operation(operation);
+                }
+                self.set_cursor_positions(&transaction.cursors_after);
+                self.is_modified = self.history.is_modified();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn undo_to(&mut self, change: usize) -> Result<(), String> {
+        self.commit_undo();
+        let (undone, redone) = self
+            .history
+            .jump(change)
+            .ok_or_else(|| format!("Undo number {} not found", change))?;
+        for transaction in &undone {
+            for operation in transaction.operations.iter().rev() {
+                self.apply_operation(&operation.inverse());
+            }
+            self.set_cursor_positions(&transaction.cursors_before);
+        }
+        for transaction in &redone {
+            for operation in transaction.operations.iter() {
+                self.apply_operation(operation);
+            }
+            self.set_cursor_positions(&transaction.cursors_after);
+        }
+        self.is_modified = self.history.is_modified();
+        Ok(())
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.commit_undo();
+        self.history.mark_saved();
+        self.is_modified = false;
+    }
+
+    pub fn to_buffer_context(&self, registers: &Registers) -> EditorBufferContext {
         EditorBufferContext {
             cursors: self
                 .cursors
                 .iter()
                 .map(|cursor| cursor.clone().to_cursor_context())
                 .collect(),
-            content: self.content.clone(),
+            content: self.content.to_lines(),
             colors: self.colors.clone(),
-            memory: self.memory.clone(),
-            buffer_window: self.buffer_window.clone(),
+            registers: registers.to_context(),
+            buffer_window: self.buffer_window,
             tab_width: self.tab_width,
         }
     }
@@ -228,17 +480,33 @@ pub struct Editor {
     pub buffers: HashMap<String, EditorBuffer>,
 
     pub buffers_to_show: Vec<String>,
+    pub buffer_list: BufferList,
+    pub layout: Layout,
     pub focus_buffer: String,
     pub editor_mode: EditorMode,
     pub close: bool,
     pub terminal_size: (u16, u16),
     pub commands_hist: Vec<String>,
     pub root: String,
+    pub pending_command: PendingCommand,
+    pub registers: Registers,
+    pub search: SearchState,
+    pub command_prompt: CommandPrompt,
+    pub pending_substitution: Option<PendingSubstitution>,
+    pub completion: Option<Completion>,
+    pub pending_recoveries: Vec<PendingRecovery>,
+    pub config: Config,
+    pub theme: Theme,
+    pub plugins: Plugins,
+    pub hosts: PluginHosts,
+    pub floating: FloatingWindows,
+    pub log: Vec<String>,
 
-    stdout: AlternateScreen<RawTerminal<Stdout>>,
+    backend: Box<dyn Backend>,
+    screen: Screen,
 }
 
-#[derive(Serialize, Deserialize, TS)]
+#[derive(Clone, Serialize, Deserialize, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
 pub struct EditorContext {
@@ -250,19 +518,37 @@ pub struct EditorContext {
     pub commands_hist: Vec<String>,
 }
 
-pub type EditorFunctions = Box<dyn Fn(&mut Editor) + Sync + Send + 'static>;
+pub type EditorFunctions =
+    Box<dyn Fn(&mut Editor, &ExCommand) -> Result<(), String> + Sync + Send + 'static>;
 
 impl Editor {
-    pub fn new(stdout: AlternateScreen<RawTerminal<Stdout>>, root: String) -> Self {
+    pub fn new(backend: Box<dyn Backend>, root: String) -> Self {
+        let terminal_size = backend.size().unwrap_or((80, 24));
         Editor {
             buffers_to_show: vec![],
             buffers: HashMap::new(),
+            buffer_list: BufferList::default(),
+            layout: Layout::new(""),
             editor_mode: EditorMode::Normal,
             close: false,
-            terminal_size: termion::terminal_size().unwrap(),
+            terminal_size,
             focus_buffer: String::new(),
             commands_hist: vec![],
-            stdout,
+            pending_command: PendingCommand::default(),
+            registers: Registers::default(),
+            search: SearchState::default(),
+            command_prompt: CommandPrompt::Ex,
+            pending_substitution: None,
+            completion: None,
+            pending_recoveries: vec![],
+            config: Config::default(),
+            theme: Theme::default(),
+            plugins: Plugins::default(),
+            hosts: PluginHosts::default(),
+            floating: FloatingWindows::default(),
+            log: vec![],
+            backend,
+            screen: Screen::new(terminal_size),
             root,
         }
     }
@@ -280,41 +566,103 @@ impl Editor {
     }
 
     pub fn invoke_buffer_handler(&mut self, key: &str) {
-        let buffers = self.buffers.clone();
-        if let Some(buffer) = buffers.get(&self.focus_buffer.clone()) {
-            (buffer.handle_keys)(self, key);
+        let focus_buffer = self.focus_buffer.clone();
+        self.invoke_handler_of(&focus_buffer, key);
+    }
+
+    fn invoke_handler_of(&mut self, buffer: &str, key: &str) {
+        let handle_keys = self
+            .buffers
+            .get(buffer)
+            .map(|buffer| Arc::clone(&buffer.handle_keys));
+
+        if let Some(handle_keys) = handle_keys {
+            handle_keys(self, key);
         }
     }
 
-    pub fn redraw(&mut self, terminal_size: (u16, u16)) {
-        self.terminal_size = terminal_size;
+    pub fn redraw(&mut self) {
+        if let Ok(terminal_size) = self.backend.size() {
+            if terminal_size != self.terminal_size {
+                self.plugins
+                    .publish(ServerMessages::Resized(ResizedMessage { terminal_size }));
+            }
+            self.terminal_size = terminal_size;
+        }
+        sync_layout(self);
+        sync_focus_buffer_lines(self);
     }
 
     pub fn execute_key(&mut self, key: &str) {
         let editor_mode = self.editor_mode;
-        editor_mode.execute_key(key, self);
+        self.plugins.publish(ServerMessages::Input(InputMessage {
+            input: key.to_string(),
+            editor_mode,
+        }));
+        let mapped = match self.pending_command.is_empty() {
+            true => self.config.keymaps.get(editor_mode, key).cloned(),
+            false => None,
+        };
+        if let Some(float) = self.floating.focus.clone() {
+            // A focused floating window takes every key but one.
+            if key == UNFOCUS_KEY {
+                self.floating.focus = None;
+            } else {
+                self.invoke_handler_of(&float, key);
+            }
+        } else if let Some(command) = mapped {
+            if let Err(message) = run_command_from(self, &command) {
+                push_message(self, error_message(&message));
+            }
+            sync_focus_buffer_lines(self);
+        } else if !(self.pending_command.is_empty()
+            && self.plugins.trigger_keymap(editor_mode, key))
+        {
+            // A key mapped by a plugin goes to it, unless it finishes a pending command.
+            editor_mode.execute_key(key, self);
+        }
+        publish_events(self);
+    }
+
+    pub fn sync_swap_files(&mut self, force: bool) {
+        // Quitting removed them on purpose.
+        if self.close || !self.config.editor.swap_file {
+            return;
+        }
+        for buffer in self.buffers.values_mut() {
+            buffer.sync_swap(force);
+        }
     }
 
     pub fn close_editor(&mut self) {
         self.close = true;
-        write!(self.stdout, "{}{}", clear::All, cursor::Show).unwrap();
-        self.stdout.flush().unwrap();
+        self.hosts.stop_all();
+        for buffer in self.buffers.values() {
+            buffer.remove_swap();
+        }
+        self.backend.clear().unwrap();
+        self.backend
+            .set_cursor(Some(&TerminalCursor {
+                position: (1, 1),
+                form: CursorForm::SteadyBlock,
+            }))
+            .unwrap();
+        self.backend.flush().unwrap();
     }
 
     pub fn to_editor_context(&self) -> EditorContext {
-        let buffers = self.buffers.clone();
-        let mut buffers_context: HashMap<String, EditorBufferContext> = HashMap::new();
-
-        buffers.into_iter().for_each(|(key, buffer)| {
-            buffers_context.insert(key, buffer.to_buffer_context());
-        });
+        let buffers_context: HashMap<String, EditorBufferContext> = self
+            .buffers
+            .iter()
+            .map(|(key, buffer)| (key.clone(), buffer.to_buffer_context(&self.registers)))
+            .collect();
 
         EditorContext {
             buffers: buffers_context,
             buffers_to_show: self.buffers_to_show.clone(),
             focus_buffer: self.focus_buffer.clone(),
-            editor_mode: self.editor_mode.clone(),
-            terminal_size: self.terminal_size.clone(),
+            editor_mode: self.editor_mode,
+            terminal_size: self.terminal_size,
             commands_hist: self.commands_hist.clone(),
         }
     }
@@ -323,147 +671,160 @@ impl Editor {
         let editor = self;
         let terminal_size = editor.terminal_size;
         let editor_mode = editor.editor_mode;
-        let buffers = editor.buffers.clone();
-
-        let mut render_buffer = String::new();
-
-        write!(
-            render_buffer,
-            "{}{}{}",
-            clear::All,
-            cursor::Goto(1, 1),
-            cursor::Hide
-        )
-        .unwrap();
-
-        for buff_name in editor.buffers_to_show.clone().iter() {
-            if let Some(buffer) = buffers.get(buff_name) {
-                let window_width =
-                    (buffer.buffer_window.end.0 - buffer.buffer_window.start.0) as usize;
-                let window_height =
-                    (buffer.buffer_window.end.1 - buffer.buffer_window.start.1) as usize;
-
-                let buffer_content = buffer.content.clone();
-                let buffer_colors = buffer.colors.clone();
-                let cursors = if buff_name == &editor.focus_buffer {
-                    buffer.cursors.clone()
+
+        editor.screen.resize(terminal_size);
+        let grid = editor.screen.back_mut();
+        let theme = &editor.theme;
+        grid.fill(theme.normal());
+
+        // Incremental search: highlight what the pattern typed so far matches.
+        let search_regex = match (editor_mode, editor.command_prompt) {
+            (EditorMode::Command, CommandPrompt::Search(_)) => editor
+                .commands_hist
+                .last()
+                .filter(|pattern| !pattern.is_empty())
+                .and_then(|pattern| compile_pattern(pattern, editor.config.editor.smart_case).ok()),
+            (EditorMode::Command, CommandPrompt::Substitute) => editor
+                .pending_substitution
+                .as_ref()
+                .map(|pending| pending.regex.clone()),
+            _ => None,
+        };
+
+        let computed = editor.layout.compute(terminal_size);
+        let gutter_style = theme.style("LineNr");
+        let mut terminal_cursor: Option<TerminalCursor> = None;
+
+        for (axis, window) in computed.separators {
+            let separator = match axis {
+                SplitAxis::Horizontal => "─",
+                SplitAxis::Vertical => "│",
+            };
+            for row in window.start.1..window.end.1 {
+                for column in window.start.0..window.end.0 {
+                    grid.set(
+                        column,
+                        row,
+                        Cell::new(separator, theme.style("WinSeparator")),
+                    );
+                }
+            }
+        }
+
+        for (pane_id, window) in computed.panes {
+            let Some(pane) = editor.layout.panes.get(&pane_id) else {
+                continue;
+            };
+            let Some(buffer) = editor.buffers.get(&pane.buffer) else {
+                continue;
+            };
+            let focused = pane_id == editor.layout.focus;
+
+            // The focused pane's view lives in the buffer, see `Pane`.
+            let (cursors, pivot): (&[EditorCursor], (u16, u16)) = if focused {
+                (&buffer.cursors, buffer.pivot)
+            } else {
+                (&[], pane.pivot)
+            };
+
+            let text = text_window(window);
+            let window_height = (text.end.1 - text.start.1) as usize;
+            let gutter_width = (text.start.0 - window.start.0) as usize;
+
+            for i in 0..window_height {
+                let line_idx = i + pivot.1 as usize;
+                let row = text.start.1 + i as u16;
+
+                let line_number = if line_idx < buffer.content.len_lines() {
+                    (line_idx + 1).to_string()
                 } else {
-                    vec![]
+                    String::new()
                 };
+                let number_width = gutter_width.saturating_sub(2);
+                grid.put_str(
+                    window.start.0,
+                    row,
+                    &format!("{:>width$} ", line_number, width = number_width),
+                    gutter_style,
+                );
 
-                for i in 0..window_height {
-                    write!(
-                        render_buffer,
-                        "{}{}{}",
-                        cursor::Goto(
-                            buffer.buffer_window.start.0,
-                            buffer.buffer_window.start.1 + i as u16
-                        ),
-                        color::Bg(color::Reset),
-                        color::Fg(color::Reset)
-                    )
-                    .unwrap();
-                    let line = buffer_content
-                        .get(i + buffer.pivot.1 as usize)
-                        .unwrap_or(&"".to_string())
-                        .clone();
-                    let line_colors = buffer_colors.get(i).unwrap_or(&vec![]).clone();
-                    let cursor = cursors.iter().find(|cursor| cursor.position.1 == i as u16);
-
-                    let current_color = line_colors
-                        .iter()
-                        .find(|color_range| color_range.range.0 <= 0 && color_range.range.1 > 0);
-
-                    for j in 0..window_width {
-                        let char = line.chars().nth(j + buffer.pivot.0 as usize).unwrap_or(' ');
-
-                        match current_color {
-                            Some(color) => {
-                                if color.range.0 == j as u16 {
-                                    write!(
-                                        render_buffer,
-                                        "{}{}",
-                                        color::Bg(color.bg_color.unwrap().to_termion_rgb()),
-                                        color::Fg(color.fg_color.unwrap().to_termion_rgb())
-                                    )
-                                    .unwrap();
-                                } else if color.range.1 == j as u16 {
-                                    write!(
-                                        render_buffer,
-                                        "{}{}",
-                                        color::Bg(color::Reset),
-                                        color::Fg(color::Reset)
-                                    )
-                                    .unwrap();
-                                } else if let Some(cursor) = cursor {
-                                    if cursor.position.0 == j as u16 {
-                                        write!(
-                                            render_buffer,
-                                            "{}{}",
-                                            color::Bg(color.fg_color.unwrap().to_termion_rgb()),
-                                            color::Fg(color.bg_color.unwrap().to_termion_rgb())
-                                        )
-                                        .unwrap();
-                                        write!(render_buffer, "{}", cursor.form.to_char()).unwrap();
-                                    } else {
-                                        write!(render_buffer, "{}", char).unwrap();
-                                    }
-                                } else {
-                                    write!(render_buffer, "{}", char).unwrap();
-                                }
-                            }
-                            None => {
-                                if let Some(cursor) = cursor {
-                                    if cursor.position.0 == j as u16 {
-                                        write!(render_buffer, "{}", cursor.form.to_char()).unwrap();
-                                    } else {
-                                        write!(render_buffer, "{}", char).unwrap();
-                                    }
-                                } else {
-                                    write!(render_buffer, "{}", char).unwrap();
-                                }
-                            }
-                        }
-                    }
+                let mut line_colors: Vec<ColorRange> =
+                    buffer.colors.get(line_idx).cloned().unwrap_or_default();
+                let cursor_line = cursors
+                    .first()
+                    .is_some_and(|cursor| cursor.position.1 as usize == line_idx);
+                if focused && editor_mode == EditorMode::Visual {
+                    line_colors.extend(selection_color_ranges(buffer, line_idx));
+                }
+                if let (true, Some(regex)) = (focused, &search_regex) {
+                    line_colors.extend(search_color_ranges(buffer, regex, line_idx));
                 }
+                let base = if cursor_line {
+                    theme.style("CursorLine")
+                } else {
+                    theme.normal()
+                };
 
-                write!(
-                    render_buffer,
-                    "{}{}",
-                    color::Bg(color::Reset),
-                    color::Fg(color::Reset)
-                )
-                .unwrap();
+                let cursor = draw_line(
+                    grid,
+                    buffer,
+                    (line_idx, row),
+                    text,
+                    pivot.0 as usize,
+                    &LineStyles::new(theme, base, &line_colors),
+                    cursors,
+                );
+                terminal_cursor = terminal_cursor.or(cursor);
             }
         }
 
-        write!(render_buffer, "{}", cursor::Goto(1, terminal_size.1)).unwrap();
+        draw_floating_windows(
+            grid,
+            &editor.buffers,
+            &editor.floating,
+            Layout::area(terminal_size),
+            theme,
+            &mut terminal_cursor,
+        );
+
+        let status_row = terminal_size.1;
+        let status_style = theme.style("StatusLine");
+        for column in 1..=terminal_size.0 {
+            grid.set(column, status_row, Cell::new(" ", status_style));
+        }
 
         match editor_mode {
             EditorMode::Command => {
-                let mut memory_text = String::new();
-                if let Some(last_memory) = editor.commands_hist.last() {
-                    memory_text = last_memory.to_string();
-                }
-                write!(render_buffer, "Command: {}{}", memory_text, cursor::Show).unwrap();
+                let label_end =
+                    grid.put_str(1, status_row, &editor.command_prompt.label(), status_style);
+                let memory_text = editor.commands_hist.last().cloned().unwrap_or_default();
+                let text_end = grid.put_ansi_str(label_end, status_row, &memory_text, status_style);
+                terminal_cursor = Some(TerminalCursor {
+                    position: (text_end.min(terminal_size.0), status_row),
+                    form: CursorForm::SteadyBar,
+                });
             }
             EditorMode::Visual => {
-                write!(
-                    render_buffer,
-                    "{}--VISUAL--{}",
-                    color::Fg(color::Cyan),
-                    color::Fg(color::Reset)
-                )
-                .unwrap();
+                let label = editor
+                    .buffers
+                    .get(&editor.focus_buffer)
+                    .and_then(|buffer| buffer.selection)
+                    .map(|selection| selection.kind.label())
+                    .unwrap_or("--VISUAL--");
+                grid.put_str(
+                    1,
+                    status_row,
+                    label,
+                    theme.apply("ModeVisual", status_style),
+                );
             }
             EditorMode::Insert => {
-                write!(
-                    render_buffer,
-                    "{}--INSERT--{}",
-                    color::Fg(color::Yellow),
-                    color::Fg(color::Reset)
-                )
-                .unwrap();
+                grid.put_str(
+                    1,
+                    status_row,
+                    "--INSERT--",
+                    theme.apply("ModeInsert", status_style),
+                );
             }
             EditorMode::Normal => {
                 let mut memory_text = String::new();
@@ -474,23 +835,408 @@ impl Editor {
                         memory_text = penultimate_memory.to_string();
                     }
                 }
-                write!(render_buffer, "{}", memory_text).unwrap();
+                grid.put_ansi_str(1, status_row, &memory_text, status_style);
             }
         }
 
+        if !editor.pending_command.is_empty() {
+            let pending_keys = &editor.pending_command.keys;
+            let column = terminal_size
+                .0
+                .saturating_sub(info.len() as u16 + pending_keys.len() as u16 + 2);
+            grid.put_str(column.max(1), status_row, pending_keys, status_style);
+        }
+
         let padding = terminal_size.0.saturating_sub(info.len() as u16);
+        grid.put_str(padding.max(1), status_row, &info, status_style);
+
+        let backend = editor.backend.as_mut();
+        editor
+            .screen
+            .flush(backend, terminal_cursor.as_ref())
+            .unwrap();
+
+        // OSC 52 requests don't touch the grid, so they bypass the diff.
+        for request in editor.registers.take_clipboard_requests() {
+            backend.write_sequence(&request).unwrap();
+        }
+        backend.flush().unwrap();
+    }
+}
 
-        write!(
-            render_buffer,
-            "{}{}",
-            cursor::Goto(padding, terminal_size.1),
-            info
-        )
-        .unwrap();
+// The styles a line is drawn with, resolved from its color ranges.
+struct LineStyles {
+    base: CellStyle,
+    ranges: Vec<((u16, u16), CellStyle)>,
+}
+
+impl LineStyles {
+    fn new(theme: &Theme, base: CellStyle, colors: &[ColorRange]) -> Self {
+        let ranges = colors
+            .iter()
+            .map(|color_range| {
+                let mut style = match color_range.group.as_deref() {
+                    Some(group) => theme.apply(group, base),
+                    None => base,
+                };
+                if let Some(fg) = color_range.fg_color {
+                    style.fg = CellColor::Rgb(fg);
+                }
+                if let Some(bg) = color_range.bg_color {
+                    style.bg = CellColor::Rgb(bg);
+                }
+                (color_range.range, style)
+            })
+            .collect();
+        LineStyles { base, ranges }
+    }
+
+    // Later ranges are drawn over earlier ones.
+    fn at(&self, column: u16) -> CellStyle {
+        self.ranges
+            .iter()
+            .rev()
+            .find(|(range, _)| range.0 <= column && column < range.1)
+            .map(|(_, style)| *style)
+            .unwrap_or(self.base)
+    }
+}
+
+// Draws line `line.0` of `buffer` on row `line.1` of `text`, scrolled
+// `pivot_x` display columns. Returns where the first cursor landed.
+fn draw_line(
+    grid: &mut Grid,
+    buffer: &EditorBuffer,
+    line: (usize, u16),
+    text: EditorWindow,
+    pivot_x: usize,
+    styles: &LineStyles,
+    cursors: &[EditorCursor],
+) -> Option<TerminalCursor> {
+    let (line_idx, row) = line;
+    let line_text = buffer.content.line_string(line_idx);
+    let line_len = buffer.content.line_len(line_idx);
+    let window_width = (text.end.0 - text.start.0) as usize;
+    let right_edge = text.start.0 + window_width as u16;
+    let mut display = 0;
+    let mut terminal_cursor = None;
+
+    // Past the end of the line each cell stands for one more column,
+    // so a cursor or a selection there is still drawn.
+    let past_end = (line_len..).map(|col| (col, " "));
+    for (col, grapheme) in grapheme_columns(&line_text).chain(past_end) {
+        let start = display;
+        display += cell_width(grapheme, start, buffer.tab_width);
+        if display <= pivot_x {
+            continue;
+        }
+        if start >= pivot_x + window_width {
+            break;
+        }
+
+        let column = col as u16;
+        let mut style = styles.at(column);
+
+        // The first cursor is the terminal cursor, the others are drawn reversed.
+        let x = text.start.0 + start.saturating_sub(pivot_x) as u16;
+        match cursors
+            .iter()
+            .position(|cursor| cursor.position == (column, line_idx as u16))
+        {
+            Some(0) => {
+                terminal_cursor = Some(TerminalCursor {
+                    position: (x, row),
+                    form: cursors[0].form.clone(),
+                });
+            }
+            Some(_) => style.reverse = true,
+            None => {}
+        }
+
+        // Tabs and wide graphemes cut by the left edge are blank cells.
+        if grapheme == "\t" || start < pivot_x {
+            let end = text.start.0 + (display - pivot_x) as u16;
+            for blank in x..end.min(right_edge) {
+                grid.set(blank, row, Cell::new(" ", style));
+            }
+            continue;
+        }
+        grid.put_grapheme(x, row, grapheme, style, right_edge);
+    }
+    terminal_cursor
+}
+
+fn clip(window: EditorWindow, area: EditorWindow) -> EditorWindow {
+    let start = (
+        window.start.0.max(area.start.0),
+        window.start.1.max(area.start.1),
+    );
+    EditorWindow {
+        start,
+        end: (
+            window.end.0.min(area.end.0).max(start.0),
+            window.end.1.min(area.end.1).max(start.1),
+        ),
+    }
+}
+
+fn draw_border(
+    grid: &mut Grid,
+    window: EditorWindow,
+    title: Option<&str>,
+    style: CellStyle,
+    title_style: CellStyle,
+) {
+    let (left, top) = window.start;
+    let (right, bottom) = (window.end.0 - 1, window.end.1 - 1);
+    for column in left..=right {
+        grid.set(column, top, Cell::new("─", style));
+        grid.set(column, bottom, Cell::new("─", style));
+    }
+    for row in top..=bottom {
+        grid.set(left, row, Cell::new("│", style));
+        grid.set(right, row, Cell::new("│", style));
+    }
+    grid.set(left, top, Cell::new("┌", style));
+    grid.set(right, top, Cell::new("┐", style));
+    grid.set(left, bottom, Cell::new("└", style));
+    grid.set(right, bottom, Cell::new("┘", style));
+
+    if let Some(title) = title {
+        let mut column = left + 1;
+        for grapheme in format!(" {} ", title).graphemes(true) {
+            if column + grapheme_width(grapheme) as u16 > right {
+                break;
+            }
+            column = grid.put_grapheme(column, top, grapheme, title_style, right);
+        }
+    }
+}
+
+// Floats cover whatever the panes drew below them. While one has focus its
+// cursor is the terminal cursor, otherwise a pane cursor under a float is hidden.
+fn draw_floating_windows(
+    grid: &mut Grid,
+    buffers: &HashMap<String, EditorBuffer>,
+    floating: &FloatingWindows,
+    area: EditorWindow,
+    theme: &Theme,
+    terminal_cursor: &mut Option<TerminalCursor>,
+) {
+    let normal = theme.style("NormalFloat");
+    let mut focused_cursor = None;
+    for float in floating.in_draw_order() {
+        let Some(buffer) = buffers.get(&float.buffer) else {
+            continue;
+        };
+        let outer = clip(float.window, area);
+        if outer.start.0 == outer.end.0 || outer.start.1 == outer.end.1 {
+            continue;
+        }
+
+        for row in outer.start.1..outer.end.1 {
+            // Wide graphemes cut by an edge would spill into the float.
+            if grid
+                .get(outer.start.0, row)
+                .is_some_and(|cell| cell.is_continuation())
+            {
+                grid.set(outer.start.0 - 1, row, Cell::default());
+            }
+            if grid
+                .get(outer.end.0, row)
+                .is_some_and(|cell| cell.is_continuation())
+            {
+                grid.set(outer.end.0, row, Cell::default());
+            }
+            for column in outer.start.0..outer.end.0 {
+                grid.set(column, row, Cell::new(" ", normal));
+            }
+        }
+        if float.border && outer == float.window && outer.end.0 - outer.start.0 >= 2 {
+            draw_border(
+                grid,
+                outer,
+                float.title.as_deref(),
+                theme.apply("FloatBorder", normal),
+                normal,
+            );
+        }
+
+        let focused = floating.focus.as_deref() == Some(float.buffer.as_str());
+        let cursors: &[EditorCursor] = if focused { &buffer.cursors } else { &[] };
+        let text = clip(float.text_window(), area);
+        for (i, row) in (text.start.1..text.end.1).enumerate() {
+            let line_idx = i + buffer.pivot.1 as usize;
+            if line_idx >= buffer.content.len_lines() {
+                break;
+            }
+            let cursor = draw_line(
+                grid,
+                buffer,
+                (line_idx, row),
+                text,
+                buffer.pivot.0 as usize,
+                &LineStyles::new(
+                    theme,
+                    normal,
+                    buffer
+                        .colors
+                        .get(line_idx)
+                        .map(Vec::as_slice)
+                        .unwrap_or(&[]),
+                ),
+                cursors,
+            );
+            if focused {
+                focused_cursor = focused_cursor.or(cursor);
+            }
+        }
+
+        if terminal_cursor
+            .as_ref()
+            .is_some_and(|cursor| float.contains(cursor.position))
+        {
+            *terminal_cursor = None;
+        }
+    }
+
+    if floating.focus.is_some() {
+        *terminal_cursor = focused_cursor;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::editor::{EditorBuffer, EditorWindow, HandleKeysFn};
+    use crate::editor_modes::EditorMode;
+    use crate::layout::floating::FloatingWindow;
+    use crate::testing::{buffer_text, cursor, editor_with_text, type_keys};
+
+    #[test]
+    fn insert_and_leave_insert_mode() {
+        let (mut editor, _) = editor_with_text("insert_and_leave_insert_mode", "bar");
+        type_keys(&mut editor, "ifoo");
+        assert_eq!(editor.editor_mode, EditorMode::Insert);
+        type_keys(&mut editor, "<Esc>");
+        assert_eq!(editor.editor_mode, EditorMode::Normal);
+        assert_eq!(buffer_text(&editor), "foobar");
+    }
+
+    #[test]
+    fn counts_and_operators() {
+        let (mut editor, _) = editor_with_text("counts_and_operators", "one two three\nl2\nl3");
+        type_keys(&mut editor, "2dw");
+        assert_eq!(buffer_text(&editor), "three\nl2\nl3");
+        type_keys(&mut editor, "jdd");
+        assert_eq!(buffer_text(&editor), "three\nl3");
+        type_keys(&mut editor, "u");
+        assert_eq!(buffer_text(&editor), "three\nl2\nl3");
+        type_keys(&mut editor, "<C-r>");
+        assert_eq!(buffer_text(&editor), "three\nl3");
+        type_keys(&mut editor, "ggyyp");
+        assert_eq!(buffer_text(&editor), "three\nthree\nl3");
+        type_keys(&mut editor, "2>>");
+        assert_eq!(buffer_text(&editor), "three\n    three\n    l3");
+    }
+
+    #[test]
+    fn counts_move_and_saturate() {
+        let (mut editor, _) = editor_with_text("counts_move_and_saturate", "abcdef\nx\ny");
+        type_keys(&mut editor, "3l");
+        assert_eq!(cursor(&editor), (3, 0));
+        type_keys(&mut editor, "100l");
+        let line_end = cursor(&editor);
+        type_keys(&mut editor, "099999999999999999999999l");
+        assert_eq!(cursor(&editor), line_end);
+        type_keys(&mut editor, "99999999999999999999999j");
+        assert_eq!(cursor(&editor).1, 2);
+        assert!(editor.pending_command.is_empty());
+    }
+
+    #[test]
+    fn change_is_one_undo_step() {
+        let (mut editor, _) = editor_with_text("change_is_one_undo_step", "one two");
+        type_keys(&mut editor, "ciwxyz<Esc>");
+        assert_eq!(buffer_text(&editor), "xyz two");
+        assert_eq!(editor.editor_mode, EditorMode::Normal);
+        type_keys(&mut editor, "u");
+        assert_eq!(buffer_text(&editor), "one two");
+    }
+
+    #[test]
+    fn escape_cancels_pending_commands_and_prompts() {
+        let (mut editor, _) = editor_with_text("escape_cancels", "abc def");
+        type_keys(&mut editor, "2d<Esc>");
+        assert!(editor.pending_command.is_empty());
+        type_keys(&mut editor, "l");
+        assert_eq!(buffer_text(&editor), "abc def");
+        assert_eq!(cursor(&editor), (1, 0));
+
+        for prompt in [":", "/", "?"] {
+            type_keys(&mut editor, &format!("{}dd", prompt));
+            assert_eq!(editor.editor_mode, EditorMode::Command);
+            type_keys(&mut editor, "<Esc>");
+            assert_eq!(editor.editor_mode, EditorMode::Normal);
+        }
+        type_keys(&mut editor, "vl<Esc>");
+        assert_eq!(editor.editor_mode, EditorMode::Normal);
+        assert_eq!(buffer_text(&editor), "abc def");
+    }
+
+    #[test]
+    fn visual_search_and_ex_commands() {
+        let (mut editor, _) = editor_with_text("visual_search_and_ex_commands", "abc def\nghi");
+        type_keys(&mut editor, "vld");
+        assert_eq!(buffer_text(&editor), "c def\nghi");
+        assert_eq!(editor.editor_mode, EditorMode::Normal);
+
+        type_keys(&mut editor, "/def<Enter>");
+        assert_eq!(editor.editor_mode, EditorMode::Normal);
+        assert_eq!(cursor(&editor), (2, 0));
+
+        type_keys(&mut editor, ":%s/[a-z]+/x/g<Enter>");
+        assert_eq!(buffer_text(&editor), "x x\nx");
+    }
+
+    #[test]
+    fn focused_float_gives_keys_back_on_ctrl_c() {
+        let (mut editor, _) = editor_with_text("focused_float_gives_keys_back", "abc\ndef");
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let keys = Arc::clone(&received);
+        let handle_keys: HandleKeysFn = Arc::new(move |_: &mut super::Editor, key: &str| {
+            keys.lock().unwrap().push(key.to_string())
+        });
+        let window = EditorWindow {
+            start: (10, 5),
+            end: (30, 10),
+        };
+        editor.add_buffer(
+            "test:float".to_string(),
+            EditorBuffer::new(handle_keys, window, 4),
+        );
+        editor.floating.open(FloatingWindow {
+            buffer: "test:float".to_string(),
+            window,
+            z_index: 0,
+            border: false,
+            title: None,
+        });
+        editor.floating.focus = Some("test:float".to_string());
 
-        let stdout = &mut editor.stdout;
-        write!(stdout, "{}", render_buffer).unwrap();
+        type_keys(&mut editor, "dd<Esc>:q<Enter>");
+        assert_eq!(
+            *received.lock().unwrap(),
+            ["<d>", "<d>", "<Esc>", "<:>", "<q>", "<Enter>"]
+        );
+        assert!(!editor.close);
+        assert_eq!(buffer_text(&editor), "abc\ndef");
 
-        stdout.flush().unwrap();
+        type_keys(&mut editor, "<C-c>dd");
+        assert_eq!(editor.floating.focus, None);
+        assert!(editor.floating.get("test:float").is_some());
+        assert_eq!(received.lock().unwrap().len(), 6);
+        assert_eq!(buffer_text(&editor), "def");
     }
 }
@@ -3,7 +3,7 @@ use std::fmt;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
-use crate::{command::match_keys_command, editor::Editor};
+use crate::{command::match_keys_command, editor::Editor, layout::match_keys_window};
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, TS)]
 #[ts(export)]
@@ -37,6 +37,7 @@ impl ExecuteKey for EditorMode {
             EditorMode::Command => {
                 match_keys_command(editor, key);
             }
+            EditorMode::Normal if match_keys_window(editor, key) => {}
             _ => {
                 editor.invoke_buffer_handler(key);
             }
@@ -0,0 +1,131 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{chown, MetadataExt};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod swap;
+
+// What the file looked like on disk when the buffer last read or wrote it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FileStamp {
+    pub modified: SystemTime,
+    pub len: u64,
+}
+
+impl FileStamp {
+    pub fn of(path: &str) -> Option<FileStamp> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(FileStamp {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
+    }
+
+    pub fn seconds(&self) -> u64 {
+        self.modified
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+// Names for the same file normalize to the same path: relative ones are
+// taken from `root`, `.` and `..` are resolved and, once the file exists,
+// symlinks too.
+pub fn normalize_path(root: &Path, path: &str) -> PathBuf {
+    let path = root.join(path);
+    if let Ok(canonical) = fs::canonicalize(&path) {
+        return canonical;
+    }
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// Writes `bytes` next to `path` and renames it into place, so a crash leaves
+// either the old or the new file, never a truncated one.
+pub fn write_atomic(path: impl AsRef<Path>, bytes: &[u8]) -> io::Result<()> {
+    // Write through symlinks instead of replacing them with a regular file.
+    let path = path.as_ref();
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file name"))?;
+    let temp = dir.join(format!(
+        ".{}.rustpen-{}.tmp",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+
+    let result = write_temp(&temp, &target, bytes).and_then(|_| fs::rename(&temp, &target));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+
+    // Persist the rename itself.
+    if let Ok(dir) = File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn write_temp(temp: &Path, target: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(temp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+
+    if let Ok(metadata) = fs::metadata(target) {
+        fs::set_permissions(temp, metadata.permissions())?;
+        // Only root can give the file away, keeping our own ownership is fine.
+        let _ = chown(temp, Some(metadata.uid()), Some(metadata.gid()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    use super::*;
+    use crate::testing::temp_dir;
+
+    #[test]
+    fn atomic_writes_replace_the_file() {
+        let dir = temp_dir("atomic_writes_replace_the_file");
+        let path = dir.join("file.txt");
+        fs::write(&path, "old\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        write_atomic(&path, b"new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        // No temp file is left behind.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // Symlinks stay links, the file behind them is written.
+        let link = dir.join("link.txt");
+        symlink(&path, &link).unwrap();
+        write_atomic(&link, b"linked\n").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "linked\n");
+
+        assert!(write_atomic(dir.join("missing/file.txt"), b"x").is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    }
+}
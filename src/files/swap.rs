@@ -0,0 +1,295 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::files::{write_atomic, FileStamp};
+use crate::undo::EditOperation;
+
+const SWAP_VERSION: u32 = 2;
+
+// Pending edits reach the swap file at most this long after they were made.
+pub const SWAP_INTERVAL: Duration = Duration::from_secs(4);
+
+// Past this many edits the journal is folded into a snapshot of the text.
+pub const JOURNAL_LIMIT: usize = 1000;
+
+// Edits made since the buffer last matched the file on disk, or `snapshot`
+// once compacted. Replaying them over that text gives back the buffer.
+#[derive(Clone)]
+pub struct Journal {
+    pub operations: Vec<EditOperation>,
+    pub snapshot: Option<String>,
+    synced: usize,
+    // Whether the swap file holds the header and `synced` operations, so new
+    // ones can be appended.
+    written: bool,
+    written_at: Instant,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Journal {
+            operations: vec![],
+            snapshot: None,
+            synced: 0,
+            written: false,
+            written_at: Instant::now(),
+        }
+    }
+}
+
+impl Journal {
+    pub fn push(&mut self, operation: EditOperation) {
+        self.operations.push(operation);
+    }
+
+    pub fn clear(&mut self) {
+        *self = Journal::default();
+    }
+
+    pub fn needs_sync(&self, force: bool) -> bool {
+        self.synced != self.operations.len()
+            && (force || self.written_at.elapsed() >= SWAP_INTERVAL)
+    }
+
+    pub fn needs_compaction(&self) -> bool {
+        self.operations.len() > JOURNAL_LIMIT
+    }
+
+    // Replaces every operation with `text`, the buffer they produced.
+    pub fn compact(&mut self, text: String) {
+        self.operations.clear();
+        self.snapshot = Some(text);
+        self.synced = 0;
+        self.written = false;
+    }
+
+    // Brings the swap at `path` up to date, appending only the operations it
+    // doesn't have yet.
+    pub fn sync(&mut self, path: &Path, file_name: &str, base: Option<FileStamp>) {
+        let pending = &self.operations[self.synced..];
+        let appended = self.written && append_journal(path, pending).is_ok();
+        let written = appended || {
+            let mut swap = SwapFile::new(file_name, base, &self.operations);
+            swap.snapshot = self.snapshot.clone();
+            swap.write(path).is_ok()
+        };
+        if written {
+            self.synced = self.operations.len();
+            self.written = true;
+            self.written_at = Instant::now();
+        }
+    }
+}
+
+// The first line of a swap file is this header as JSON, followed by one line
+// per operation of the journal.
+#[derive(Serialize, Deserialize)]
+pub struct SwapFile {
+    pub version: u32,
+    pub file_name: String,
+    pub pid: u32,
+    // The file the journal applies to, as its mtime in seconds and its length.
+    pub base_modified: u64,
+    pub base_len: u64,
+    // Text the journal applies to instead of the file, after a compaction.
+    pub snapshot: Option<String>,
+    #[serde(skip)]
+    pub journal: Vec<EditOperation>,
+}
+
+pub fn swap_dir() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("state")))
+        .map(|dir| dir.join("rustpen").join("swap"))
+}
+
+pub fn absolute_path(file_name: &str) -> String {
+    fs::canonicalize(file_name)
+        .or_else(|_| std::env::current_dir().map(|dir| dir.join(file_name)))
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or(file_name.to_string())
+}
+
+// Like vim, the swap of /a/b.txt is named %a%b.txt.swp.
+pub fn swap_path(file_name: &str) -> Option<PathBuf> {
+    let absolute = absolute_path(file_name);
+    Some(swap_dir()?.join(format!("{}.swp", absolute.replace('/', "%"))))
+}
+
+impl SwapFile {
+    pub fn new(file_name: &str, base: Option<FileStamp>, journal: &[EditOperation]) -> Self {
+        SwapFile {
+            version: SWAP_VERSION,
+            file_name: absolute_path(file_name),
+            pid: process::id(),
+            base_modified: base.map(|stamp| stamp.seconds()).unwrap_or(0),
+            base_len: base.map(|stamp| stamp.len).unwrap_or(0),
+            snapshot: None,
+            journal: journal.to_vec(),
+        }
+    }
+
+    pub fn read(path: &Path) -> io::Result<SwapFile> {
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+        let bytes = fs::read(path)?;
+        let mut lines = bytes.split(|&byte| byte == b'\n');
+        let mut swap: SwapFile =
+            serde_json::from_slice(lines.next().unwrap_or_default()).map_err(invalid)?;
+        if swap.version != SWAP_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown swap file version {}", swap.version),
+            ));
+        }
+        // A crash while appending leaves the last line cut short.
+        swap.journal = lines
+            .map_while(|line| serde_json::from_slice(line).ok())
+            .collect();
+        Ok(swap)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut bytes =
+            serde_json::to_vec(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        bytes.push(b'\n');
+        bytes.extend(journal_lines(&self.journal)?);
+        write_atomic(path, &bytes)
+    }
+
+    pub fn is_owner_running(&self) -> bool {
+        self.pid != process::id() && Path::new("/proc").join(self.pid.to_string()).exists()
+    }
+
+    // The journal may not apply cleanly if the file was written after it.
+    pub fn is_file_newer(&self) -> bool {
+        FileStamp::of(&self.file_name).is_some_and(|stamp| {
+            (stamp.seconds(), stamp.len) != (self.base_modified, self.base_len)
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let mut description = format!(
+            "{} change{}, process {}",
+            self.journal.len(),
+            if self.journal.len() == 1 { "" } else { "s" },
+            self.pid
+        );
+        if self.is_owner_running() {
+            description.push_str(" (still running)");
+        }
+        if self.is_file_newer() {
+            description.push_str(", file is newer");
+        }
+        description
+    }
+}
+
+fn journal_lines(operations: &[EditOperation]) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    for operation in operations {
+        serde_json::to_writer(&mut bytes, operation)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        bytes.push(b'\n');
+    }
+    Ok(bytes)
+}
+
+// Fails if the swap is gone, so the caller can write it again in full.
+fn append_journal(path: &Path, operations: &[EditOperation]) -> io::Result<()> {
+    let mut file = OpenOptions::new().append(true).open(path)?;
+    file.write_all(&journal_lines(operations)?)?;
+    file.sync_data()
+}
+
+pub fn list_swap_files() -> Vec<(PathBuf, SwapFile)> {
+    let Some(Ok(entries)) = swap_dir().map(fs::read_dir) else {
+        return vec![];
+    };
+
+    let mut swaps: Vec<(PathBuf, SwapFile)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "swp"))
+        .filter_map(|path| SwapFile::read(&path).ok().map(|swap| (path, swap)))
+        .collect();
+    swaps.sort_by(|a, b| a.1.file_name.cmp(&b.1.file_name));
+    swaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    fn insert(text: &str) -> EditOperation {
+        EditOperation::Insert {
+            position: (0, 0),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn journal_is_appended_to_the_swap() {
+        let dir = temp_dir("journal_is_appended_to_the_swap");
+        let file_name = dir.join("file.txt").to_string_lossy().to_string();
+        let path = dir.join("file.txt.swp");
+        let mut journal = Journal::default();
+
+        journal.push(insert("a"));
+        journal.sync(&path, &file_name, None);
+        let first = fs::read(&path).unwrap();
+        journal.push(insert("b"));
+        journal.push(insert("c"));
+        assert!(journal.needs_sync(true));
+        journal.sync(&path, &file_name, None);
+        assert!(!journal.needs_sync(true));
+
+        // What was there is left alone, the new edits go after it.
+        let second = fs::read(&path).unwrap();
+        assert!(second.starts_with(&first));
+        assert_eq!(second.iter().filter(|&&byte| byte == b'\n').count(), 4);
+        let swap = SwapFile::read(&path).unwrap();
+        assert_eq!(swap.file_name, file_name);
+        assert_eq!(swap.journal, vec![insert("a"), insert("b"), insert("c")]);
+
+        // A cut short last line is dropped, the rest is still read.
+        fs::write(&path, &second[..second.len() - 4]).unwrap();
+        assert_eq!(SwapFile::read(&path).unwrap().journal.len(), 2);
+
+        // A swap removed meanwhile is written again in full.
+        fs::remove_file(&path).unwrap();
+        journal.push(insert("d"));
+        journal.sync(&path, &file_name, None);
+        assert_eq!(SwapFile::read(&path).unwrap().journal.len(), 4);
+    }
+
+    #[test]
+    fn long_journals_become_a_snapshot() {
+        let dir = temp_dir("long_journals_become_a_snapshot");
+        let file_name = dir.join("file.txt").to_string_lossy().to_string();
+        let path = dir.join("file.txt.swp");
+        let mut journal = Journal::default();
+        for _ in 0..=JOURNAL_LIMIT {
+            journal.push(insert("x"));
+        }
+        assert!(journal.needs_compaction());
+
+        journal.compact("x".repeat(JOURNAL_LIMIT + 1));
+        assert!(!journal.needs_compaction());
+        journal.sync(&path, &file_name, None);
+        journal.push(insert("y"));
+        journal.sync(&path, &file_name, None);
+
+        let swap = SwapFile::read(&path).unwrap();
+        assert_eq!(swap.snapshot, Some("x".repeat(JOURNAL_LIMIT + 1)));
+        assert_eq!(swap.journal, vec![insert("y")]);
+    }
+}
@@ -1,25 +1,22 @@
-use std::usize;
-
 use crate::editor::EditorBuffer;
+use crate::text::to_coord;
 
 pub fn insert_chars_to_buffer(buffer: &mut EditorBuffer, chs: Vec<char>) {
-    for (idx, cursor) in buffer.cursors.iter_mut().enumerate() {
-        if let Some(line) = buffer.content.get_mut(cursor.position.1 as usize) {
-            let mut chars: Vec<char> = line.chars().collect();
-            let remaining_chars = chars.split_off(cursor.position.0 as usize);
-
-            chars.extend(chs.clone());
-            chars.extend(remaining_chars);
+    let text: String = chs.into_iter().collect();
 
-            *line = chars.into_iter().collect();
+    for idx in 0..buffer.cursors.len() {
+        let position = buffer.cursors[idx].position;
 
-            cursor.position.0 += chs.len() as u16;
+        if (position.1 as usize) < buffer.content.len_lines() {
+            let end = buffer.insert_text((position.1 as usize, position.0 as usize), &text);
+            buffer.cursors[idx].position.0 = to_coord(end.1);
         }
 
         let window_width = buffer.buffer_window.end.0 - buffer.buffer_window.start.0;
+        let cursor_x = buffer.display_col(buffer.cursors[idx].position);
 
-        if idx == 0 && cursor.position.0 >= window_width + 1 as u16 {
-            buffer.pivot.0 = cursor.position.0 - window_width - 1;
+        if idx == 0 && cursor_x > window_width {
+            buffer.pivot.0 = cursor_x - window_width - 1;
         }
     }
 }
@@ -0,0 +1,74 @@
+use crate::editor::EditorWindow;
+
+// Never sent to a focused float: it gives the keys back to the focused pane,
+// even when the plugin behind the float stopped answering.
+pub const UNFOCUS_KEY: &str = "<C-c>";
+
+// A buffer drawn above the panes, like a completion menu or a picker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatingWindow {
+    pub buffer: String,
+    // Outer cells, border included, as [start, end) in terminal coordinates.
+    pub window: EditorWindow,
+    // Higher ones are drawn above lower ones, ties by opening order.
+    pub z_index: i32,
+    pub border: bool,
+    pub title: Option<String>,
+}
+
+impl FloatingWindow {
+    pub fn text_window(&self) -> EditorWindow {
+        let inset = self.border as u16;
+        let start = (self.window.start.0 + inset, self.window.start.1 + inset);
+        EditorWindow {
+            start,
+            end: (
+                self.window.end.0.saturating_sub(inset).max(start.0),
+                self.window.end.1.saturating_sub(inset).max(start.1),
+            ),
+        }
+    }
+
+    pub fn contains(&self, position: (u16, u16)) -> bool {
+        (self.window.start.0..self.window.end.0).contains(&position.0)
+            && (self.window.start.1..self.window.end.1).contains(&position.1)
+    }
+}
+
+#[derive(Default)]
+pub struct FloatingWindows {
+    windows: Vec<FloatingWindow>,
+    // The float receiving keys instead of the focused pane.
+    pub focus: Option<String>,
+}
+
+impl FloatingWindows {
+    // Opening a buffer that already floats moves it instead.
+    pub fn open(&mut self, float: FloatingWindow) {
+        match self
+            .windows
+            .iter_mut()
+            .find(|window| window.buffer == float.buffer)
+        {
+            Some(window) => *window = float,
+            None => self.windows.push(float),
+        }
+    }
+
+    pub fn close(&mut self, buffer: &str) {
+        self.windows.retain(|window| window.buffer != buffer);
+        if self.focus.as_deref() == Some(buffer) {
+            self.focus = None;
+        }
+    }
+
+    pub fn get(&self, buffer: &str) -> Option<&FloatingWindow> {
+        self.windows.iter().find(|window| window.buffer == buffer)
+    }
+
+    pub fn in_draw_order(&self) -> Vec<&FloatingWindow> {
+        let mut windows: Vec<&FloatingWindow> = self.windows.iter().collect();
+        windows.sort_by_key(|window| window.z_index);
+        windows
+    }
+}
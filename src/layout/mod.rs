@@ -0,0 +1,672 @@
+use std::collections::HashMap;
+
+use crate::{
+    buffers::sync_focus_buffer_lines,
+    command::{error_message, push_message, recovery::recovery_prompt_open},
+    editor::{Editor, EditorCursor, EditorWindow},
+    editor_modes::EditorMode,
+};
+
+pub mod floating;
+
+// Columns every pane reserves on its left for line numbers.
+pub const GUTTER_WIDTH: u16 = 8;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SplitAxis {
+    // Panes stacked on top of each other, as `:split` creates them.
+    Horizontal,
+    // Panes side by side, as `:vsplit` creates them.
+    Vertical,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PaneDirection {
+    Left,
+    Down,
+    Up,
+    Right,
+}
+
+#[derive(Clone, Debug)]
+enum LayoutNode {
+    Pane(usize),
+    Split {
+        axis: SplitAxis,
+        // Share of the available cells given to `first`.
+        ratio: f32,
+        first: Box<LayoutNode>,
+        second: Box<LayoutNode>,
+    },
+}
+
+// A view into a buffer. The focused pane keeps its cursors and pivot in the
+// `EditorBuffer` itself, so the stored ones are only current for the others.
+#[derive(Clone)]
+pub struct Pane {
+    pub buffer: String,
+    pub cursors: Vec<EditorCursor>,
+    pub pivot: (u16, u16),
+}
+
+// Every pane with the window it covers, plus the separators between them.
+pub struct ComputedLayout {
+    pub panes: Vec<(usize, EditorWindow)>,
+    pub separators: Vec<(SplitAxis, EditorWindow)>,
+}
+
+#[derive(Clone)]
+pub struct Layout {
+    root: LayoutNode,
+    pub panes: HashMap<usize, Pane>,
+    pub focus: usize,
+    last_id: usize,
+    pub window_prefix: bool,
+}
+
+fn split_window(
+    window: EditorWindow,
+    axis: SplitAxis,
+    ratio: f32,
+) -> (EditorWindow, EditorWindow, EditorWindow) {
+    let (start, end) = match axis {
+        SplitAxis::Horizontal => (window.start.1, window.end.1),
+        SplitAxis::Vertical => (window.start.0, window.end.0),
+    };
+    // One cell is left for the separator between both halves. A window shrunk
+    // by a resize below three cells leaves the second half, or both, empty.
+    let available = end.saturating_sub(start).saturating_sub(1);
+    let first_size = ((available as f32 * ratio).round() as u16).clamp(1, available.max(2) - 1);
+    let separator = (start + first_size).min(end);
+    let second = (separator + 1).min(end);
+
+    match axis {
+        SplitAxis::Horizontal => (
+            EditorWindow {
+                start: window.start,
+                end: (window.end.0, separator),
+            },
+            EditorWindow {
+                start: (window.start.0, separator),
+                end: (window.end.0, second),
+            },
+            EditorWindow {
+                start: (window.start.0, second),
+                end: window.end,
+            },
+        ),
+        SplitAxis::Vertical => (
+            EditorWindow {
+                start: window.start,
+                end: (separator, window.end.1),
+            },
+            EditorWindow {
+                start: (separator, window.start.1),
+                end: (second, window.end.1),
+            },
+            EditorWindow {
+                start: (second, window.start.1),
+                end: window.end,
+            },
+        ),
+    }
+}
+
+impl LayoutNode {
+    fn contains(&self, id: usize) -> bool {
+        match self {
+            LayoutNode::Pane(pane) => *pane == id,
+            LayoutNode::Split { first, second, .. } => first.contains(id) || second.contains(id),
+        }
+    }
+
+    fn first_pane(&self) -> usize {
+        match self {
+            LayoutNode::Pane(pane) => *pane,
+            LayoutNode::Split { first, .. } => first.first_pane(),
+        }
+    }
+
+    fn compute(
+        &self,
+        window: EditorWindow,
+        panes: &mut Vec<(usize, EditorWindow)>,
+        separators: &mut Vec<(SplitAxis, EditorWindow)>,
+    ) {
+        match self {
+            LayoutNode::Pane(pane) => panes.push((*pane, window)),
+            LayoutNode::Split {
+                axis,
+                ratio,
+                first,
+                second,
+            } => {
+                let (first_window, separator, second_window) = split_window(window, *axis, *ratio);
+                first.compute(first_window, panes, separators);
+                separators.push((*axis, separator));
+                second.compute(second_window, panes, separators);
+            }
+        }
+    }
+
+    fn split(&mut self, id: usize, new_id: usize, axis: SplitAxis) {
+        match self {
+            LayoutNode::Pane(pane) if *pane == id => {
+                *self = LayoutNode::Split {
+                    axis,
+                    ratio: 0.5,
+                    first: Box::new(LayoutNode::Pane(new_id)),
+                    second: Box::new(LayoutNode::Pane(id)),
+                };
+            }
+            LayoutNode::Pane(_) => {}
+            LayoutNode::Split { first, second, .. } => {
+                first.split(id, new_id, axis);
+                second.split(id, new_id, axis);
+            }
+        }
+    }
+
+    // Replaces the split holding `id` with its other child.
+    fn remove(&mut self, id: usize) {
+        if let LayoutNode::Split { first, second, .. } = self {
+            if matches!(**first, LayoutNode::Pane(pane) if pane == id) {
+                *self = (**second).clone();
+            } else if matches!(**second, LayoutNode::Pane(pane) if pane == id) {
+                *self = (**first).clone();
+            } else {
+                first.remove(id);
+                second.remove(id);
+            }
+        }
+    }
+
+    // Grows (or shrinks) pane `id` by `delta` cells along `axis`, using the
+    // innermost split that can do it. Returns whether one was found.
+    fn resize(&mut self, window: EditorWindow, id: usize, axis: SplitAxis, delta: i32) -> bool {
+        let LayoutNode::Split {
+            axis: split_axis,
+            ratio,
+            first,
+            second,
+        } = self
+        else {
+            return false;
+        };
+
+        let (first_window, _, second_window) = split_window(window, *split_axis, *ratio);
+        let in_first = first.contains(id);
+        if in_first && first.resize(first_window, id, axis, delta) {
+            return true;
+        }
+        if !in_first && second.contains(id) && second.resize(second_window, id, axis, delta) {
+            return true;
+        }
+        if *split_axis != axis || !(in_first || second.contains(id)) {
+            return false;
+        }
+
+        let (first_size, available) = match axis {
+            SplitAxis::Horizontal => (
+                first_window.end.1 - first_window.start.1,
+                window.end.1.saturating_sub(window.start.1 + 1),
+            ),
+            SplitAxis::Vertical => (
+                first_window.end.0 - first_window.start.0,
+                window.end.0.saturating_sub(window.start.0 + 1),
+            ),
+        };
+        if available >= 2 {
+            let delta = if in_first { delta } else { -delta };
+            let new_size = (first_size as i32 + delta).clamp(1, available as i32 - 1);
+            *ratio = new_size as f32 / available as f32;
+        }
+        true
+    }
+
+    fn pane_ids(&self, ids: &mut Vec<usize>) {
+        match self {
+            LayoutNode::Pane(pane) => ids.push(*pane),
+            LayoutNode::Split { first, second, .. } => {
+                first.pane_ids(ids);
+                second.pane_ids(ids);
+            }
+        }
+    }
+
+    fn equalize(&mut self) {
+        if let LayoutNode::Split {
+            ratio,
+            first,
+            second,
+            ..
+        } = self
+        {
+            *ratio = 0.5;
+            first.equalize();
+            second.equalize();
+        }
+    }
+}
+
+impl Layout {
+    pub fn new(buffer: &str) -> Layout {
+        let mut panes = HashMap::new();
+        panes.insert(
+            0,
+            Pane {
+                buffer: buffer.to_string(),
+                cursors: vec![],
+                pivot: (0, 0),
+            },
+        );
+
+        Layout {
+            root: LayoutNode::Pane(0),
+            panes,
+            focus: 0,
+            last_id: 0,
+            window_prefix: false,
+        }
+    }
+
+    // Area left for panes: everything but the status line.
+    pub fn area(terminal_size: (u16, u16)) -> EditorWindow {
+        EditorWindow {
+            start: (1, 1),
+            end: (
+                terminal_size.0.max(1),
+                terminal_size.1.saturating_sub(1).max(1),
+            ),
+        }
+    }
+
+    pub fn compute(&self, terminal_size: (u16, u16)) -> ComputedLayout {
+        let mut computed = ComputedLayout {
+            panes: vec![],
+            separators: vec![],
+        };
+        self.root.compute(
+            Layout::area(terminal_size),
+            &mut computed.panes,
+            &mut computed.separators,
+        );
+        computed
+    }
+
+    pub fn pane_window(&self, id: usize, terminal_size: (u16, u16)) -> Option<EditorWindow> {
+        self.compute(terminal_size)
+            .panes
+            .into_iter()
+            .find(|(pane, _)| *pane == id)
+            .map(|(_, window)| window)
+    }
+
+    pub fn pane_count(&self) -> usize {
+        self.panes.len()
+    }
+
+    pub fn buffers(&self) -> Vec<String> {
+        let mut ids = vec![];
+        self.root.pane_ids(&mut ids);
+
+        let mut buffers: Vec<String> = vec![];
+        for id in ids {
+            if let Some(pane) = self.panes.get(&id) {
+                if !buffers.contains(&pane.buffer) {
+                    buffers.push(pane.buffer.clone());
+                }
+            }
+        }
+        buffers
+    }
+}
+
+// The part of a pane window to the right of its gutter.
+pub fn text_window(window: EditorWindow) -> EditorWindow {
+    EditorWindow {
+        start: (
+            (window.start.0 + GUTTER_WIDTH).min(window.end.0),
+            window.start.1,
+        ),
+        end: window.end,
+    }
+}
+
+fn store_focused_view(editor: &mut Editor) {
+    let focus = editor.layout.focus;
+    let Some(buffer) = editor.buffers.get(&editor.focus_buffer) else {
+        return;
+    };
+    let (cursors, pivot) = (buffer.cursors.clone(), buffer.pivot);
+
+    if let Some(pane) = editor.layout.panes.get_mut(&focus) {
+        pane.cursors = cursors;
+        pane.pivot = pivot;
+    }
+}
+
+fn load_focused_view(editor: &mut Editor) {
+    let Some(pane) = editor.layout.panes.get(&editor.layout.focus).cloned() else {
+        return;
+    };
+    editor.focus_buffer = pane.buffer.clone();
+
+    if let Some(buffer) = editor.buffers.get_mut(&pane.buffer) {
+        if !pane.cursors.is_empty() {
+            buffer.cursors = pane.cursors;
+            buffer.pivot = pane.pivot;
+        }
+
+        // Another pane may have shortened the buffer meanwhile.
+        let last_line = buffer.content.len_lines().saturating_sub(1);
+        for cursor in buffer.cursors.iter_mut() {
+            let line = (cursor.position.1 as usize).min(last_line);
+            let col = (cursor.position.0 as usize).min(buffer.content.line_len(line));
+            cursor.position = (col as u16, line as u16);
+        }
+    }
+}
+
+// Keeps `buffers_to_show` and the focused buffer's window in line with the
+// layout after it changes.
+pub fn sync_layout(editor: &mut Editor) {
+    editor.buffers_to_show = editor.layout.buffers();
+
+    let window = editor
+        .layout
+        .pane_window(editor.layout.focus, editor.terminal_size);
+    let focus_buffer = editor.focus_buffer.clone();
+    if let (Some(window), Some(buffer)) = (window, editor.buffers.get_mut(&focus_buffer)) {
+        buffer.buffer_window = text_window(window);
+    }
+}
+
+pub fn focus_pane(editor: &mut Editor, id: usize) {
+    if !editor.layout.panes.contains_key(&id) || id == editor.layout.focus {
+        return;
+    }
+
+    store_focused_view(editor);
+    editor.layout.focus = id;
+    load_focused_view(editor);
+    editor.editor_mode = EditorMode::Normal;
+    sync_layout(editor);
+    sync_focus_buffer_lines(editor);
+}
+
+// Shows buffer `key` in the focused pane.
+pub fn show_buffer(editor: &mut Editor, key: &str) {
+    let focus = editor.layout.focus;
+    if let Some(pane) = editor.layout.panes.get_mut(&focus) {
+        pane.buffer = key.to_string();
+        pane.cursors = vec![];
+    }
+    editor.focus_buffer = key.to_string();
+    // Opening the buffer may have asked about its swap file.
+    if !recovery_prompt_open(editor) {
+        editor.editor_mode = EditorMode::Normal;
+    }
+    sync_layout(editor);
+    sync_focus_buffer_lines(editor);
+}
+
+// Points every pane showing `key` at `replacement` instead.
+pub fn replace_buffer(editor: &mut Editor, key: &str, replacement: &str) {
+    for pane in editor.layout.panes.values_mut() {
+        if pane.buffer == key {
+            pane.buffer = replacement.to_string();
+            pane.cursors = vec![];
+        }
+    }
+    if editor.focus_buffer == key {
+        show_buffer(editor, replacement);
+    } else {
+        sync_layout(editor);
+    }
+}
+
+// Both halves need a row, or a column, besides the separator.
+const MIN_SPLIT_SIZE: u16 = 3;
+
+pub fn split_pane(editor: &mut Editor, axis: SplitAxis) -> Result<(), String> {
+    let focus = editor.layout.focus;
+    let Some(pane) = editor.layout.panes.get(&focus).cloned() else {
+        return Ok(());
+    };
+    let window = editor
+        .layout
+        .pane_window(focus, editor.terminal_size)
+        .ok_or("No window to split")?;
+    let size = match axis {
+        SplitAxis::Horizontal => window.end.1 - window.start.1,
+        SplitAxis::Vertical => window.end.0 - window.start.0,
+    };
+    if size < MIN_SPLIT_SIZE {
+        return Err("Not enough room".to_string());
+    }
+
+    store_focused_view(editor);
+    editor.layout.last_id += 1;
+    let new_id = editor.layout.last_id;
+
+    editor.layout.root.split(focus, new_id, axis);
+    editor.layout.panes.insert(new_id, pane);
+    editor.layout.focus = new_id;
+    sync_layout(editor);
+    sync_focus_buffer_lines(editor);
+    Ok(())
+}
+
+pub fn close_pane(editor: &mut Editor, id: usize) -> Result<(), String> {
+    if editor.layout.pane_count() <= 1 {
+        return Err("Cannot close last window".to_string());
+    }
+
+    editor.layout.root.remove(id);
+    editor.layout.panes.remove(&id);
+
+    if editor.layout.focus == id {
+        editor.layout.focus = editor.layout.root.first_pane();
+        load_focused_view(editor);
+    }
+    editor.editor_mode = EditorMode::Normal;
+    sync_layout(editor);
+    sync_focus_buffer_lines(editor);
+    Ok(())
+}
+
+pub fn only_pane(editor: &mut Editor) {
+    let focus = editor.layout.focus;
+    editor.layout.root = LayoutNode::Pane(focus);
+    editor.layout.panes.retain(|id, _| *id == focus);
+    sync_layout(editor);
+    sync_focus_buffer_lines(editor);
+}
+
+pub fn resize_pane(editor: &mut Editor, axis: SplitAxis, delta: i32) {
+    let area = Layout::area(editor.terminal_size);
+    let focus = editor.layout.focus;
+    editor.layout.root.resize(area, focus, axis, delta);
+    sync_layout(editor);
+    sync_focus_buffer_lines(editor);
+}
+
+pub fn equalize_panes(editor: &mut Editor) {
+    editor.layout.root.equalize();
+    sync_layout(editor);
+    sync_focus_buffer_lines(editor);
+}
+
+// The closest pane in `direction` that overlaps the focused one, preferring
+// the one next to the cursor.
+fn neighbour(editor: &Editor, direction: PaneDirection) -> Option<usize> {
+    let panes = editor.layout.compute(editor.terminal_size).panes;
+    let current = panes
+        .iter()
+        .find(|(id, _)| *id == editor.layout.focus)
+        .map(|(_, window)| *window)?;
+
+    let cursor = editor
+        .buffers
+        .get(&editor.focus_buffer)
+        .and_then(|buffer| {
+            let cursor = buffer.cursors.first()?.position;
+            Some((
+                buffer.buffer_window.start.0
+                    + buffer.display_col(cursor).saturating_sub(buffer.pivot.0),
+                buffer.buffer_window.start.1 + cursor.1.saturating_sub(buffer.pivot.1),
+            ))
+        })
+        .unwrap_or(current.start);
+
+    panes
+        .iter()
+        .filter(|(id, _)| *id != editor.layout.focus)
+        .filter_map(|(id, window)| {
+            let overlaps_rows = window.start.1 < current.end.1 && current.start.1 < window.end.1;
+            let overlaps_cols = window.start.0 < current.end.0 && current.start.0 < window.end.0;
+            let (distance, along_cursor) = match direction {
+                PaneDirection::Left if overlaps_rows && window.end.0 <= current.start.0 => (
+                    current.start.0 - window.end.0,
+                    window.start.1 <= cursor.1 && cursor.1 < window.end.1,
+                ),
+                PaneDirection::Right if overlaps_rows && window.start.0 >= current.end.0 => (
+                    window.start.0 - current.end.0,
+                    window.start.1 <= cursor.1 && cursor.1 < window.end.1,
+                ),
+                PaneDirection::Up if overlaps_cols && window.end.1 <= current.start.1 => (
+                    current.start.1 - window.end.1,
+                    window.start.0 <= cursor.0 && cursor.0 < window.end.0,
+                ),
+                PaneDirection::Down if overlaps_cols && window.start.1 >= current.end.1 => (
+                    window.start.1 - current.end.1,
+                    window.start.0 <= cursor.0 && cursor.0 < window.end.0,
+                ),
+                _ => return None,
+            };
+            Some((distance, !along_cursor, *id))
+        })
+        .min()
+        .map(|(_, _, id)| id)
+}
+
+fn cycle_pane(editor: &mut Editor, forward: bool) {
+    let panes = editor.layout.compute(editor.terminal_size).panes;
+    let Some(idx) = panes.iter().position(|(id, _)| *id == editor.layout.focus) else {
+        return;
+    };
+    let next = if forward {
+        (idx + 1) % panes.len()
+    } else {
+        (idx + panes.len() - 1) % panes.len()
+    };
+    focus_pane(editor, panes[next].0);
+}
+
+// `<C-w>` followed by a window command. Returns whether the key was used.
+pub fn match_keys_window(editor: &mut Editor, key: &str) -> bool {
+    if !editor.layout.window_prefix {
+        if key == "<C-w>" {
+            editor.layout.window_prefix = true;
+            return true;
+        }
+        return false;
+    }
+    editor.layout.window_prefix = false;
+
+    let direction = match key {
+        "<h>" | "<Left>" | "<C-h>" => Some(PaneDirection::Left),
+        "<j>" | "<Down>" | "<C-j>" => Some(PaneDirection::Down),
+        "<k>" | "<Up>" | "<C-k>" => Some(PaneDirection::Up),
+        "<l>" | "<Right>" | "<C-l>" => Some(PaneDirection::Right),
+        _ => None,
+    };
+    if let Some(direction) = direction {
+        if let Some(id) = neighbour(editor, direction) {
+            focus_pane(editor, id);
+        }
+        return true;
+    }
+
+    match key {
+        "<w>" | "<C-w>" => cycle_pane(editor, true),
+        "<W>" => cycle_pane(editor, false),
+        "<s>" | "<S>" | "<C-s>" => {
+            if let Err(message) = split_pane(editor, SplitAxis::Horizontal) {
+                push_message(editor, error_message(&message));
+            }
+        }
+        "<v>" | "<C-v>" => {
+            if let Err(message) = split_pane(editor, SplitAxis::Vertical) {
+                push_message(editor, error_message(&message));
+            }
+        }
+        "<c>" | "<q>" => {
+            let focus = editor.layout.focus;
+            if let Err(message) = close_pane(editor, focus) {
+                push_message(editor, error_message(&message));
+            }
+        }
+        "<o>" => only_pane(editor),
+        "<+>" => resize_pane(editor, SplitAxis::Horizontal, 1),
+        "<->" => resize_pane(editor, SplitAxis::Horizontal, -1),
+        "<>>" => resize_pane(editor, SplitAxis::Vertical, 1),
+        "<<>" => resize_pane(editor, SplitAxis::Vertical, -1),
+        "<=>" => equalize_panes(editor),
+        _ => {}
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{editor_with_text, type_keys};
+
+    fn assert_windows_valid(editor: &Editor, terminal_size: (u16, u16)) {
+        let computed = editor.layout.compute(terminal_size);
+        let separators = computed.separators.into_iter().map(|(_, window)| window);
+        for window in computed
+            .panes
+            .into_iter()
+            .map(|(_, window)| window)
+            .chain(separators)
+        {
+            assert!(window.start.0 <= window.end.0 && window.start.1 <= window.end.1);
+        }
+    }
+
+    #[test]
+    fn split_until_there_is_no_room() {
+        for (key, axis) in [("<s>", SplitAxis::Horizontal), ("<v>", SplitAxis::Vertical)] {
+            let (mut editor, _) = editor_with_text("split_until_there_is_no_room", "text\n");
+            let mut panes = 1;
+            loop {
+                type_keys(&mut editor, &format!("<C-w>{}", key));
+                editor.render(String::new());
+                assert_windows_valid(&editor, editor.terminal_size);
+                if editor.layout.pane_count() == panes {
+                    break;
+                }
+                panes = editor.layout.pane_count();
+            }
+            assert!(panes > 1);
+            assert!(split_pane(&mut editor, axis).is_err());
+            assert_eq!(editor.layout.pane_count(), panes);
+            assert!(editor
+                .commands_hist
+                .iter()
+                .any(|entry| entry.contains("Not enough room")));
+        }
+    }
+
+    #[test]
+    fn shrunk_terminal_keeps_windows_valid() {
+        let (mut editor, _) = editor_with_text("shrunk_terminal_keeps_windows_valid", "text\n");
+        type_keys(&mut editor, "<C-w>s<C-w>v<C-w>s");
+        for size in [(40, 6), (4, 3), (1, 2), (0, 1)] {
+            assert_windows_valid(&editor, size);
+        }
+    }
+}
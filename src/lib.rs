@@ -4,15 +4,31 @@ pub mod editor_modes;
 
 pub mod buffers;
 pub mod command;
+pub mod config;
+pub mod files;
 pub mod insert;
+pub mod layout;
 pub mod normal;
+pub mod operator;
+pub mod registers;
+pub mod screen;
+pub mod search;
+pub mod setup;
+pub mod text;
+pub mod theme;
+pub mod undo;
 pub mod visual;
 
 pub mod server;
 
+#[cfg(test)]
+pub mod testing;
+
 pub enum EditorMessage {
     Close,
     Render(String),
+    // A line for the log shown by `:messages`.
+    Log(String),
 }
 
 pub fn match_char_with_special_keys(c: &str, prefix: &str, suffix: &str) -> String {
@@ -25,6 +41,19 @@ pub fn match_char_with_special_keys(c: &str, prefix: &str, suffix: &str) -> Stri
     }
 }
 
+// Keys reach keymaps one at a time, each as a single `<...>` like `<a>`,
+// `<C-s>` or `<<>`.
+pub fn is_single_key(keys: &str) -> bool {
+    match keys
+        .strip_prefix('<')
+        .and_then(|keys| keys.strip_suffix('>'))
+    {
+        Some("<" | ">") => true,
+        Some(inner) => !inner.is_empty() && !inner.contains(['<', '>']),
+        None => false,
+    }
+}
+
 pub fn key_to_string(key: Key) -> String {
     match key {
         Key::Char(c) => match_char_with_special_keys(c.to_string().as_str(), "<", ">"),
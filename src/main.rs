@@ -1,18 +1,41 @@
-use rustpen::buffers::editor_buffer;
+use rustpen::buffers::buffer_list::open_file_buffer;
 use rustpen::buffers::explorer_buffer::init_explorer_buffer;
-use rustpen::editor::{ColorRange, Editor, EditorBuffer, EditorWindow, Rgb};
-use rustpen::server::UnixServer;
+use rustpen::command::{error_message, push_message};
+use rustpen::config::load_config;
+use rustpen::editor::Editor;
+use rustpen::files::swap::{list_swap_files, SWAP_INTERVAL};
+use rustpen::layout::{sync_layout, Layout};
+use rustpen::screen::backend::TermionBackend;
+use rustpen::server::hosts::{append_log, PluginHosts};
+use rustpen::server::{default_socket_path, UnixServer, SOCKET_ENV};
+use rustpen::setup::{doctor, setup};
+use rustpen::theme::Theme;
 use rustpen::{key_to_string, EditorMessage};
-use signal_hook::consts::SIGWINCH;
+use signal_hook::consts::{SIGHUP, SIGTERM, SIGWINCH};
 use signal_hook::iterator::Signals;
 use std::io::{stdin, stdout};
+use std::path::Path;
 use std::sync::{mpsc, Arc, Mutex};
-use std::time::Instant;
-use std::{env, thread};
+use std::{env, fs, process, thread};
 use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 use termion::screen::IntoAlternateScreen;
-use termion::terminal_size;
+
+fn list_recoverable_files() {
+    let swaps = list_swap_files();
+    if swaps.is_empty() {
+        println!("No swap files found");
+        return;
+    }
+    for (path, swap) in swaps {
+        println!(
+            "{}\n    {}\n    {}",
+            swap.file_name,
+            swap.describe(),
+            path.display()
+        );
+    }
+}
 
 fn init_editor() -> Result<Editor, ()> {
     let stdout = stdout()
@@ -23,99 +46,80 @@ fn init_editor() -> Result<Editor, ()> {
 
     let root = env::current_dir().unwrap().to_str().unwrap().to_string();
 
-    let mut editor = Editor::new(stdout, root.clone());
+    let mut editor = Editor::new(Box::new(TermionBackend::new(stdout)), root.clone());
+    // Read before any buffer is opened, since their options come from it.
+    let (config, mut errors) = load_config(Path::new(&root));
+    editor.hosts = PluginHosts::new(config.plugins.clone());
+    match Theme::load(&config.theme.colorscheme, &config.theme.groups) {
+        Ok(theme) => editor.theme = theme,
+        Err(e) => errors.push(e),
+    }
+    editor.config = config;
+    for error in errors {
+        push_message(&mut editor, error_message(&error));
+    }
     let args: Vec<String> = env::args().collect();
-    let terminal_size = editor.terminal_size.clone();
+    let terminal_size = editor.terminal_size;
 
     if args.len() < 2 {
-        eprintln!("Usage: {} <filename>", args[0]);
+        eprintln!("Usage: {} <filename>...", args[0]);
+        eprintln!("       {} -r", args[0]);
+        eprintln!("       {} setup [--install-deps]", args[0]);
+        eprintln!("       {} doctor", args[0]);
         return Err(());
     }
 
-    let filename = if args.len() > 1 {
-        Some(args[1].clone())
-    } else {
-        None
-    };
-
-    let main_buffer = if let Some(filename) = filename.clone() {
-        if filename == "." {
-            init_explorer_buffer(&root, terminal_size)
+    let mut focus_buffer = None;
+    for filename in args[1..].iter() {
+        let key = if filename == "." {
+            editor.add_buffer(
+                "explorer".to_string(),
+                init_explorer_buffer(&root, terminal_size, editor.config.editor.tab_width),
+            );
+            "explorer".to_string()
         } else {
-            EditorBuffer::from_file(
-                &filename,
-                Arc::new(|editor: &mut Editor, key: &str| {
-                    editor_buffer::match_editor_mode(editor, key)
-                }),
-                EditorWindow {
-                    start: (9, 1),
-                    end: (terminal_size.0, terminal_size.1 - 1),
-                },
-                4,
-            )
-        }
-    } else {
-        EditorBuffer::new(
-            Arc::new(|editor: &mut Editor, key: &str| {
-                editor_buffer::match_editor_mode(editor, key)
-            }),
-            EditorWindow {
-                start: (9, 1),
-                end: (terminal_size.0, terminal_size.1 - 1),
-            },
-            4,
-        )
-    };
-
-    let focus_buffer = match filename {
-        Some(filename) => match filename.as_str() {
-            "." => "explorer".to_string(),
-            _ => "main".to_string(),
-        },
-        None => "void".to_string(),
-    };
-
-    editor.add_buffer(focus_buffer.clone(), main_buffer.clone());
-
-    let mut numerate_lines_content: Vec<String> = Vec::new();
-    let mut numerate_lines_colors: Vec<Vec<ColorRange>> = Vec::new();
-
-    for i in 1..=main_buffer.content.len() {
-        numerate_lines_content.push(format!("{:>6} ", i));
-        numerate_lines_colors.push(vec![ColorRange {
-            range: (0, 7),
-            bg_color: Some(Rgb(42, 42, 55)),
-            fg_color: Some(Rgb(84, 83, 108)),
-        }]);
+            open_file_buffer(&mut editor, Some(filename))
+        };
+        focus_buffer.get_or_insert(key);
     }
-
-    editor.add_buffer(
-        "numerate_lines".to_string(),
-        EditorBuffer {
-            cursors: vec![],
-            content: numerate_lines_content,
-            colors: numerate_lines_colors,
-            file_name: None,
-            is_modified: false,
-            memory: vec![],
-            last_input: Instant::now(),
-            buffer_window: EditorWindow {
-                start: (1, 1),
-                end: (8, terminal_size.1 - 1),
-            },
-            pivot: (0, 0),
-            handle_keys: Arc::new(|_, _| {}),
-            tab_width: 4,
-        },
-    );
-
-    editor.buffers_to_show = vec!["numerate_lines".to_string(), focus_buffer.clone()];
+    let focus_buffer = focus_buffer.unwrap();
+    editor.layout = Layout::new(&focus_buffer);
     editor.focus_buffer = focus_buffer;
+    sync_layout(&mut editor);
 
     Ok(editor)
 }
 
 fn main() {
+    match env::args().nth(1).as_deref() {
+        Some("-r") => {
+            list_recoverable_files();
+            return;
+        }
+        Some("setup") => {
+            let flags: Vec<String> = env::args().skip(2).collect();
+            let install_deps = match flags.as_slice() {
+                [] => false,
+                [flag] if flag == "--install-deps" => true,
+                _ => {
+                    eprintln!("Usage: rustpen setup [--install-deps]");
+                    process::exit(2);
+                }
+            };
+            if let Err(e) = setup(install_deps) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Some("doctor") => process::exit(if doctor() { 0 } else { 1 }),
+        _ => {}
+    }
+
+    // Exported before any thread starts, so everything the editor spawns sees it.
+    let socket_path = default_socket_path();
+    env::set_var(SOCKET_ENV, &socket_path);
+
     let editor = init_editor().unwrap();
 
     let editor_ref = Arc::new(Mutex::new(editor));
@@ -137,6 +141,7 @@ fn main() {
             let mut editor = editor_keys.lock().unwrap();
 
             editor.execute_key(&key_str);
+            editor.sync_swap_files(false);
 
             if editor.close {
                 tx_key.send(EditorMessage::Close).unwrap();
@@ -150,32 +155,57 @@ fn main() {
     let tx_resize = tx.clone();
 
     thread::spawn(move || {
-        let mut signals = Signals::new(&[SIGWINCH]).unwrap();
+        let mut signals = Signals::new([SIGWINCH]).unwrap();
         for _ in signals.forever() {
             let mut editor = editor_resize.lock().unwrap();
-            editor.redraw(terminal_size().unwrap());
+            editor.redraw();
             tx_resize
                 .send(EditorMessage::Render("Redraw".to_string()))
                 .unwrap();
         }
     });
 
+    // Flush pending edits to the swap files even while no key is pressed.
+    let editor_swap = Arc::clone(&editor_ref);
+    thread::spawn(move || loop {
+        thread::sleep(SWAP_INTERVAL);
+        editor_swap.lock().unwrap().sync_swap_files(false);
+    });
+
+    // Killed from outside: leave the swap files behind so the edits can be recovered.
+    let editor_term = Arc::clone(&editor_ref);
+    let tx_term = tx.clone();
+    thread::spawn(move || {
+        let mut signals = Signals::new([SIGTERM, SIGHUP]).unwrap();
+        if signals.forever().next().is_some() {
+            editor_term.lock().unwrap().sync_swap_files(true);
+            tx_term.send(EditorMessage::Close).unwrap();
+        }
+    });
+
     let tx_server = tx.clone();
 
+    let server = UnixServer::new(&socket_path);
     thread::spawn(move || {
-        let server = UnixServer::new("/tmp/rustpen_unix_socket/ts");
         server.start(tx_server, editor_server);
     });
 
     loop {
         match rx.recv() {
             Ok(EditorMessage::Close) => {
+                editor_ref.lock().unwrap().hosts.stop_all();
+                let _ = fs::remove_file(&socket_path);
                 break;
             }
             Ok(EditorMessage::Render(ch)) => {
                 let mut editor = editor_ref.lock().unwrap();
                 editor.render(ch);
             }
+            Ok(EditorMessage::Log(line)) => {
+                let mut editor = editor_ref.lock().unwrap();
+                append_log(&mut editor, line);
+                editor.render(String::new());
+            }
             Err(err) => {
                 eprintln!("Error: {}", err);
             }
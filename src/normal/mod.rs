@@ -1,49 +1,163 @@
 use crate::editor::EditorBuffer;
+use crate::text::{to_cursor, TextRope};
 
+pub mod pending;
+pub mod text_objects;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum CursorDirections {
     Left,
     Right,
     Up,
     Down,
+    WordForward,
+    WordBackward,
+    LineStart,
+    LineEnd,
+    ToLine(usize),
+}
+
+// Moves to `line` keeping the cursor on the same screen column.
+fn move_to_line(content: &TextRope, tab_width: u16, (x, y): (usize, usize), line: usize) -> usize {
+    let display = content.display_col(y, x, tab_width);
+    content.col_at_display(line, display, tab_width)
 }
 
+// Positions are worked out as `usize` and only clamped into the cursor at the
+// end, so lines and columns past `u16::MAX` don't wrap around.
 pub fn move_cursors(buffer: &mut EditorBuffer, direction: CursorDirections) {
+    let last_line = buffer.content.len_lines().saturating_sub(1);
     for cursor in buffer.cursors.iter_mut() {
+        let (mut x, mut y) = (cursor.position.0 as usize, cursor.position.1 as usize);
         match direction {
             CursorDirections::Left => {
                 // Mover el cursor a la izquierda si no está en el borde
-                if cursor.position.0 > 0 {
-                    cursor.position.0 -= 1;
+                if x > 0 {
+                    x = buffer.content.prev_grapheme(y, x);
                 }
             }
             CursorDirections::Right => {
                 // Mover el cursor a la derecha si no está al final de la línea
-                if cursor.position.0 < buffer.content[cursor.position.1 as usize].len() as u16 {
-                    cursor.position.0 += 1;
+                if x < buffer.content.line_len(y) {
+                    x = buffer.content.next_grapheme(y, x);
                 }
             }
             CursorDirections::Up => {
                 // Mover el cursor hacia arriba si no está en la primera línea
-                if cursor.position.1 > 0 {
-                    cursor.position.1 -= 1;
-                    // Asegurar que el cursor no esté más allá del final de la línea
-                    cursor.position.0 = cursor
-                        .position
-                        .0
-                        .min(buffer.content[cursor.position.1 as usize].len() as u16);
+                if y > 0 {
+                    x = move_to_line(&buffer.content, buffer.tab_width, (x, y), y - 1);
+                    y -= 1;
                 }
             }
             CursorDirections::Down => {
                 // Mover el cursor hacia abajo si no está en la última línea
-                if cursor.position.1 < buffer.content.len() as u16 - 1 {
-                    cursor.position.1 += 1;
-                    // Asegurar que el cursor no esté más allá del final de la línea
-                    cursor.position.0 = cursor
-                        .position
-                        .0
-                        .min(buffer.content[cursor.position.1 as usize].len() as u16);
+                if y < last_line {
+                    x = move_to_line(&buffer.content, buffer.tab_width, (x, y), y + 1);
+                    y += 1;
+                }
+            }
+            CursorDirections::WordForward => {
+                if let Some(line) = buffer.content.line(y) {
+                    if x + 1 < line.len_chars() {
+                        let chars: Vec<char> = line.chars().collect();
+
+                        let flag = chars[x].is_alphanumeric();
+
+                        if chars[x] == ' ' {
+                            while x + 1 < chars.len() && chars[x] == ' ' {
+                                x += 1;
+                            }
+                        }
+
+                        while x + 1 < chars.len() && flag == chars[x].is_alphanumeric() {
+                            x += 1;
+                        }
+
+                        while x + 1 < chars.len() && chars[x] == ' ' {
+                            x += 1;
+                        }
+                    } else if y < last_line {
+                        x = 0;
+                        y += 1;
+                    }
                 }
             }
+            CursorDirections::WordBackward => {
+                if let Some(line) = buffer.content.line(y) {
+                    if x > 0 {
+                        let chars: Vec<char> = line.chars().collect();
+
+                        if x >= chars.len() {
+                            x = chars.len().saturating_sub(1);
+                        }
+
+                        let flag = chars[x].is_alphanumeric();
+
+                        if chars[x] == ' ' {
+                            while x > 0 && chars[x] == ' ' {
+                                x -= 1;
+                            }
+                        }
+
+                        while x > 0 && flag == chars[x].is_alphanumeric() {
+                            x -= 1;
+                        }
+
+                        while x > 0 && chars[x] == ' ' {
+                            x -= 1;
+                        }
+                    } else if y > 0 {
+                        y -= 1;
+                        x = buffer.content.line_len(y).saturating_sub(1);
+                    }
+                }
+            }
+            CursorDirections::LineStart => {
+                x = 0;
+            }
+            CursorDirections::LineEnd => {
+                x = buffer.content.prev_grapheme(y, buffer.content.line_len(y));
+            }
+            CursorDirections::ToLine(line) => {
+                let line = line.min(last_line);
+                x = move_to_line(&buffer.content, buffer.tab_width, (x, y), line);
+                y = line;
+            }
         }
+
+        // Word motions step over chars, keep the cursor off combining marks.
+        x = buffer.content.grapheme_start(y, x);
+        cursor.position = to_cursor((y, x));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{cursor, editor_with_text, type_keys};
+
+    #[test]
+    fn lines_past_u16_clamp_the_cursor() {
+        for lines in [65536, 70000] {
+            let text = "ab\n".repeat(lines - 1) + "ab";
+            let (mut editor, _) = editor_with_text("lines_past_u16_clamp_the_cursor", &text);
+            type_keys(&mut editor, "G");
+            assert_eq!(cursor(&editor), (0, u16::MAX));
+            type_keys(&mut editor, "jjw");
+            assert_eq!(cursor(&editor).1, u16::MAX);
+            type_keys(&mut editor, "gg03j");
+            assert_eq!(cursor(&editor), (0, 3));
+            type_keys(&mut editor, ":65536<Enter>");
+            assert_eq!(cursor(&editor), (0, u16::MAX));
+        }
+    }
+
+    #[test]
+    fn columns_past_u16_clamp_the_cursor() {
+        let text = "a".repeat(70000);
+        let (mut editor, _) = editor_with_text("columns_past_u16_clamp_the_cursor", &text);
+        type_keys(&mut editor, "$");
+        assert_eq!(cursor(&editor).0, u16::MAX);
+        type_keys(&mut editor, "i<BS>b<Esc>0l");
+        assert_eq!(cursor(&editor), (1, 0));
     }
 }
@@ -0,0 +1,434 @@
+use crate::{
+    command::{error_message, push_message},
+    editor::{Editor, EditorBuffer},
+    editor_modes::EditorMode,
+    operator::{apply_operator, put_register, Operator, OperatorRange},
+};
+
+use crate::registers::Registers;
+
+use super::{move_cursors, text_objects::text_object_range, CursorDirections};
+
+// Cursor positions are u16, so a larger count can't mean anything more.
+const MAX_COUNT: usize = u16::MAX as usize;
+
+// Keys typed so far for a `["x][count][operator][count](motion | text object)`
+// command in Normal mode. `keys` is what the status line shows.
+#[derive(Clone, Default, Debug)]
+pub struct PendingCommand {
+    pub keys: String,
+    register: Option<char>,
+    count: Option<usize>,
+    operator: Option<Operator>,
+    motion_count: Option<usize>,
+    prefix: Option<char>,
+}
+
+impl PendingCommand {
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn total_count(&self) -> usize {
+        self.count
+            .unwrap_or(1)
+            .saturating_mul(self.motion_count.unwrap_or(1))
+            .min(MAX_COUNT)
+    }
+
+    fn push_digit(&mut self, digit: usize) {
+        let count = if self.operator.is_some() {
+            &mut self.motion_count
+        } else {
+            &mut self.count
+        };
+        *count = Some(
+            count
+                .unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(digit)
+                .min(MAX_COUNT),
+        );
+    }
+
+    pub fn register(&self) -> Option<char> {
+        self.register
+    }
+
+    fn has_count_in_progress(&self) -> bool {
+        if self.operator.is_some() {
+            self.motion_count.is_some()
+        } else {
+            self.count.is_some()
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+}
+
+impl Motion {
+    fn from_char(ch: char) -> Option<Motion> {
+        match ch {
+            'h' => Some(Motion::Left),
+            'l' => Some(Motion::Right),
+            'k' => Some(Motion::Up),
+            'j' => Some(Motion::Down),
+            'w' => Some(Motion::WordForward),
+            'b' => Some(Motion::WordBackward),
+            '0' => Some(Motion::LineStart),
+            '$' => Some(Motion::LineEnd),
+            'G' => Some(Motion::LastLine),
+            _ => None,
+        }
+    }
+
+    fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine
+        )
+    }
+
+    fn is_inclusive(self) -> bool {
+        self == Motion::LineEnd
+    }
+}
+
+fn key_char(key: &str) -> Option<char> {
+    let inner = key.strip_prefix('<')?.strip_suffix('>')?;
+    let mut chars = inner.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Some(ch),
+        _ => None,
+    }
+}
+
+fn move_by_motion(buffer: &mut EditorBuffer, motion: Motion, pending: &PendingCommand) {
+    let explicit_count = pending.count.is_some() || pending.motion_count.is_some();
+    let count = pending.total_count();
+
+    let direction = match motion {
+        Motion::Left => CursorDirections::Left,
+        Motion::Right => CursorDirections::Right,
+        Motion::Up => CursorDirections::Up,
+        Motion::Down => CursorDirections::Down,
+        Motion::WordForward => CursorDirections::WordForward,
+        Motion::WordBackward => CursorDirections::WordBackward,
+        Motion::LineStart => CursorDirections::LineStart,
+        Motion::LineEnd => CursorDirections::LineEnd,
+        Motion::FirstLine => {
+            let line = if explicit_count { count - 1 } else { 0 };
+            CursorDirections::ToLine(line)
+        }
+        Motion::LastLine => {
+            let line = if explicit_count {
+                count - 1
+            } else {
+                buffer.content.len_lines().saturating_sub(1)
+            };
+            CursorDirections::ToLine(line)
+        }
+    };
+
+    let repeat = match motion {
+        Motion::LineStart | Motion::LineEnd | Motion::FirstLine | Motion::LastLine => 1,
+        _ => count,
+    };
+
+    for _ in 0..repeat {
+        let before: Vec<(u16, u16)> = buffer
+            .cursors
+            .iter()
+            .map(|cursor| cursor.position)
+            .collect();
+        move_cursors(buffer, direction);
+        // Stuck at the edge of the buffer, the rest of the count does nothing.
+        if buffer
+            .cursors
+            .iter()
+            .map(|cursor| cursor.position)
+            .eq(before.iter().copied())
+        {
+            break;
+        }
+    }
+}
+
+fn motion_range(
+    buffer: &EditorBuffer,
+    motion: Motion,
+    from: (u16, u16),
+    to: (u16, u16),
+) -> OperatorRange {
+    if motion.is_linewise() {
+        return OperatorRange::Lines {
+            start: from.1.min(to.1) as usize,
+            end: from.1.max(to.1) as usize,
+        };
+    }
+
+    let from = (from.1 as usize, from.0 as usize);
+    let to = (to.1 as usize, to.0 as usize);
+    let (start, mut end) = if from <= to { (from, to) } else { (to, from) };
+
+    if motion.is_inclusive() {
+        end.1 = buffer.content.next_grapheme(end.0, end.1);
+    }
+
+    // Like vim, a word motion that crosses a line only acts up to the end of
+    // the line it started on.
+    if motion == Motion::WordForward && end.0 > start.0 && end.1 == 0 {
+        end = (end.0 - 1, buffer.line_len(end.0 - 1));
+    }
+
+    OperatorRange::Chars { start, end }
+}
+
+fn run_operator(
+    editor: &mut Editor,
+    buffer: &mut EditorBuffer,
+    pending: &PendingCommand,
+    operator: Operator,
+    range: OperatorRange,
+) {
+    let applied = apply_operator(
+        buffer,
+        &mut editor.registers,
+        pending.register,
+        operator,
+        range,
+    );
+    if let Err(message) = applied {
+        push_message(editor, error_message(&message));
+    } else if operator == Operator::Change {
+        editor.editor_mode = EditorMode::Insert;
+    }
+}
+
+fn run_motion(
+    editor: &mut Editor,
+    buffer: &mut EditorBuffer,
+    pending: &PendingCommand,
+    motion: Motion,
+) {
+    let Some(operator) = pending.operator else {
+        move_by_motion(buffer, motion, pending);
+        return;
+    };
+
+    let saved_cursors = buffer.cursors.clone();
+    let from = buffer.cursors[0].position;
+    move_by_motion(buffer, motion, pending);
+    let to = buffer.cursors[0].position;
+    buffer.cursors = saved_cursors;
+
+    let range = motion_range(buffer, motion, from, to);
+    run_operator(editor, buffer, pending, operator, range);
+}
+
+fn run_linewise(
+    editor: &mut Editor,
+    buffer: &mut EditorBuffer,
+    pending: &PendingCommand,
+    operator: Operator,
+) {
+    let start = buffer.cursors[0].position.1 as usize;
+    let last_line = buffer.content.len_lines().saturating_sub(1);
+    let end = (start + pending.total_count() - 1).min(last_line);
+
+    run_operator(
+        editor,
+        buffer,
+        pending,
+        operator,
+        OperatorRange::Lines { start, end },
+    );
+}
+
+fn run_put(editor: &mut Editor, buffer: &mut EditorBuffer, pending: &PendingCommand, after: bool) {
+    match editor.registers.get(pending.register) {
+        Some(register) => put_register(buffer, &register, after, pending.total_count()),
+        None => {
+            let name = pending.register.unwrap_or('"');
+            editor
+                .commands_hist
+                .push(format!("Nothing in register {}", name));
+            editor.commands_hist.push(String::new());
+        }
+    }
+}
+
+// Feeds one Normal mode key into the pending command. Returns false when the
+// key is not part of the grammar so the caller can handle it as a plain key.
+pub fn handle_pending_key(editor: &mut Editor, buffer: &mut EditorBuffer, key: &str) -> bool {
+    let mut pending = std::mem::take(&mut editor.pending_command);
+
+    if key == "<Esc>" || key == "<C-c>" {
+        return !pending.is_empty();
+    }
+
+    let Some(ch) = key_char(key) else {
+        return !pending.is_empty();
+    };
+
+    if let Some(prefix) = pending.prefix.take() {
+        match (prefix, ch) {
+            ('g', 'g') => run_motion(editor, buffer, &pending, Motion::FirstLine),
+            ('g', '~') => match pending.operator {
+                None => {
+                    pending.operator = Some(Operator::ToggleCase);
+                    pending.keys.push(ch);
+                    editor.pending_command = pending;
+                }
+                Some(Operator::ToggleCase) => {
+                    run_linewise(editor, buffer, &pending, Operator::ToggleCase)
+                }
+                Some(_) => {}
+            },
+            ('i' | 'a', _) => {
+                if let (Some(operator), Some(range)) = (
+                    pending.operator,
+                    text_object_range(buffer, prefix == 'a', ch),
+                ) {
+                    run_operator(editor, buffer, &pending, operator, range);
+                }
+            }
+            ('"', name) if Registers::is_valid(name) => {
+                pending.register = Some(name);
+                pending.keys.push(ch);
+                editor.pending_command = pending;
+            }
+            _ => {}
+        }
+        return true;
+    }
+
+    if ch.is_ascii_digit() && (ch != '0' || pending.has_count_in_progress()) {
+        pending.push_digit(ch.to_digit(10).unwrap_or(0) as usize);
+        pending.keys.push(ch);
+        editor.pending_command = pending;
+        return true;
+    }
+
+    if ch == 'g'
+        || (pending.operator.is_some() && (ch == 'i' || ch == 'a'))
+        || (pending.operator.is_none() && ch == '"')
+    {
+        pending.prefix = Some(ch);
+        pending.keys.push(ch);
+        editor.pending_command = pending;
+        return true;
+    }
+
+    if let Some(operator) = Operator::from_key(key).filter(|op| *op != Operator::ToggleCase) {
+        match pending.operator {
+            None => {
+                pending.operator = Some(operator);
+                pending.keys.push(ch);
+                editor.pending_command = pending;
+            }
+            Some(current) if current == operator => {
+                run_linewise(editor, buffer, &pending, operator);
+            }
+            Some(_) => {}
+        }
+        return true;
+    }
+
+    if let Some(motion) = Motion::from_char(ch) {
+        run_motion(editor, buffer, &pending, motion);
+        return true;
+    }
+
+    if pending.operator.is_none() && (ch == 'p' || ch == 'P') {
+        run_put(editor, buffer, &pending, ch == 'p');
+        return true;
+    }
+
+    !pending.is_empty()
+}
+
+// The part of the grammar Visual mode shares: counts, `gg`, `"x` and motions.
+// Returns false for any other key, leaving the pending command (and so the
+// register) for the caller to act on the selection with.
+pub fn handle_visual_pending_key(
+    editor: &mut Editor,
+    buffer: &mut EditorBuffer,
+    key: &str,
+) -> bool {
+    let mut pending = std::mem::take(&mut editor.pending_command);
+
+    if key == "<Esc>" || key == "<C-c>" {
+        return !pending.is_empty();
+    }
+
+    let Some(ch) = key_char(key) else {
+        editor.pending_command = pending;
+        return false;
+    };
+
+    if let Some(prefix) = pending.prefix.take() {
+        match (prefix, ch) {
+            ('g', 'g') => move_by_motion(buffer, Motion::FirstLine, &pending),
+            ('"', name) if Registers::is_valid(name) => {
+                pending.register = Some(name);
+                pending.keys.push(ch);
+                editor.pending_command = pending;
+            }
+            _ => {}
+        }
+        return true;
+    }
+
+    if ch.is_ascii_digit() && (ch != '0' || pending.has_count_in_progress()) {
+        pending.push_digit(ch.to_digit(10).unwrap_or(0) as usize);
+        pending.keys.push(ch);
+        editor.pending_command = pending;
+        return true;
+    }
+
+    if ch == 'g' || ch == '"' {
+        pending.prefix = Some(ch);
+        pending.keys.push(ch);
+        editor.pending_command = pending;
+        return true;
+    }
+
+    if let Some(motion) = Motion::from_char(ch) {
+        move_by_motion(buffer, motion, &pending);
+        return true;
+    }
+
+    editor.pending_command = pending;
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_counts_saturate() {
+        let mut pending = PendingCommand::default();
+        for _ in 0..30 {
+            pending.push_digit(9);
+        }
+        pending.operator = Some(Operator::Delete);
+        for _ in 0..30 {
+            pending.push_digit(9);
+        }
+        assert_eq!(pending.total_count(), MAX_COUNT);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{editor::EditorBuffer, operator::OperatorRange};
+
+fn char_class(ch: char) -> u8 {
+    if ch.is_whitespace() {
+        0
+    } else if ch.is_alphanumeric() || ch == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+fn word_object(buffer: &EditorBuffer, around: bool) -> Option<OperatorRange> {
+    let (x, y) = buffer.cursors.first()?.position;
+    let (x, y) = (x as usize, y as usize);
+    let chars: Vec<char> = buffer.content.line(y)?.chars().collect();
+    if chars.is_empty() {
+        return None;
+    }
+
+    let x = x.min(chars.len() - 1);
+    let class = char_class(chars[x]);
+
+    let mut start = x;
+    while start > 0 && char_class(chars[start - 1]) == class {
+        start -= 1;
+    }
+    let mut end = x + 1;
+    while end < chars.len() && char_class(chars[end]) == class {
+        end += 1;
+    }
+
+    if around && class != 0 {
+        let trailing = chars[end..]
+            .iter()
+            .take_while(|ch| ch.is_whitespace())
+            .count();
+        if trailing > 0 {
+            end += trailing;
+        } else {
+            while start > 0 && chars[start - 1].is_whitespace() {
+                start -= 1;
+            }
+        }
+    }
+
+    Some(OperatorRange::Chars {
+        start: (y, start),
+        end: (y, end),
+    })
+}
+
+fn quote_object(buffer: &EditorBuffer, around: bool, quote: char) -> Option<OperatorRange> {
+    let (x, y) = buffer.cursors.first()?.position;
+    let (x, y) = (x as usize, y as usize);
+    let line = buffer.content.line(y)?;
+
+    let mut quotes: Vec<usize> = vec![];
+    let mut escaped = false;
+    for (idx, ch) in line.chars().enumerate() {
+        if ch == quote && !escaped {
+            quotes.push(idx);
+        }
+        escaped = ch == '\\' && !escaped;
+    }
+
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|(open, close)| *open <= x && x <= *close)
+        .or_else(|| {
+            quotes
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|(open, _)| *open > x)
+        })?;
+
+    Some(if around {
+        OperatorRange::Chars {
+            start: (y, open),
+            end: (y, close + 1),
+        }
+    } else {
+        OperatorRange::Chars {
+            start: (y, open + 1),
+            end: (y, close),
+        }
+    })
+}
+
+fn bracket_object(
+    buffer: &EditorBuffer,
+    around: bool,
+    open_ch: char,
+    close_ch: char,
+) -> Option<OperatorRange> {
+    let (x, y) = buffer.cursors.first()?.position;
+    let content = &buffer.content;
+    let cursor = content.pos_to_char((y as usize, x as usize));
+
+    let mut depth = 0;
+    let mut open = None;
+    let mut idx = cursor + 1;
+    while idx > 0 {
+        idx -= 1;
+        match content.char_at(idx) {
+            Some(ch) if ch == close_ch && idx != cursor => depth += 1,
+            Some(ch) if ch == open_ch => {
+                if depth == 0 {
+                    open = Some(idx);
+                    break;
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    let open = open?;
+
+    let mut depth = 0;
+    let mut close = None;
+    for idx in open + 1..content.len_chars() {
+        match content.char_at(idx) {
+            Some(ch) if ch == open_ch => depth += 1,
+            Some(ch) if ch == close_ch => {
+                if depth == 0 {
+                    close = Some(idx);
+                    break;
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    let close = close?;
+
+    let (start, end) = if around {
+        (open, close + 1)
+    } else {
+        (open + 1, close)
+    };
+
+    Some(OperatorRange::Chars {
+        start: content.char_to_pos(start),
+        end: content.char_to_pos(end),
+    })
+}
+
+fn paragraph_object(buffer: &EditorBuffer, around: bool) -> Option<OperatorRange> {
+    let y = buffer.cursors.first()?.position.1 as usize;
+    let content = &buffer.content;
+    let is_blank = |line: usize| {
+        content
+            .line(line)
+            .is_none_or(|line| line.chars().all(char::is_whitespace))
+    };
+
+    let blank = is_blank(y);
+    let last_line = content.len_lines() - 1;
+
+    let mut start = y;
+    while start > 0 && is_blank(start - 1) == blank {
+        start -= 1;
+    }
+    let mut end = y;
+    while end < last_line && is_blank(end + 1) == blank {
+        end += 1;
+    }
+
+    if around {
+        if end < last_line {
+            end += 1;
+            while end < last_line && is_blank(end + 1) != blank {
+                end += 1;
+            }
+        } else {
+            while start > 0 && is_blank(start - 1) != blank {
+                start -= 1;
+            }
+        }
+    }
+
+    Some(OperatorRange::Lines { start, end })
+}
+
+pub fn text_object_range(
+    buffer: &EditorBuffer,
+    around: bool,
+    object: char,
+) -> Option<OperatorRange> {
+    match object {
+        'w' => word_object(buffer, around),
+        'p' => paragraph_object(buffer, around),
+        '"' | '\'' | '`' => quote_object(buffer, around, object),
+        '(' | ')' | 'b' => bracket_object(buffer, around, '(', ')'),
+        '{' | '}' | 'B' => bracket_object(buffer, around, '{', '}'),
+        '[' | ']' => bracket_object(buffer, around, '[', ']'),
+        '<' | '>' => bracket_object(buffer, around, '<', '>'),
+        _ => None,
+    }
+}
@@ -0,0 +1,403 @@
+use crate::{
+    editor::EditorBuffer,
+    registers::{Register, RegisterKind, Registers},
+    text::to_cursor,
+    undo::TextPosition,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    Delete,
+    Yank,
+    Change,
+    Indent,
+    Dedent,
+    ToggleCase,
+}
+
+impl Operator {
+    pub fn from_key(key: &str) -> Option<Operator> {
+        match key {
+            "<d>" => Some(Operator::Delete),
+            "<y>" => Some(Operator::Yank),
+            "<c>" => Some(Operator::Change),
+            "<>>" => Some(Operator::Indent),
+            "<<>" => Some(Operator::Dedent),
+            "<~>" => Some(Operator::ToggleCase),
+            _ => None,
+        }
+    }
+}
+
+// `Chars` ends are exclusive, `Lines` ends are inclusive and `Block` spans
+// every line in `lines` (inclusive) between the exclusive screen column range
+// `cols`, so it stays a rectangle over tabs and wide characters.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OperatorRange {
+    Chars {
+        start: TextPosition,
+        end: TextPosition,
+    },
+    Lines {
+        start: usize,
+        end: usize,
+    },
+    Block {
+        lines: (usize, usize),
+        cols: (usize, usize),
+    },
+}
+
+fn toggle_case(text: &str) -> String {
+    text.chars()
+        .flat_map(|ch| {
+            if ch.is_uppercase() {
+                ch.to_lowercase().collect::<Vec<char>>()
+            } else {
+                ch.to_uppercase().collect::<Vec<char>>()
+            }
+        })
+        .collect()
+}
+
+fn first_non_blank(buffer: &EditorBuffer, line: usize) -> usize {
+    buffer
+        .content
+        .line(line)
+        .and_then(|line| line.chars().position(|ch| !ch.is_whitespace()))
+        .unwrap_or(0)
+}
+
+// Char columns of `line` inside the block, with graphemes that only
+// partly overlap its screen columns taken whole.
+pub fn block_line_range(
+    buffer: &EditorBuffer,
+    line: usize,
+    cols: (usize, usize),
+) -> (usize, usize) {
+    let content = &buffer.content;
+    let from = content.col_at_display(line, cols.0, buffer.tab_width);
+    let last = content.col_at_display(line, cols.1.saturating_sub(1), buffer.tab_width);
+    (from, content.next_grapheme(line, last).max(from))
+}
+
+pub fn range_text(buffer: &EditorBuffer, range: OperatorRange) -> String {
+    match range {
+        OperatorRange::Chars { start, end } => buffer.content.slice(start, end),
+        OperatorRange::Lines { start, end } => {
+            let mut text = String::new();
+            for line in start..=end {
+                text.push_str(&buffer.content.line_string(line));
+                text.push('\n');
+            }
+            text
+        }
+        OperatorRange::Block { lines, cols } => (lines.0..=lines.1)
+            .map(|line| {
+                let (from, to) = block_line_range(buffer, line, cols);
+                buffer.content.slice((line, from), (line, to))
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+    }
+}
+
+fn delete_range(buffer: &mut EditorBuffer, range: OperatorRange, keep_line: bool) -> TextPosition {
+    match range {
+        OperatorRange::Chars { start, end } => {
+            buffer.delete_text(start, end);
+            start
+        }
+        OperatorRange::Lines { start, end } => {
+            let last_line = buffer.content.len_lines() - 1;
+            let end_len = buffer.line_len(end);
+
+            if keep_line {
+                buffer.delete_text((start, 0), (end, end_len));
+            } else if end < last_line {
+                buffer.delete_text((start, 0), (end + 1, 0));
+            } else if start > 0 {
+                let prev_len = buffer.line_len(start - 1);
+                buffer.delete_text((start - 1, prev_len), (end, end_len));
+            } else {
+                buffer.delete_text((0, 0), (end, end_len));
+            }
+
+            let line = start.min(buffer.content.len_lines() - 1);
+            (line, first_non_blank(buffer, line))
+        }
+        OperatorRange::Block { lines, cols } => {
+            for line in lines.0..=lines.1 {
+                let (from, to) = block_line_range(buffer, line, cols);
+                buffer.delete_text((line, from), (line, to));
+            }
+            (lines.0, block_line_range(buffer, lines.0, cols).0)
+        }
+    }
+}
+
+fn range_lines(range: OperatorRange) -> (usize, usize) {
+    match range {
+        OperatorRange::Chars { start, end } => (start.0, end.0),
+        OperatorRange::Lines { start, end } => (start, end),
+        OperatorRange::Block { lines, .. } => lines,
+    }
+}
+
+// The indent is measured in display columns and rewritten one shift width
+// further in or out, with tabs unless the buffer expands them.
+fn shift_lines(buffer: &mut EditorBuffer, lines: (usize, usize), indent: bool) {
+    let width = buffer.tab_width as usize;
+
+    for line in lines.0..=lines.1 {
+        let text = buffer.content.line_string(line);
+        let leading: Vec<char> = text
+            .chars()
+            .take_while(|ch| *ch == ' ' || *ch == '\t')
+            .collect();
+        if indent && leading.len() == text.len() {
+            continue;
+        }
+
+        let columns = leading.iter().fold(0, |column, ch| match ch {
+            '\t' => (column / width + 1) * width,
+            _ => column + 1,
+        });
+        let columns = if indent {
+            columns + width
+        } else {
+            columns.saturating_sub(width)
+        };
+        let new_indent = if buffer.expand_tab {
+            " ".repeat(columns)
+        } else {
+            "\t".repeat(columns / width) + &" ".repeat(columns % width)
+        };
+
+        if new_indent.chars().ne(leading.iter().copied()) {
+            buffer.delete_text((line, 0), (line, leading.len()));
+            buffer.insert_text((line, 0), &new_indent);
+        }
+    }
+}
+
+fn toggle_range_case(buffer: &mut EditorBuffer, range: OperatorRange) {
+    let spans: Vec<(TextPosition, TextPosition)> = match range {
+        OperatorRange::Chars { start, end } => vec![(start, end)],
+        OperatorRange::Lines { start, end } => (start..=end)
+            .map(|line| ((line, 0), (line, buffer.line_len(line))))
+            .collect(),
+        OperatorRange::Block { lines, cols } => (lines.0..=lines.1)
+            .map(|line| {
+                let (from, to) = block_line_range(buffer, line, cols);
+                ((line, from), (line, to))
+            })
+            .collect(),
+    };
+
+    for (start, end) in spans {
+        let text = buffer.content.slice(start, end);
+        let toggled = toggle_case(&text);
+        if toggled != text {
+            buffer.delete_text(start, end);
+            buffer.insert_text(start, &toggled);
+        }
+    }
+}
+
+// Applies `operator` over `range` and returns where the cursor should land.
+// Every operator except `Change` closes its undo transaction, so the text
+// typed after a change is undone together with the deletion. Nothing changes
+// when the register can't be written.
+pub fn apply_operator(
+    buffer: &mut EditorBuffer,
+    registers: &mut Registers,
+    register_name: Option<char>,
+    operator: Operator,
+    range: OperatorRange,
+) -> Result<TextPosition, String> {
+    if matches!(
+        operator,
+        Operator::Yank | Operator::Delete | Operator::Change
+    ) {
+        Registers::check_writable(register_name)?;
+    }
+
+    let position = match operator {
+        Operator::Yank => {
+            let register = Register {
+                content: range_text(buffer, range),
+                kind: RegisterKind::from_range(&range),
+            };
+            registers.yank(register_name, register)?;
+            match range {
+                OperatorRange::Chars { start, .. } => start,
+                OperatorRange::Lines { start, .. } => (start, first_non_blank(buffer, start)),
+                OperatorRange::Block { lines, cols } => {
+                    (lines.0, block_line_range(buffer, lines.0, cols).0)
+                }
+            }
+        }
+        Operator::Delete | Operator::Change => {
+            let register = Register {
+                content: range_text(buffer, range),
+                kind: RegisterKind::from_range(&range),
+            };
+            registers.delete(register_name, register)?;
+            delete_range(buffer, range, operator == Operator::Change)
+        }
+        Operator::Indent | Operator::Dedent => {
+            let lines = range_lines(range);
+            shift_lines(buffer, lines, operator == Operator::Indent);
+            (lines.0, first_non_blank(buffer, lines.0))
+        }
+        Operator::ToggleCase => {
+            toggle_range_case(buffer, range);
+            match range {
+                OperatorRange::Chars { start, .. } => start,
+                OperatorRange::Lines { start, .. } => (start, 0),
+                OperatorRange::Block { lines, cols } => {
+                    (lines.0, block_line_range(buffer, lines.0, cols).0)
+                }
+            }
+        }
+    };
+
+    if let Some(cursor) = buffer.cursors.first_mut() {
+        cursor.position = to_cursor(position);
+    }
+
+    if operator != Operator::Change {
+        buffer.commit_undo();
+    }
+
+    Ok(position)
+}
+
+// Puts `register` `count` times after (`p`) or before (`P`) the first cursor.
+pub fn put_register(buffer: &mut EditorBuffer, register: &Register, after: bool, count: usize) {
+    let Some(cursor) = buffer.cursors.first() else {
+        return;
+    };
+    let (x, y) = (cursor.position.0 as usize, cursor.position.1 as usize);
+    let count = count.max(1);
+
+    let position = match register.kind {
+        RegisterKind::Charwise => {
+            let col = if after && buffer.line_len(y) > 0 {
+                buffer.content.next_grapheme(y, x)
+            } else {
+                x.min(buffer.line_len(y))
+            };
+            let text = register.content.repeat(count);
+            let end = buffer.insert_text((y, col), &text);
+            (end.0, buffer.content.prev_grapheme(end.0, end.1))
+        }
+        RegisterKind::Linewise => {
+            let mut text = register.content.repeat(count);
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+
+            let line = if after { y + 1 } else { y };
+            if line < buffer.content.len_lines() {
+                buffer.insert_text((line, 0), &text);
+            } else {
+                text.pop();
+                let len = buffer.line_len(y);
+                buffer.insert_text((y, len), &format!("\n{}", text));
+            }
+            (line, first_non_blank(buffer, line))
+        }
+        // Each line of the block goes in at the same screen column.
+        RegisterKind::Blockwise => {
+            let col = if after && buffer.line_len(y) > 0 {
+                buffer.content.next_grapheme(y, x)
+            } else {
+                x.min(buffer.line_len(y))
+            };
+            let display = buffer.content.display_col(y, col, buffer.tab_width);
+
+            for (offset, block_line) in register.content.split('\n').enumerate() {
+                let line = y + offset;
+                if line >= buffer.content.len_lines() {
+                    let last = buffer.content.len_lines() - 1;
+                    let len = buffer.line_len(last);
+                    buffer.insert_text((last, len), "\n");
+                }
+
+                let len = buffer.line_len(line);
+                let width = buffer.content.display_col(line, len, buffer.tab_width);
+                if width < display {
+                    buffer.insert_text((line, len), &" ".repeat(display - width));
+                }
+                let col = buffer
+                    .content
+                    .col_at_display(line, display, buffer.tab_width);
+                buffer.insert_text((line, col), &block_line.repeat(count));
+            }
+            (y, col)
+        }
+    };
+
+    if let Some(cursor) = buffer.cursors.first_mut() {
+        cursor.position = to_cursor(position);
+    }
+    buffer.commit_undo();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{editor::EditorWindow, text::TextRope};
+
+    fn shift(text: &str, expand_tab: bool, operator: Operator) -> String {
+        let window = EditorWindow {
+            start: (0, 0),
+            end: (80, 24),
+        };
+        let mut buffer = EditorBuffer::new(Arc::new(|_, _| {}), window, 4);
+        buffer.content = TextRope::from_text(text);
+        buffer.expand_tab = expand_tab;
+        let lines = buffer.content.len_lines() - 1;
+        apply_operator(
+            &mut buffer,
+            &mut Registers::default(),
+            None,
+            operator,
+            OperatorRange::Lines {
+                start: 0,
+                end: lines,
+            },
+        )
+        .unwrap();
+        buffer.content.to_lines().join("\n")
+    }
+
+    #[test]
+    fn indent_with_spaces() {
+        assert_eq!(
+            shift("a\n\tb\n", true, Operator::Indent),
+            "    a\n        b\n"
+        );
+    }
+
+    #[test]
+    fn indent_with_tabs() {
+        assert_eq!(
+            shift("a\n\tb\n  c", false, Operator::Indent),
+            "\ta\n\t\tb\n\t  c"
+        );
+    }
+
+    #[test]
+    fn dedent_tabs_and_spaces() {
+        assert_eq!(
+            shift("\t\ta\n      b\n  c\nd", false, Operator::Dedent),
+            "\ta\n  b\nc\nd"
+        );
+        assert_eq!(shift("\t\ta", true, Operator::Dedent), "    a");
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::operator::OperatorRange;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub enum RegisterKind {
+    Charwise,
+    Linewise,
+    Blockwise,
+}
+
+impl RegisterKind {
+    pub fn from_range(range: &OperatorRange) -> RegisterKind {
+        match range {
+            OperatorRange::Chars { .. } => RegisterKind::Charwise,
+            OperatorRange::Lines { .. } => RegisterKind::Linewise,
+            OperatorRange::Block { .. } => RegisterKind::Blockwise,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct Register {
+    pub content: String,
+    pub kind: RegisterKind,
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+
+        for (idx, shift) in [18, 12, 6, 0].iter().enumerate() {
+            if idx <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[((n >> shift) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+// Builds the OSC 52 sequence that asks the terminal to set the system
+// clipboard, which also works through SSH and tmux without any external tool.
+pub fn osc52_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64_encode(text.as_bytes()))
+}
+
+#[derive(Clone, Default)]
+pub struct Registers {
+    unnamed: Option<Register>,
+    named: HashMap<char, Register>,
+    numbered: [Option<Register>; 10],
+    last_inserted: String,
+    insert_session: String,
+    last_command: String,
+    last_search: String,
+    clipboard: Option<Register>,
+    clipboard_requests: Vec<String>,
+}
+
+impl Registers {
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphanumeric() || matches!(name, '"' | '.' | ':' | '/' | '+' | '*' | '_')
+    }
+
+    pub fn is_read_only(name: char) -> bool {
+        matches!(name, '.' | ':' | '/')
+    }
+
+    // Whether yanked or deleted text can go to `name`.
+    pub fn check_writable(name: Option<char>) -> Result<(), String> {
+        match name {
+            Some(name) if !Self::is_valid(name) || Self::is_read_only(name) => {
+                Err(format!("Invalid register name: {}", name))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn store_named(&mut self, name: char, register: &Register) {
+        match name {
+            'a'..='z' => {
+                self.named.insert(name, register.clone());
+            }
+            'A'..='Z' => {
+                let entry = self
+                    .named
+                    .entry(name.to_ascii_lowercase())
+                    .or_insert_with(|| Register {
+                        content: String::new(),
+                        kind: register.kind,
+                    });
+                if register.kind == RegisterKind::Linewise && !entry.content.ends_with('\n') {
+                    entry.content.push('\n');
+                }
+                entry.content.push_str(&register.content);
+                if register.kind == RegisterKind::Linewise {
+                    entry.kind = RegisterKind::Linewise;
+                }
+            }
+            '0'..='9' => {
+                self.numbered[name.to_digit(10).unwrap_or(0) as usize] = Some(register.clone());
+            }
+            '+' | '*' => {
+                self.clipboard_requests
+                    .push(osc52_sequence(&register.content));
+                self.clipboard = Some(register.clone());
+            }
+            _ => {}
+        }
+    }
+
+    // Shifts "1..."8 into "2..."9 so a new delete can take "1.
+    fn shift_delete_ring(&mut self) {
+        for idx in (2..10).rev() {
+            self.numbered[idx] = self.numbered[idx - 1].take();
+        }
+    }
+
+    pub fn yank(&mut self, name: Option<char>, register: Register) -> Result<(), String> {
+        Self::check_writable(name)?;
+        if name == Some('_') {
+            return Ok(());
+        }
+        match name {
+            Some(name) if name != '"' => self.store_named(name, &register),
+            _ => self.numbered[0] = Some(register.clone()),
+        }
+        self.unnamed = Some(register);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, name: Option<char>, register: Register) -> Result<(), String> {
+        Self::check_writable(name)?;
+        if name == Some('_') {
+            return Ok(());
+        }
+        match name {
+            Some(name) if name != '"' => self.store_named(name, &register),
+            _ => {
+                self.shift_delete_ring();
+                self.numbered[1] = Some(register.clone());
+            }
+        }
+        self.unnamed = Some(register);
+        Ok(())
+    }
+
+    pub fn get(&self, name: Option<char>) -> Option<Register> {
+        let charwise = |content: &str| {
+            Some(Register {
+                content: content.to_string(),
+                kind: RegisterKind::Charwise,
+            })
+        };
+
+        match name.unwrap_or('"') {
+            '"' => self.unnamed.clone(),
+            '.' => charwise(&self.last_inserted),
+            ':' => charwise(&self.last_command),
+            '/' => charwise(&self.last_search),
+            '+' | '*' => self.clipboard.clone(),
+            name @ '0'..='9' => self.numbered[name.to_digit(10).unwrap_or(0) as usize].clone(),
+            name @ ('a'..='z' | 'A'..='Z') => self.named.get(&name.to_ascii_lowercase()).cloned(),
+            _ => None,
+        }
+    }
+
+    pub fn record_insert(&mut self, text: &str) {
+        self.insert_session.push_str(text);
+    }
+
+    pub fn record_backspace(&mut self) {
+        self.insert_session.pop();
+    }
+
+    pub fn finish_insert(&mut self) {
+        if !self.insert_session.is_empty() {
+            self.last_inserted = std::mem::take(&mut self.insert_session);
+        }
+    }
+
+    pub fn set_last_command(&mut self, command: &str) {
+        self.last_command = command.to_string();
+    }
+
+    pub fn set_last_search(&mut self, pattern: &str) {
+        self.last_search = pattern.to_string();
+    }
+
+    pub fn take_clipboard_requests(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.clipboard_requests)
+    }
+
+    pub fn to_context(&self) -> HashMap<String, Register> {
+        let mut registers = HashMap::new();
+        let names = ['"', '.', ':', '/', '+']
+            .into_iter()
+            .chain('0'..='9')
+            .chain('a'..='z');
+
+        for name in names {
+            if let Some(register) = self.get(Some(name)) {
+                if !register.content.is_empty() {
+                    registers.insert(name.to_string(), register);
+                }
+            }
+        }
+
+        registers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{buffer_text, editor_with_text, type_keys};
+
+    fn charwise(content: &str) -> Register {
+        Register {
+            content: content.to_string(),
+            kind: RegisterKind::Charwise,
+        }
+    }
+
+    fn content(registers: &Registers, name: char) -> Option<String> {
+        registers.get(Some(name)).map(|register| register.content)
+    }
+
+    #[test]
+    fn deletes_shift_the_numbered_registers() {
+        let mut registers = Registers::default();
+        registers.yank(None, charwise("yanked")).unwrap();
+        for idx in 0..10 {
+            registers.delete(None, charwise(&idx.to_string())).unwrap();
+        }
+        assert_eq!(content(&registers, '0').as_deref(), Some("yanked"));
+        assert_eq!(content(&registers, '1').as_deref(), Some("9"));
+        assert_eq!(content(&registers, '9').as_deref(), Some("1"));
+        assert_eq!(content(&registers, '"').as_deref(), Some("9"));
+
+        // Named and black hole deletes leave the ring alone.
+        registers.delete(Some('a'), charwise("named")).unwrap();
+        registers.delete(Some('_'), charwise("gone")).unwrap();
+        assert_eq!(content(&registers, '1').as_deref(), Some("9"));
+        assert_eq!(content(&registers, '"').as_deref(), Some("named"));
+    }
+
+    #[test]
+    fn uppercase_names_append() {
+        let mut registers = Registers::default();
+        registers.yank(Some('a'), charwise("one")).unwrap();
+        registers.yank(Some('A'), charwise(" two")).unwrap();
+        assert_eq!(content(&registers, 'a').as_deref(), Some("one two"));
+
+        let line = Register {
+            content: "three\n".to_string(),
+            kind: RegisterKind::Linewise,
+        };
+        registers.yank(Some('A'), line).unwrap();
+        let register = registers.get(Some('a')).unwrap();
+        assert_eq!(register.content, "one two\nthree\n");
+        assert_eq!(register.kind, RegisterKind::Linewise);
+    }
+
+    #[test]
+    fn read_only_registers_refuse_text() {
+        let mut registers = Registers::default();
+        registers.yank(None, charwise("kept")).unwrap();
+        for name in ['.', ':', '/', '!'] {
+            assert!(registers.yank(Some(name), charwise("new")).is_err());
+            assert!(registers.delete(Some(name), charwise("new")).is_err());
+        }
+        assert_eq!(content(&registers, '"').as_deref(), Some("kept"));
+        assert_eq!(content(&registers, '1'), None);
+
+        let (mut editor, _) = editor_with_text("read_only_registers_refuse_text", "one\ntwo");
+        type_keys(&mut editor, "\":dd\".yy");
+        assert_eq!(buffer_text(&editor), "one\ntwo");
+        assert!(editor.registers.get(None).is_none());
+    }
+
+    #[test]
+    fn clipboard_goes_out_as_osc52() {
+        assert_eq!(osc52_sequence(""), "\x1b]52;c;\x07");
+        assert_eq!(osc52_sequence("hi"), "\x1b]52;c;aGk=\x07");
+        assert_eq!(osc52_sequence("héllo\n"), "\x1b]52;c;aMOpbGxvCg==\x07");
+
+        let mut registers = Registers::default();
+        registers.yank(Some('+'), charwise("abc")).unwrap();
+        assert_eq!(registers.take_clipboard_requests(), ["\x1b]52;c;YWJj\x07"]);
+        assert!(registers.take_clipboard_requests().is_empty());
+        assert_eq!(content(&registers, '*').as_deref(), Some("abc"));
+    }
+}
@@ -0,0 +1,270 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use termion::{clear, color, cursor, style};
+
+use crate::screen::{Cell, CellColor, CellStyle, Grid, TerminalCursor};
+use crate::text::grapheme_width;
+
+// Where the rendered frame ends up: a real terminal, or a grid in memory for tests.
+pub trait Backend: Send {
+    fn size(&self) -> io::Result<(u16, u16)>;
+    fn clear(&mut self) -> io::Result<()>;
+    // Cells come in row order, a cell right after the previous one needs no cursor move.
+    fn draw(&mut self, cells: &[(u16, u16, Cell)]) -> io::Result<()>;
+    // `None` hides the cursor.
+    fn set_cursor(&mut self, cursor: Option<&TerminalCursor>) -> io::Result<()>;
+    // Escape sequences that don't touch the grid, like OSC 52 clipboard requests.
+    fn write_sequence(&mut self, sequence: &str) -> io::Result<()>;
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+pub struct TermionBackend<W: Write + Send> {
+    out: W,
+    pen: Option<(u16, u16)>,
+}
+
+impl<W: Write + Send> TermionBackend<W> {
+    pub fn new(out: W) -> Self {
+        TermionBackend { out, pen: None }
+    }
+}
+
+fn write_sgr(out: &mut impl Write, cell_style: CellStyle) -> io::Result<()> {
+    write!(out, "{}", style::Reset)?;
+    match cell_style.fg {
+        CellColor::Reset => {}
+        CellColor::Ansi(value) => write!(out, "{}", color::Fg(color::AnsiValue(value)))?,
+        CellColor::Rgb(rgb) => write!(out, "{}", color::Fg(rgb.to_termion_rgb()))?,
+    }
+    match cell_style.bg {
+        CellColor::Reset => {}
+        CellColor::Ansi(value) => write!(out, "{}", color::Bg(color::AnsiValue(value)))?,
+        CellColor::Rgb(rgb) => write!(out, "{}", color::Bg(rgb.to_termion_rgb()))?,
+    }
+    if cell_style.bold {
+        write!(out, "{}", style::Bold)?;
+    }
+    if cell_style.italic {
+        write!(out, "{}", style::Italic)?;
+    }
+    if cell_style.underline {
+        write!(out, "{}", style::Underline)?;
+    }
+    if cell_style.reverse {
+        write!(out, "{}", style::Invert)?;
+    }
+    Ok(())
+}
+
+impl<W: Write + Send> Backend for TermionBackend<W> {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        termion::terminal_size()
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.pen = None;
+        write!(self.out, "{}{}", style::Reset, clear::All)
+    }
+
+    fn draw(&mut self, cells: &[(u16, u16, Cell)]) -> io::Result<()> {
+        let (width, _) = self.size().unwrap_or((u16::MAX, u16::MAX));
+        let mut current_style: Option<CellStyle> = None;
+
+        for (x, y, cell) in cells {
+            let (x, y) = (*x, *y);
+            // Covered by the glyph of the wide grapheme before it.
+            if cell.is_continuation() {
+                continue;
+            }
+            if self.pen != Some((x, y)) {
+                write!(self.out, "{}", cursor::Goto(x, y))?;
+            }
+            if current_style != Some(cell.style) {
+                write_sgr(&mut self.out, cell.style)?;
+                current_style = Some(cell.style);
+            }
+            write!(self.out, "{}", cell.symbol)?;
+
+            // Past the last column the terminal position depends on autowrap.
+            let next = x + grapheme_width(&cell.symbol) as u16;
+            self.pen = (next <= width).then_some((next, y));
+        }
+
+        if current_style.is_some() {
+            write!(self.out, "{}", style::Reset)?;
+        }
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, terminal_cursor: Option<&TerminalCursor>) -> io::Result<()> {
+        match terminal_cursor {
+            Some(terminal_cursor) => {
+                let (x, y) = terminal_cursor.position;
+                self.pen = Some((x, y));
+                write!(
+                    self.out,
+                    "{}{}{}",
+                    cursor::Goto(x, y),
+                    terminal_cursor.form.to_termion_cursor(),
+                    cursor::Show
+                )
+            }
+            None => write!(self.out, "{}", cursor::Hide),
+        }
+    }
+
+    fn write_sequence(&mut self, sequence: &str) -> io::Result<()> {
+        self.out.write_all(sequence.as_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+struct TestTerminal {
+    grid: Grid,
+    cursor: Option<TerminalCursor>,
+    sequences: Vec<String>,
+    // Where cells were drawn since the last `take_drawn`.
+    drawn: Vec<(u16, u16)>,
+}
+
+// Clones share the same terminal, so a test can keep one while the editor owns another.
+#[derive(Clone)]
+pub struct TestBackend {
+    terminal: Arc<Mutex<TestTerminal>>,
+}
+
+impl TestBackend {
+    pub fn new(size: (u16, u16)) -> Self {
+        TestBackend {
+            terminal: Arc::new(Mutex::new(TestTerminal {
+                grid: Grid::new(size),
+                cursor: None,
+                sequences: vec![],
+                drawn: vec![],
+            })),
+        }
+    }
+
+    pub fn grid(&self) -> Grid {
+        self.terminal.lock().unwrap().grid.clone()
+    }
+
+    pub fn row_text(&self, y: u16) -> String {
+        self.terminal.lock().unwrap().grid.row_text(y)
+    }
+
+    pub fn cursor(&self) -> Option<TerminalCursor> {
+        self.terminal.lock().unwrap().cursor.clone()
+    }
+
+    pub fn take_sequences(&self) -> Vec<String> {
+        std::mem::take(&mut self.terminal.lock().unwrap().sequences)
+    }
+
+    pub fn take_drawn(&self) -> Vec<(u16, u16)> {
+        std::mem::take(&mut self.terminal.lock().unwrap().drawn)
+    }
+
+    // Behaves like a terminal window being resized: the contents are lost.
+    pub fn set_size(&self, size: (u16, u16)) {
+        self.terminal.lock().unwrap().grid = Grid::new(size);
+    }
+}
+
+impl Backend for TestBackend {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        Ok(self.terminal.lock().unwrap().grid.size())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.terminal.lock().unwrap().grid.clear();
+        Ok(())
+    }
+
+    fn draw(&mut self, cells: &[(u16, u16, Cell)]) -> io::Result<()> {
+        let mut terminal = self.terminal.lock().unwrap();
+        for (x, y, cell) in cells {
+            terminal.grid.set(*x, *y, cell.clone());
+            terminal.drawn.push((*x, *y));
+        }
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, terminal_cursor: Option<&TerminalCursor>) -> io::Result<()> {
+        self.terminal.lock().unwrap().cursor = terminal_cursor.cloned();
+        Ok(())
+    }
+
+    fn write_sequence(&mut self, sequence: &str) -> io::Result<()> {
+        self.terminal
+            .lock()
+            .unwrap()
+            .sequences
+            .push(sequence.to_string());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::CursorForm;
+    use crate::layout::GUTTER_WIDTH;
+    use std::fs;
+
+    use crate::buffers::buffer_list::open_file_buffer;
+    use crate::layout::{sync_layout, Layout};
+    use crate::testing::{editor_with_text, temp_dir, test_editor, type_keys};
+
+    #[test]
+    fn renders_lines_and_cursor() {
+        let (mut editor, backend) = editor_with_text("renders_lines_and_cursor", "a界b\nsecond\n");
+        editor.render(String::new());
+        assert_eq!(backend.row_text(1).trim_end(), "     1  a界b");
+        assert_eq!(backend.row_text(2).trim_end(), "     2  second");
+        let text_start = GUTTER_WIDTH + 1;
+        let cursor = backend.cursor().unwrap();
+        assert_eq!(cursor.position, (text_start, 1));
+        assert_eq!(cursor.form, CursorForm::SteadyBlock);
+
+        // The cursor skips both cells of the wide grapheme.
+        type_keys(&mut editor, "ll");
+        editor.render(String::new());
+        assert_eq!(backend.cursor().unwrap().position, (text_start + 3, 1));
+
+        type_keys(&mut editor, "i");
+        editor.render(String::new());
+        assert_eq!(backend.row_text(24).trim(), "--INSERT--");
+
+        type_keys(&mut editor, "<Esc>:ls");
+        editor.render(String::new());
+        let cursor = backend.cursor().unwrap();
+        assert_eq!(backend.row_text(24).trim(), "Command: ls");
+        assert_eq!(cursor.position, (12, 24));
+        assert_eq!(cursor.form, CursorForm::SteadyBar);
+    }
+
+    #[test]
+    fn line_numbers_are_not_cut() {
+        let dir = temp_dir("line_numbers_are_not_cut");
+        let path = dir.join("file.txt");
+        fs::write(&path, "x\n".repeat(2000)).unwrap();
+        // Too narrow for the whole gutter.
+        let (mut editor, backend) = test_editor(dir.to_str().unwrap(), (6, 24));
+        let key = open_file_buffer(&mut editor, path.to_str());
+        editor.layout = Layout::new(&key);
+        editor.focus_buffer = key;
+        sync_layout(&mut editor);
+        type_keys(&mut editor, ":1010<Enter>");
+        editor.render(String::new());
+        let rows: Vec<String> = (1..24).map(|row| backend.row_text(row)).collect();
+        assert!(rows.iter().any(|row| row.starts_with("1000")));
+    }
+}
@@ -0,0 +1,430 @@
+pub mod backend;
+
+use std::io;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::editor::{CursorForm, Rgb};
+use crate::screen::backend::Backend;
+use crate::text::grapheme_width;
+
+// Rewriting a short run of unchanged cells is cheaper than a Goto sequence.
+const MAX_REWRITE_GAP: u16 = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum CellColor {
+    #[default]
+    Reset,
+    Ansi(u8),
+    Rgb(Rgb),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct CellStyle {
+    pub fg: CellColor,
+    pub bg: CellColor,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl CellStyle {
+    pub fn colors(fg: Option<Rgb>, bg: Option<Rgb>) -> Self {
+        CellStyle {
+            fg: fg.map(CellColor::Rgb).unwrap_or_default(),
+            bg: bg.map(CellColor::Rgb).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    pub fn fg(fg: CellColor) -> Self {
+        CellStyle {
+            fg,
+            ..Default::default()
+        }
+    }
+
+    // Applies the parameters of an SGR escape (`ESC [ ... m`), `0` goes back to `base`.
+    fn apply_sgr(&mut self, params: &str, base: CellStyle) {
+        let params: Vec<u16> = params
+            .split(';')
+            .map(|param| param.parse().unwrap_or(0))
+            .collect();
+
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => *self = base,
+                1 => self.bold = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.reverse = true,
+                22 => self.bold = false,
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.reverse = false,
+                code @ 30..=37 => self.fg = CellColor::Ansi((code - 30) as u8),
+                code @ 40..=47 => self.bg = CellColor::Ansi((code - 40) as u8),
+                code @ 90..=97 => self.fg = CellColor::Ansi((code - 90 + 8) as u8),
+                code @ 100..=107 => self.bg = CellColor::Ansi((code - 100 + 8) as u8),
+                39 => self.fg = base.fg,
+                49 => self.bg = base.bg,
+                code @ (38 | 48) => {
+                    let color = match params.get(i + 1) {
+                        Some(5) => {
+                            let value = params.get(i + 2).copied().unwrap_or(0);
+                            i += 2;
+                            CellColor::Ansi(value as u8)
+                        }
+                        Some(2) => {
+                            let channel = |idx: usize| params.get(idx).copied().unwrap_or(0) as u8;
+                            let rgb = Rgb(channel(i + 2), channel(i + 3), channel(i + 4));
+                            i += 4;
+                            CellColor::Rgb(rgb)
+                        }
+                        _ => CellColor::Reset,
+                    };
+                    if code == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+}
+
+// `symbol` is one grapheme. A wide grapheme is followed by a cell with an
+// empty symbol that its glyph covers.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cell {
+    pub symbol: String,
+    pub style: CellStyle,
+}
+
+impl Cell {
+    pub fn new(symbol: &str, style: CellStyle) -> Self {
+        Cell {
+            symbol: symbol.to_string(),
+            style,
+        }
+    }
+
+    pub fn is_continuation(&self) -> bool {
+        self.symbol.is_empty()
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell::new(" ", CellStyle::default())
+    }
+}
+
+// Cells are addressed with terminal coordinates, starting at (1, 1).
+#[derive(Clone, Debug)]
+pub struct Grid {
+    width: u16,
+    height: u16,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    pub fn new(size: (u16, u16)) -> Self {
+        Grid {
+            width: size.0,
+            height: size.1,
+            cells: vec![Cell::default(); size.0 as usize * size.1 as usize],
+        }
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        if x == 0 || y == 0 || x > self.width || y > self.height {
+            return None;
+        }
+        Some((y - 1) as usize * self.width as usize + (x - 1) as usize)
+    }
+
+    pub fn get(&self, x: u16, y: u16) -> Option<&Cell> {
+        self.index(x, y).map(|idx| &self.cells[idx])
+    }
+
+    pub fn get_mut(&mut self, x: u16, y: u16) -> Option<&mut Cell> {
+        self.index(x, y).map(|idx| &mut self.cells[idx])
+    }
+
+    pub fn set(&mut self, x: u16, y: u16, cell: Cell) {
+        if let Some(target) = self.get_mut(x, y) {
+            *target = cell;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(Cell::default());
+    }
+
+    pub fn fill(&mut self, style: CellStyle) {
+        self.cells.fill(Cell::new(" ", style));
+    }
+
+    // Writes one grapheme and returns the column after it. A wide grapheme that
+    // doesn't fit before `limit` (exclusive) is replaced by a space.
+    pub fn put_grapheme(
+        &mut self,
+        x: u16,
+        y: u16,
+        grapheme: &str,
+        style: CellStyle,
+        limit: u16,
+    ) -> u16 {
+        let width = grapheme_width(grapheme) as u16;
+        if x.saturating_add(width) > limit.min(self.width + 1) {
+            self.set(x, y, Cell::new(" ", style));
+            return x.saturating_add(1);
+        }
+
+        let symbol = if grapheme.starts_with(char::is_control) {
+            " "
+        } else {
+            grapheme
+        };
+        self.set(x, y, Cell::new(symbol, style));
+        for column in x + 1..x + width {
+            self.set(column, y, Cell::new("", style));
+        }
+        x + width
+    }
+
+    // Returns the column after the last written cell; text past the edge is dropped.
+    pub fn put_str(&mut self, x: u16, y: u16, text: &str, style: CellStyle) -> u16 {
+        let mut column = x;
+        for grapheme in text.graphemes(true) {
+            if column > self.width {
+                break;
+            }
+            column = self.put_grapheme(column, y, grapheme, style, u16::MAX);
+        }
+        column
+    }
+
+    // Like `put_str`, but SGR escapes in `text` (e.g. from `color::Fg`) style the cells.
+    pub fn put_ansi_str(&mut self, x: u16, y: u16, text: &str, base: CellStyle) -> u16 {
+        let mut column = x;
+        let mut style = base;
+        let mut rest = text;
+
+        while !rest.is_empty() {
+            let (plain, escape) = match rest.find("\x1b[") {
+                Some(idx) => rest.split_at(idx),
+                None => (rest, ""),
+            };
+            column = self.put_str(column, y, plain, style);
+
+            let Some(end) = escape.find(|ch: char| ('@'..='~').contains(&ch) && ch != '[') else {
+                break;
+            };
+            if escape[end..].starts_with('m') {
+                style.apply_sgr(&escape[2..end], base);
+            }
+            rest = &escape[end + 1..];
+        }
+        column
+    }
+
+    pub fn row_text(&self, y: u16) -> String {
+        (1..=self.width)
+            .filter_map(|x| self.get(x, y))
+            .map(|cell| cell.symbol.as_str())
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerminalCursor {
+    pub position: (u16, u16),
+    pub form: CursorForm,
+}
+
+// `front` mirrors what the terminal shows, `back` is the frame being drawn.
+pub struct Screen {
+    front: Grid,
+    back: Grid,
+    invalidated: bool,
+}
+
+impl Screen {
+    pub fn new(size: (u16, u16)) -> Self {
+        Screen {
+            front: Grid::new(size),
+            back: Grid::new(size),
+            invalidated: true,
+        }
+    }
+
+    pub fn resize(&mut self, size: (u16, u16)) {
+        if self.back.size() != size {
+            self.front = Grid::new(size);
+            self.back = Grid::new(size);
+            self.invalidated = true;
+        }
+    }
+
+    // The next flush clears the terminal and repaints every cell.
+    pub fn invalidate(&mut self) {
+        self.invalidated = true;
+    }
+
+    pub fn front(&self) -> &Grid {
+        &self.front
+    }
+
+    pub fn back_mut(&mut self) -> &mut Grid {
+        &mut self.back
+    }
+
+    pub fn flush(
+        &mut self,
+        backend: &mut dyn Backend,
+        cursor: Option<&TerminalCursor>,
+    ) -> io::Result<()> {
+        backend.set_cursor(None)?;
+
+        if self.invalidated {
+            backend.clear()?;
+            self.front.clear();
+            self.invalidated = false;
+        }
+
+        let (width, height) = self.back.size();
+        let mut changes: Vec<(u16, u16, Cell)> = vec![];
+
+        for y in 1..=height {
+            let mut next: Option<u16> = None;
+            for x in 1..=width {
+                let cell = self.back.get(x, y).unwrap();
+                if self.front.get(x, y) == Some(cell) {
+                    continue;
+                }
+
+                // The glyph of a wide grapheme is drawn from its first cell.
+                let head = if cell.is_continuation() && x > 1 {
+                    x - 1
+                } else {
+                    x
+                };
+                let from = match next {
+                    Some(next) if head < next + MAX_REWRITE_GAP => next,
+                    _ => head,
+                };
+                for column in from..=x {
+                    changes.push((column, y, self.back.get(column, y).unwrap().clone()));
+                }
+                next = Some(x + 1);
+            }
+        }
+
+        backend.draw(&changes)?;
+        backend.set_cursor(cursor)?;
+
+        self.front.clone_from(&self.back);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::backend::TestBackend;
+
+    fn flush(screen: &mut Screen, backend: &TestBackend) -> Vec<(u16, u16)> {
+        let mut terminal = backend.clone();
+        screen.flush(&mut terminal, None).unwrap();
+        backend.take_drawn()
+    }
+
+    #[test]
+    fn flush_draws_only_what_changed() {
+        let backend = TestBackend::new((10, 2));
+        let mut screen = Screen::new((10, 2));
+        screen
+            .back_mut()
+            .put_str(1, 1, "hello", CellStyle::default());
+        assert_eq!(flush(&mut screen, &backend).len(), 5);
+        assert_eq!(backend.row_text(1), "hello     ");
+
+        assert!(flush(&mut screen, &backend).is_empty());
+
+        screen.back_mut().put_str(2, 1, "a", CellStyle::default());
+        assert_eq!(flush(&mut screen, &backend), vec![(2, 1)]);
+        assert_eq!(backend.row_text(1), "hallo     ");
+
+        // A short run of unchanged cells is rewritten instead of skipped.
+        screen.back_mut().put_str(1, 2, "x", CellStyle::default());
+        screen.back_mut().put_str(4, 2, "y", CellStyle::default());
+        assert_eq!(
+            flush(&mut screen, &backend),
+            vec![(1, 2), (2, 2), (3, 2), (4, 2)]
+        );
+        assert_eq!(backend.row_text(2), "x  y      ");
+
+        // After a clear, every cell that isn't blank is drawn again.
+        screen.invalidate();
+        assert_eq!(flush(&mut screen, &backend).len(), 9);
+        assert_eq!(backend.row_text(1), "hallo     ");
+    }
+
+    #[test]
+    fn wide_graphemes_take_two_cells() {
+        let mut grid = Grid::new((6, 1));
+        assert_eq!(grid.put_str(1, 1, "a界b", CellStyle::default()), 5);
+        assert_eq!(grid.get(2, 1).unwrap().symbol, "界");
+        assert!(grid.get(3, 1).unwrap().is_continuation());
+        assert_eq!(grid.row_text(1), "a界b  ");
+
+        // Cut by the right edge, it becomes a space.
+        grid.put_str(6, 1, "界", CellStyle::default());
+        assert_eq!(grid.get(6, 1).unwrap().symbol, " ");
+
+        let backend = TestBackend::new((6, 1));
+        let mut screen = Screen::new((6, 1));
+        screen
+            .back_mut()
+            .put_str(1, 1, "a界b", CellStyle::default());
+        flush(&mut screen, &backend);
+        screen.back_mut().put_str(2, 1, "世", CellStyle::default());
+        assert_eq!(flush(&mut screen, &backend), vec![(2, 1)]);
+        assert_eq!(backend.row_text(1), "a世b  ");
+
+        // The glyph is drawn from its first cell even when only the second changed.
+        let reversed = CellStyle {
+            reverse: true,
+            ..Default::default()
+        };
+        screen.back_mut().set(3, 1, Cell::new("", reversed));
+        assert_eq!(flush(&mut screen, &backend), vec![(2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn ansi_escapes_style_cells() {
+        let mut grid = Grid::new((20, 1));
+        let base = CellStyle::fg(CellColor::Ansi(4));
+        let end = grid.put_ansi_str(1, 1, "\x1b[31mab\x1b[0mc\x1b[1;38;2;1;2;3md\x1b[2Ke", base);
+        assert_eq!(end, 6);
+        assert_eq!(grid.row_text(1).trim_end(), "abcde");
+        assert_eq!(grid.get(1, 1).unwrap().style.fg, CellColor::Ansi(1));
+        assert_eq!(grid.get(3, 1).unwrap().style, base);
+        let bold = grid.get(4, 1).unwrap().style;
+        assert!(bold.bold);
+        assert_eq!(bold.fg, CellColor::Rgb(Rgb(1, 2, 3)));
+        // Escapes other than SGR are dropped without changing the style.
+        assert_eq!(grid.get(5, 1).unwrap().style, bold);
+    }
+}
@@ -0,0 +1,398 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    buffers::sync_focus_buffer_lines,
+    editor::{ColorRange, Editor, EditorBuffer},
+    editor_modes::EditorMode,
+    text::{to_coord, to_cursor},
+    undo::TextPosition,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+impl SearchDirection {
+    pub fn reverse(self) -> SearchDirection {
+        match self {
+            SearchDirection::Forward => SearchDirection::Backward,
+            SearchDirection::Backward => SearchDirection::Forward,
+        }
+    }
+
+    pub fn symbol(self) -> char {
+        match self {
+            SearchDirection::Forward => '/',
+            SearchDirection::Backward => '?',
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SearchState {
+    pub history: Vec<String>,
+    pub last_pattern: Option<String>,
+    pub last_direction: SearchDirection,
+    pub history_idx: Option<usize>,
+    // Where the cursor was when the prompt opened, while typing moves it.
+    pub origin: Option<(u16, u16)>,
+}
+
+impl Default for SearchState {
+    fn default() -> Self {
+        SearchState {
+            history: vec![],
+            last_pattern: None,
+            last_direction: SearchDirection::Forward,
+            history_idx: None,
+            origin: None,
+        }
+    }
+}
+
+impl SearchState {
+    pub fn remember(&mut self, pattern: &str, direction: SearchDirection) {
+        self.history.retain(|entry| entry != pattern);
+        self.history.push(pattern.to_string());
+        self.last_pattern = Some(pattern.to_string());
+        self.last_direction = direction;
+        self.history_idx = None;
+    }
+}
+
+// Smartcase: a pattern without uppercase letters matches case-insensitively.
+pub fn smartcase(pattern: &str, smart_case: bool) -> bool {
+    smart_case && !pattern.chars().any(char::is_uppercase)
+}
+
+pub fn compile_pattern(pattern: &str, smart_case: bool) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(smartcase(pattern, smart_case))
+        .build()
+}
+
+fn line_matches(buffer: &EditorBuffer, regex: &Regex, line: usize) -> Vec<(usize, usize)> {
+    let text = buffer.content.line_string(line);
+
+    regex
+        .find_iter(&text)
+        .filter(|found| !found.is_empty())
+        .map(|found| {
+            let start = text[..found.start()].chars().count();
+            (start, start + found.as_str().chars().count())
+        })
+        .collect()
+}
+
+pub fn find_match(
+    buffer: &EditorBuffer,
+    regex: &Regex,
+    from: TextPosition,
+    direction: SearchDirection,
+) -> Option<TextPosition> {
+    let total_lines = buffer.content.len_lines();
+    let (from_line, from_col) = from;
+
+    // The last iteration revisits the starting line so matches before the
+    // cursor are found after wrapping around the buffer.
+    for offset in 0..=total_lines {
+        let line = match direction {
+            SearchDirection::Forward => (from_line + offset) % total_lines,
+            SearchDirection::Backward => {
+                (from_line + total_lines - offset % total_lines) % total_lines
+            }
+        };
+        let matches = line_matches(buffer, regex, line);
+
+        let found = match direction {
+            SearchDirection::Forward => matches
+                .iter()
+                .find(|(start, _)| offset > 0 || *start > from_col),
+            SearchDirection::Backward => matches
+                .iter()
+                .rev()
+                .find(|(start, _)| offset > 0 || *start < from_col),
+        };
+
+        if let Some((start, _)) = found {
+            return Some((line, *start));
+        }
+    }
+
+    None
+}
+
+pub fn search_color_ranges(buffer: &EditorBuffer, regex: &Regex, line: usize) -> Vec<ColorRange> {
+    line_matches(buffer, regex, line)
+        .into_iter()
+        .map(|(start, end)| ColorRange {
+            range: (to_coord(start), to_coord(end)),
+            bg_color: None,
+            fg_color: None,
+            group: Some("Search".to_string()),
+        })
+        .collect()
+}
+
+pub fn word_under_cursor(buffer: &EditorBuffer) -> Option<String> {
+    let (x, y) = buffer.cursors.first()?.position;
+    let chars: Vec<char> = buffer.content.line(y as usize)?.chars().collect();
+    let is_word = |ch: &char| ch.is_alphanumeric() || *ch == '_';
+
+    let x = (x as usize).min(chars.len().checked_sub(1)?);
+    let start_search = chars[x..].iter().position(is_word)? + x;
+
+    let mut start = start_search;
+    while start > 0 && is_word(&chars[start - 1]) {
+        start -= 1;
+    }
+    let end = chars[start..]
+        .iter()
+        .position(|ch| !is_word(ch))
+        .map(|len| start + len)
+        .unwrap_or(chars.len());
+
+    Some(chars[start..end].iter().collect())
+}
+
+pub fn jump_to_match(
+    buffer: &mut EditorBuffer,
+    pattern: &str,
+    direction: SearchDirection,
+    smart_case: bool,
+) -> Result<(), String> {
+    let regex =
+        compile_pattern(pattern, smart_case).map_err(|e| format!("Invalid pattern: {}", e))?;
+    let Some(cursor) = buffer.cursors.first().map(|cursor| cursor.position) else {
+        return Ok(());
+    };
+
+    match find_match(
+        buffer,
+        &regex,
+        (cursor.1 as usize, cursor.0 as usize),
+        direction,
+    ) {
+        Some((line, col)) => {
+            buffer.cursors[0].position = to_cursor((line, col));
+            Ok(())
+        }
+        None => Err(format!("Pattern not found: {}", pattern)),
+    }
+}
+
+fn restore_origin(editor: &mut Editor) {
+    let focus_buffer = editor.focus_buffer.clone();
+    if let (Some(origin), Some(buffer)) = (
+        editor.search.origin.take(),
+        editor.buffers.get_mut(&focus_buffer),
+    ) {
+        if let Some(cursor) = buffer.cursors.first_mut() {
+            cursor.position = origin;
+        }
+    }
+}
+
+// Moves the cursor to the first match of what's typed so far, counting from
+// where the search started, or back there while nothing matches.
+pub fn preview_search(editor: &mut Editor, direction: SearchDirection) {
+    let Some(origin) = editor.search.origin else {
+        return;
+    };
+    let pattern = editor.commands_hist.last().cloned().unwrap_or_default();
+    let smart_case = editor.config.editor.smart_case;
+    let focus_buffer = editor.focus_buffer.clone();
+    if let Some(buffer) = editor.buffers.get_mut(&focus_buffer) {
+        if let Some(cursor) = buffer.cursors.first_mut() {
+            cursor.position = origin;
+        }
+        if !pattern.is_empty() {
+            let _ = jump_to_match(buffer, &pattern, direction, smart_case);
+        }
+    }
+    sync_focus_buffer_lines(editor);
+}
+
+pub fn cancel_search(editor: &mut Editor) {
+    restore_origin(editor);
+    sync_focus_buffer_lines(editor);
+}
+
+fn push_message(editor: &mut Editor, message: String) {
+    editor.commands_hist.push(message);
+    editor.commands_hist.push(String::new());
+}
+
+pub fn execute_search(editor: &mut Editor, entry: &str, direction: SearchDirection) {
+    let pattern = if entry.is_empty() {
+        editor.search.last_pattern.clone()
+    } else {
+        Some(entry.to_string())
+    };
+    editor.editor_mode = EditorMode::Normal;
+    restore_origin(editor);
+
+    let Some(pattern) = pattern else {
+        push_message(editor, "No previous search pattern".to_string());
+        return;
+    };
+
+    if let Some(last_entry) = editor.commands_hist.last_mut() {
+        *last_entry = format!("{}{}", direction.symbol(), pattern);
+    }
+    editor.search.remember(&pattern, direction);
+    editor.registers.set_last_search(&pattern);
+
+    let focus_buffer = editor.focus_buffer.clone();
+    let smart_case = editor.config.editor.smart_case;
+    let result = match editor.buffers.get_mut(&focus_buffer) {
+        Some(buffer) => jump_to_match(buffer, &pattern, direction, smart_case),
+        None => Ok(()),
+    };
+
+    match result {
+        Ok(()) => editor.commands_hist.push(String::new()),
+        Err(message) => push_message(editor, message),
+    }
+    sync_focus_buffer_lines(editor);
+}
+
+pub fn repeat_search(editor: &mut Editor, buffer: &mut EditorBuffer, reverse: bool) {
+    let Some(pattern) = editor.search.last_pattern.clone() else {
+        push_message(editor, "No previous search pattern".to_string());
+        return;
+    };
+
+    let direction = if reverse {
+        editor.search.last_direction.reverse()
+    } else {
+        editor.search.last_direction
+    };
+
+    match jump_to_match(buffer, &pattern, direction, editor.config.editor.smart_case) {
+        Ok(()) => push_message(editor, format!("{}{}", direction.symbol(), pattern)),
+        Err(message) => push_message(editor, message),
+    }
+}
+
+pub fn search_word_under_cursor(
+    editor: &mut Editor,
+    buffer: &mut EditorBuffer,
+    direction: SearchDirection,
+) {
+    let Some(word) = word_under_cursor(buffer) else {
+        push_message(editor, "No string under cursor".to_string());
+        return;
+    };
+
+    let pattern = format!(r"\b{}\b", regex::escape(&word));
+    editor.search.remember(&pattern, direction);
+    editor.registers.set_last_search(&pattern);
+
+    match jump_to_match(buffer, &pattern, direction, editor.config.editor.smart_case) {
+        Ok(()) => push_message(editor, format!("{}{}", direction.symbol(), pattern)),
+        Err(message) => push_message(editor, message),
+    }
+}
+
+pub fn browse_history(editor: &mut Editor, older: bool) {
+    let history_len = editor.search.history.len();
+    if history_len == 0 {
+        return;
+    }
+
+    let idx = match (editor.search.history_idx, older) {
+        (None, true) => Some(history_len - 1),
+        (Some(idx), true) => Some(idx.saturating_sub(1)),
+        (Some(idx), false) if idx + 1 < history_len => Some(idx + 1),
+        _ => None,
+    };
+    editor.search.history_idx = idx;
+
+    let entry = idx
+        .map(|idx| editor.search.history[idx].clone())
+        .unwrap_or_default();
+    if let Some(last_entry) = editor.commands_hist.last_mut() {
+        *last_entry = entry;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{cursor, editor_with_text, type_keys};
+
+    #[test]
+    fn matches_wrap_around_the_buffer() {
+        let (editor, _) = editor_with_text("matches_wrap_around_the_buffer", "ab x\ncd\nx ef x");
+        let buffer = editor.get_buffer(&editor.focus_buffer).unwrap();
+        let regex = compile_pattern("x", false).unwrap();
+        let forward = SearchDirection::Forward;
+        let backward = SearchDirection::Backward;
+
+        assert_eq!(find_match(buffer, &regex, (0, 3), forward), Some((2, 0)));
+        assert_eq!(find_match(buffer, &regex, (2, 5), forward), Some((0, 3)));
+        assert_eq!(find_match(buffer, &regex, (0, 3), backward), Some((2, 5)));
+        assert_eq!(find_match(buffer, &regex, (2, 0), backward), Some((0, 3)));
+
+        // The only match is found again from itself, after a full turn.
+        let regex = compile_pattern("cd", false).unwrap();
+        assert_eq!(find_match(buffer, &regex, (1, 0), forward), Some((1, 0)));
+        assert_eq!(find_match(buffer, &regex, (1, 0), backward), Some((1, 0)));
+        let regex = compile_pattern("zz", false).unwrap();
+        assert_eq!(find_match(buffer, &regex, (1, 0), forward), None);
+    }
+
+    #[test]
+    fn smartcase_follows_uppercase_in_the_pattern() {
+        assert!(compile_pattern("foo", true).unwrap().is_match("FOO"));
+        assert!(!compile_pattern("Foo", true).unwrap().is_match("FOO"));
+        assert!(!compile_pattern("foo", false).unwrap().is_match("FOO"));
+
+        let (mut editor, _) = editor_with_text("smartcase_search", "Foo foo FOO");
+        type_keys(&mut editor, "/FOO<Enter>");
+        assert_eq!(cursor(&editor), (8, 0));
+        type_keys(&mut editor, "/foo<Enter>");
+        assert_eq!(cursor(&editor), (0, 0));
+    }
+
+    #[test]
+    fn star_and_hash_match_whole_words() {
+        let text = "foo foobar foo_x\nbar foo";
+        let (mut editor, _) = editor_with_text("star_and_hash_match_whole_words", text);
+        type_keys(&mut editor, "*");
+        assert_eq!(cursor(&editor), (4, 1));
+        type_keys(&mut editor, "*");
+        assert_eq!(cursor(&editor), (0, 0));
+        type_keys(&mut editor, "#");
+        assert_eq!(cursor(&editor), (4, 1));
+        type_keys(&mut editor, "n");
+        assert_eq!(cursor(&editor), (0, 0));
+
+        // From a blank the next word on the line is used.
+        type_keys(&mut editor, "03l*");
+        assert_eq!(cursor(&editor), (4, 0));
+    }
+
+    #[test]
+    fn typing_a_pattern_moves_the_cursor() {
+        let (mut editor, _) =
+            editor_with_text("typing_a_pattern_moves_the_cursor", "abc\nbcd\nabd");
+        type_keys(&mut editor, "/b");
+        assert_eq!(cursor(&editor), (1, 0));
+        type_keys(&mut editor, "d");
+        assert_eq!(cursor(&editor), (1, 2));
+        type_keys(&mut editor, "x");
+        assert_eq!(cursor(&editor), (0, 0));
+        type_keys(&mut editor, "<BS><Esc>");
+        assert_eq!(editor.editor_mode, EditorMode::Normal);
+        assert_eq!(cursor(&editor), (0, 0));
+
+        type_keys(&mut editor, "?b<Enter>");
+        assert_eq!(cursor(&editor), (1, 2));
+        type_keys(&mut editor, "/bd<Enter>");
+        assert_eq!(cursor(&editor), (1, 2));
+    }
+}
@@ -0,0 +1,395 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{
+    buffers::sync_focus_buffer_lines,
+    command::{error_message, push_message, run_command_from},
+    editor::{ColorRange, Editor, EditorBuffer, EditorCursor, EditorWindow},
+    editor_modes::EditorMode,
+    layout::floating::FloatingWindow,
+    server::{
+        plugin_buffers::{
+            close_float, destroy_plugin_buffer, focus_float, open_float, set_plugin_buffer,
+        },
+        server_messages::{BufferMessage, EditorEventKind},
+    },
+};
+
+// Positions are (x, y) like cursors: a char column and a zero-based line.
+// Without a `buffer_name` the message applies to the focused buffer.
+
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ClientHelloMessage {
+    pub protocol_version: u32,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct SetContentMessage {
+    pub buffer_name: Option<String>,
+    pub content: Vec<String>,
+}
+
+// Replaces the text between `start` and `end` (exclusive).
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct SetTextMessage {
+    pub buffer_name: Option<String>,
+    pub start: (u16, u16),
+    pub end: (u16, u16),
+    pub text: String,
+}
+
+// One list of ranges per line, replacing the colors the buffer had.
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct SetColorsMessage {
+    pub buffer_name: Option<String>,
+    pub colors: Vec<Vec<ColorRange>>,
+}
+
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct SetCursorsMessage {
+    pub buffer_name: Option<String>,
+    pub positions: Vec<(u16, u16)>,
+}
+
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ExecuteCommandMessage {
+    pub command: String,
+}
+
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct RegisterCommandMessage {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct RegisterKeymapMessage {
+    pub mode: EditorMode,
+    pub keys: String,
+}
+
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct SubscribeMessage {
+    pub events: Vec<EditorEventKind>,
+}
+
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct PluginBufferMessage {
+    pub buffer_name: String,
+}
+
+// `window` covers the border too. Opening a buffer that already floats moves it.
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct OpenFloatMessage {
+    pub buffer_name: String,
+    pub window: EditorWindow,
+    pub z_index: i32,
+    pub border: bool,
+    pub title: Option<String>,
+    pub focus: bool,
+}
+
+// The user can always take the keys back from a float with `<C-c>`, which
+// the plugin only sees as an `input` event.
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct FocusFloatMessage {
+    pub buffer_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ShowMessageMessage {
+    pub text: String,
+    pub error: bool,
+}
+
+#[derive(Serialize, Deserialize, TS)]
+#[serde(tag = "message_type", content = "info", rename_all = "camelCase")]
+#[ts(export)]
+pub enum ClientMessages {
+    Hello(ClientHelloMessage),
+    SetContent(SetContentMessage),
+    SetText(SetTextMessage),
+    SetColors(SetColorsMessage),
+    SetCursors(SetCursorsMessage),
+    ExecuteCommand(ExecuteCommandMessage),
+    RegisterCommand(RegisterCommandMessage),
+    RegisterKeymap(RegisterKeymapMessage),
+    ShowMessage(ShowMessageMessage),
+    Subscribe(SubscribeMessage),
+    Unsubscribe(SubscribeMessage),
+    // Plugin buffers are named "prefix:name" and live until destroyed or
+    // until their plugin disconnects.
+    Buffer(BufferMessage),
+    DestroyBuffer(PluginBufferMessage),
+    OpenFloat(OpenFloatMessage),
+    CloseFloat(PluginBufferMessage),
+    FocusFloat(FocusFloatMessage),
+}
+
+// The editor answers each request with a `ResponseMessage` carrying its id.
+#[derive(Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ClientRequest {
+    pub request_id: String,
+    pub message: ClientMessages,
+}
+
+// Any plugin may edit file buffers, plugin buffers only take changes from
+// the plugin that made them.
+fn target_buffer<'a>(
+    editor: &'a mut Editor,
+    client_id: usize,
+    buffer_name: &Option<String>,
+) -> Result<&'a mut EditorBuffer, String> {
+    let key = buffer_name
+        .clone()
+        .unwrap_or_else(|| editor.focus_buffer.clone());
+    if editor
+        .plugins
+        .owner_of(&key)
+        .is_some_and(|owner| owner != client_id)
+    {
+        return Err(format!("Buffer {} belongs to another plugin", key));
+    }
+    editor
+        .get_buffer_mut(&key)
+        .ok_or(format!("No such buffer: {}", key))
+}
+
+fn text_position(buffer: &EditorBuffer, position: (u16, u16)) -> Result<(usize, usize), String> {
+    let (col, line) = (position.0 as usize, position.1 as usize);
+    if line >= buffer.content.len_lines() || col > buffer.line_len(line) {
+        return Err(format!(
+            "Invalid position: ({}, {})",
+            position.0, position.1
+        ));
+    }
+    Ok((line, col))
+}
+
+fn set_content(buffer: &mut EditorBuffer, content: &[String]) {
+    let last_line = buffer.content.len_lines() - 1;
+    buffer.delete_text((0, 0), (last_line, buffer.line_len(last_line)));
+    buffer.insert_text((0, 0), &content.join("\n"));
+    buffer.commit_undo();
+    buffer.clamp_cursors();
+}
+
+fn set_text(buffer: &mut EditorBuffer, message: &SetTextMessage) -> Result<(), String> {
+    let start = text_position(buffer, message.start)?;
+    let end = text_position(buffer, message.end)?;
+    if end < start {
+        return Err("Invalid range: end is before start".to_string());
+    }
+
+    buffer.delete_text(start, end);
+    buffer.insert_text(start, &message.text);
+    buffer.commit_undo();
+    buffer.clamp_cursors();
+    Ok(())
+}
+
+fn set_cursors(buffer: &mut EditorBuffer, positions: &[(u16, u16)]) -> Result<(), String> {
+    if positions.is_empty() {
+        return Err("A buffer needs at least one cursor".to_string());
+    }
+    for position in positions {
+        text_position(buffer, *position)?;
+    }
+
+    let form = buffer.cursors[0].form.clone();
+    buffer.cursors = positions
+        .iter()
+        .map(|position| EditorCursor {
+            position: *position,
+            form: form.clone(),
+        })
+        .collect();
+    buffer.clamp_cursors();
+    Ok(())
+}
+
+// `client_id` is the plugin that sent the message, see `Plugins`.
+pub fn apply_client_message(
+    editor: &mut Editor,
+    client_id: usize,
+    message: ClientMessages,
+) -> Result<(), String> {
+    match message {
+        // Answered by the server while connecting.
+        ClientMessages::Hello(_) => return Err("Already connected".to_string()),
+        ClientMessages::SetContent(message) => {
+            set_content(
+                target_buffer(editor, client_id, &message.buffer_name)?,
+                &message.content,
+            );
+        }
+        ClientMessages::SetText(message) => {
+            set_text(
+                target_buffer(editor, client_id, &message.buffer_name)?,
+                &message,
+            )?;
+        }
+        ClientMessages::SetColors(message) => {
+            target_buffer(editor, client_id, &message.buffer_name)?.colors = message.colors;
+        }
+        ClientMessages::SetCursors(message) => {
+            set_cursors(
+                target_buffer(editor, client_id, &message.buffer_name)?,
+                &message.positions,
+            )?;
+        }
+        ClientMessages::ExecuteCommand(message) => {
+            run_command_from(editor, &message.command)?;
+        }
+        ClientMessages::RegisterCommand(message) => {
+            editor.plugins.register_command(client_id, &message.name)?;
+        }
+        ClientMessages::RegisterKeymap(message) => {
+            editor
+                .plugins
+                .register_keymap(client_id, message.mode, &message.keys)?;
+        }
+        ClientMessages::ShowMessage(message) => {
+            let text = if message.error {
+                error_message(&message.text)
+            } else {
+                message.text
+            };
+            push_message(editor, text);
+        }
+        ClientMessages::Subscribe(message) => {
+            editor.plugins.subscribe(client_id, &message.events)?;
+        }
+        ClientMessages::Unsubscribe(message) => {
+            editor.plugins.unsubscribe(client_id, &message.events)?;
+        }
+        ClientMessages::Buffer(message) => {
+            set_plugin_buffer(editor, client_id, message)?;
+        }
+        ClientMessages::DestroyBuffer(message) => {
+            destroy_plugin_buffer(editor, client_id, &message.buffer_name)?;
+        }
+        ClientMessages::OpenFloat(message) => {
+            let float = FloatingWindow {
+                buffer: message.buffer_name,
+                window: message.window,
+                z_index: message.z_index,
+                border: message.border,
+                title: message.title,
+            };
+            open_float(editor, client_id, float, message.focus)?;
+        }
+        ClientMessages::CloseFloat(message) => {
+            close_float(editor, client_id, &message.buffer_name)?;
+        }
+        ClientMessages::FocusFloat(message) => {
+            focus_float(editor, client_id, message.buffer_name)?;
+        }
+    }
+
+    sync_focus_buffer_lines(editor);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc};
+
+    use super::*;
+    use crate::editor::HandleKeysFn;
+    use crate::server::plugins::plugin_connected;
+    use crate::testing::{buffer_text, editor_with_text};
+
+    fn set_content(buffer_name: Option<&str>, text: &str) -> ClientMessages {
+        ClientMessages::SetContent(SetContentMessage {
+            buffer_name: buffer_name.map(String::from),
+            content: vec![text.to_string()],
+        })
+    }
+
+    #[test]
+    fn plugins_only_change_their_own_buffers() {
+        let (mut editor, _) = editor_with_text("plugins_only_change_their_own", "text\n");
+        let owner = plugin_connected(&mut editor, "owner", mpsc::channel().0);
+        let other = plugin_connected(&mut editor, "other", mpsc::channel().0);
+        let handle_keys: HandleKeysFn = Arc::new(|_: &mut Editor, _: &str| {});
+        let window = EditorWindow {
+            start: (1, 1),
+            end: (20, 5),
+        };
+        editor.add_buffer(
+            "owner:list".to_string(),
+            EditorBuffer::new(handle_keys, window, 4),
+        );
+        editor.plugins.claim_buffer(owner, "owner:list");
+        let list_text = |editor: &Editor| {
+            editor
+                .get_buffer("owner:list")
+                .unwrap()
+                .content
+                .to_lines()
+                .join("\n")
+        };
+
+        let refused =
+            apply_client_message(&mut editor, other, set_content(Some("owner:list"), "x"));
+        assert_eq!(
+            refused,
+            Err("Buffer owner:list belongs to another plugin".to_string())
+        );
+        let set_text = ClientMessages::SetText(SetTextMessage {
+            buffer_name: Some("owner:list".to_string()),
+            start: (0, 0),
+            end: (0, 0),
+            text: "x".to_string(),
+        });
+        assert!(apply_client_message(&mut editor, other, set_text).is_err());
+        let set_cursors = ClientMessages::SetCursors(SetCursorsMessage {
+            buffer_name: Some("owner:list".to_string()),
+            positions: vec![(0, 0)],
+        });
+        assert!(apply_client_message(&mut editor, other, set_cursors).is_err());
+        assert_eq!(list_text(&editor), "");
+
+        let unfocus = || ClientMessages::FocusFloat(FocusFloatMessage { buffer_name: None });
+        editor.floating.focus = Some("owner:list".to_string());
+        assert!(apply_client_message(&mut editor, other, unfocus()).is_err());
+        apply_client_message(&mut editor, owner, unfocus()).unwrap();
+        assert_eq!(editor.floating.focus, None);
+
+        apply_client_message(&mut editor, owner, set_content(Some("owner:list"), "mine")).unwrap();
+        assert_eq!(list_text(&editor), "mine");
+
+        // File buffers stay open to every plugin.
+        apply_client_message(&mut editor, other, set_content(None, "edited")).unwrap();
+        assert_eq!(buffer_text(&editor), "edited");
+    }
+}
@@ -0,0 +1,200 @@
+use std::io::Read;
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::editor::Editor;
+use crate::server::client_messages::{apply_client_message, ClientMessages, ClientRequest};
+use crate::server::events::publish_events;
+use crate::server::framing::FrameDecoder;
+use crate::server::plugins::{plugin_connected, plugin_disconnected};
+use crate::server::server_messages::{
+    ContextMessage, HelloMessage, ResponseMessage, ServerMessages,
+};
+use crate::server::{send_message, PROTOCOL_VERSION};
+use crate::EditorMessage;
+
+struct Connection {
+    editor_ref: Arc<Mutex<Editor>>,
+    tx: mpsc::Sender<EditorMessage>,
+    plugin_tx: mpsc::Sender<ServerMessages>,
+    // Set once the plugin answered the hello with a version we speak.
+    client_id: Option<usize>,
+}
+
+impl Connection {
+    fn respond(&self, request_id: Option<String>, result: Result<(), String>) {
+        let _ = self
+            .plugin_tx
+            .send(ServerMessages::Response(ResponseMessage {
+                request_id,
+                error: result.err(),
+            }));
+    }
+
+    fn handshake(&mut self, message: ClientMessages) -> Result<(), String> {
+        let ClientMessages::Hello(hello) = message else {
+            return Err("Expected a hello request first".to_string());
+        };
+        if hello.protocol_version != PROTOCOL_VERSION {
+            return Err(format!(
+                "Unsupported protocol version {}, the editor speaks {}",
+                hello.protocol_version, PROTOCOL_VERSION
+            ));
+        }
+
+        let mut editor = self.editor_ref.lock().unwrap();
+        // Events from now on are relative to the state in the context below.
+        publish_events(&mut editor);
+        self.client_id = Some(plugin_connected(
+            &mut editor,
+            &hello.name,
+            self.plugin_tx.clone(),
+        ));
+        let _ = self.plugin_tx.send(ServerMessages::Context(ContextMessage {
+            editor: editor.to_editor_context(),
+        }));
+        Ok(())
+    }
+
+    // Returns false when the connection should be closed.
+    fn handle_frame(&mut self, frame: &[u8]) -> bool {
+        // Parsed in two steps so a request that doesn't fit still gets its id back.
+        let value: serde_json::Value = match serde_json::from_slice(frame) {
+            Ok(value) => value,
+            Err(e) => {
+                self.respond(None, Err(format!("Parse error: {}", e)));
+                return true;
+            }
+        };
+        let request_id = value
+            .get("requestId")
+            .and_then(|id| id.as_str())
+            .map(String::from);
+        let request: ClientRequest = match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => {
+                self.respond(request_id, Err(format!("Invalid request: {}", e)));
+                return true;
+            }
+        };
+
+        let Some(client_id) = self.client_id else {
+            let result = self.handshake(request.message);
+            let accepted = result.is_ok();
+            self.respond(Some(request.request_id), result);
+            if accepted {
+                let _ = self.tx.send(EditorMessage::Render(String::new()));
+            }
+            return accepted;
+        };
+
+        let (result, close) = {
+            let mut editor = self.editor_ref.lock().unwrap();
+            let result = apply_client_message(&mut editor, client_id, request.message);
+            publish_events(&mut editor);
+            (result, editor.close)
+        };
+        self.respond(Some(request.request_id), result);
+        // A `:q` run by the plugin ends the editor like one typed by the user.
+        let message = if close {
+            EditorMessage::Close
+        } else {
+            EditorMessage::Render(String::new())
+        };
+        let _ = self.tx.send(message);
+        !close
+    }
+}
+
+// Talks to one plugin until it disconnects.
+pub fn serve(
+    mut stream: UnixStream,
+    tx: mpsc::Sender<EditorMessage>,
+    editor_ref: Arc<Mutex<Editor>>,
+) {
+    // Replies and notifications are written by their own thread, so the
+    // editor can reach the plugin while this one waits for input.
+    let (plugin_tx, plugin_rx) = mpsc::channel::<ServerMessages>();
+    let mut writer = stream.try_clone().expect("Failed to clone plugin socket.");
+    thread::spawn(move || {
+        for message in plugin_rx {
+            if send_message(&mut writer, message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let _ = plugin_tx.send(ServerMessages::Hello(HelloMessage {
+        protocol_version: PROTOCOL_VERSION,
+    }));
+    let mut connection = Connection {
+        editor_ref,
+        tx,
+        plugin_tx,
+        client_id: None,
+    };
+
+    let mut decoder = FrameDecoder::default();
+    let mut buffer = [0; 4096];
+    'read: loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                decoder.push(&buffer[0..n]);
+                while let Some(frame) = decoder.next_frame() {
+                    let keep_open = match frame {
+                        Ok(frame) => connection.handle_frame(&frame),
+                        Err(e) => {
+                            connection.respond(None, Err(e));
+                            true
+                        }
+                    };
+                    if !keep_open {
+                        break 'read;
+                    }
+                }
+            }
+            Err(_) => break,
+        }
+    }
+
+    if let Some(client_id) = connection.client_id {
+        plugin_disconnected(&mut connection.editor_ref.lock().unwrap(), client_id);
+        let _ = connection.tx.send(EditorMessage::Render(String::new()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::editor_with_text;
+
+    #[test]
+    fn quit_from_a_plugin_closes_the_editor() {
+        let (mut editor, _backend) = editor_with_text("plugin-quit", "text\n");
+        let (plugin_tx, plugin_rx) = mpsc::channel();
+        let client_id = plugin_connected(&mut editor, "test", plugin_tx.clone());
+        let (tx, rx) = mpsc::channel();
+        let mut connection = Connection {
+            editor_ref: Arc::new(Mutex::new(editor)),
+            tx,
+            plugin_tx,
+            client_id: Some(client_id),
+        };
+
+        let frame = br#"{"requestId":"1","message":{"message_type":"executeCommand","info":{"command":"q"}}}"#;
+        assert!(!connection.handle_frame(frame));
+
+        assert!(connection.editor_ref.lock().unwrap().close);
+        assert!(matches!(rx.try_recv(), Ok(EditorMessage::Close)));
+        assert!(matches!(
+            plugin_rx.try_recv(),
+            Ok(ServerMessages::Response(ResponseMessage {
+                error: None,
+                ..
+            }))
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+use std::mem;
+
+use crate::{
+    editor::Editor,
+    editor_modes::EditorMode,
+    server::server_messages::{
+        BufferEventMessage, CursorMovedMessage, ModeChangedMessage, ServerMessages, TextChange,
+        TextChangedMessage,
+    },
+    text::{to_cursor, TextRope},
+    undo::EditOperation,
+};
+
+// Recorded by a buffer as it changes, `publish_events` turns them into events.
+#[derive(Clone)]
+pub enum BufferNotice {
+    Changed(TextChange),
+    Saved,
+}
+
+// What plugins were last told, so only what changed since is sent.
+#[derive(Default)]
+pub struct ObservedState {
+    mode: Option<EditorMode>,
+    cursors: Option<(String, Vec<(u16, u16)>)>,
+}
+
+pub fn text_change(operation: &EditOperation) -> TextChange {
+    match operation {
+        EditOperation::Insert { position: at, text } => TextChange {
+            start: to_cursor(*at),
+            end: to_cursor(*at),
+            text: text.clone(),
+        },
+        EditOperation::Delete { position: at, text } => TextChange {
+            start: to_cursor(*at),
+            end: to_cursor(TextRope::end_of_text(*at, text)),
+            text: String::new(),
+        },
+    }
+}
+
+pub fn buffer_event(editor: &Editor, key: &str) -> BufferEventMessage {
+    BufferEventMessage {
+        buffer_name: key.to_string(),
+        file_name: editor
+            .get_buffer(key)
+            .and_then(|buffer| buffer.file_name.clone()),
+    }
+}
+
+// Sends what happened since the last call: text changes and saves of every
+// buffer, then mode and cursor moves.
+pub fn publish_events(editor: &mut Editor) {
+    let mut messages = vec![];
+
+    let mut keys: Vec<String> = editor.buffers.keys().cloned().collect();
+    keys.sort();
+    for key in keys {
+        let Some(buffer) = editor.get_buffer_mut(&key) else {
+            continue;
+        };
+        let notices = mem::take(&mut buffer.notices);
+        let file_name = buffer.file_name.clone();
+
+        let mut changes = vec![];
+        for notice in notices {
+            match notice {
+                BufferNotice::Changed(change) => changes.push(change),
+                BufferNotice::Saved => {
+                    if !changes.is_empty() {
+                        messages.push(ServerMessages::TextChanged(TextChangedMessage {
+                            buffer_name: key.clone(),
+                            changes: mem::take(&mut changes),
+                        }));
+                    }
+                    messages.push(ServerMessages::BufferSaved(BufferEventMessage {
+                        buffer_name: key.clone(),
+                        file_name: file_name.clone(),
+                    }));
+                }
+            }
+        }
+        if !changes.is_empty() {
+            messages.push(ServerMessages::TextChanged(TextChangedMessage {
+                buffer_name: key.clone(),
+                changes,
+            }));
+        }
+    }
+
+    let mode = editor.editor_mode;
+    let observed = &mut editor.plugins.observed;
+    if let Some(from) = observed.mode.filter(|from| *from != mode) {
+        messages.push(ServerMessages::ModeChanged(ModeChangedMessage {
+            from,
+            to: mode,
+        }));
+    }
+    observed.mode = Some(mode);
+
+    let cursors = editor
+        .get_buffer(&editor.focus_buffer)
+        .map(|buffer| (editor.focus_buffer.clone(), buffer.cursor_positions()));
+    let observed = &mut editor.plugins.observed;
+    if observed.cursors.is_some() && observed.cursors != cursors {
+        if let Some((buffer_name, positions)) = cursors.clone() {
+            messages.push(ServerMessages::CursorMoved(CursorMovedMessage {
+                buffer_name,
+                positions,
+            }));
+        }
+    }
+    observed.cursors = cursors;
+
+    for message in messages {
+        editor.plugins.publish(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver};
+
+    use super::*;
+    use crate::server::plugins::plugin_connected;
+    use crate::server::server_messages::EditorEventKind;
+    use crate::testing::{buffer_text, editor_with_text, type_keys};
+
+    // A plugin that subscribed to `events`, what it is sent arrives on the receiver.
+    fn fake_client(
+        editor: &mut Editor,
+        name: &str,
+        events: &[EditorEventKind],
+    ) -> Receiver<ServerMessages> {
+        let (tx, rx) = mpsc::channel();
+        let id = plugin_connected(editor, name, tx);
+        editor.plugins.subscribe(id, events).unwrap();
+        rx
+    }
+
+    fn kinds(rx: &Receiver<ServerMessages>) -> Vec<EditorEventKind> {
+        rx.try_iter()
+            .filter_map(|message| message.event_kind())
+            .collect()
+    }
+
+    // Applies the text changes sent to `rx` to `shadow`, returns every event kind.
+    fn replay(rx: &Receiver<ServerMessages>, shadow: &mut TextRope) -> Vec<EditorEventKind> {
+        let mut kinds = vec![];
+        for message in rx.try_iter() {
+            kinds.extend(message.event_kind());
+            let ServerMessages::TextChanged(changed) = message else {
+                continue;
+            };
+            for change in changed.changes {
+                let start = (change.start.1 as usize, change.start.0 as usize);
+                let end = (change.end.1 as usize, change.end.0 as usize);
+                shadow.remove(start, end);
+                shadow.insert(start, &change.text);
+            }
+        }
+        kinds
+    }
+
+    #[test]
+    fn plugins_get_the_events_they_subscribed_to() {
+        let (mut editor, _) = editor_with_text("plugins_get_their_events", "one\ntwo\n");
+        let text = fake_client(&mut editor, "text", &[EditorEventKind::TextChanged]);
+        let moves = fake_client(
+            &mut editor,
+            "moves",
+            &[EditorEventKind::ModeChanged, EditorEventKind::CursorMoved],
+        );
+        let input = fake_client(&mut editor, "input", &[EditorEventKind::Input]);
+        let none = fake_client(&mut editor, "none", &[]);
+        publish_events(&mut editor);
+
+        type_keys(&mut editor, "j");
+        assert_eq!(kinds(&text), vec![]);
+        assert_eq!(kinds(&moves), vec![EditorEventKind::CursorMoved]);
+        assert_eq!(kinds(&input), vec![EditorEventKind::Input]);
+
+        type_keys(&mut editor, "ix<Esc>");
+        assert_eq!(kinds(&text), vec![EditorEventKind::TextChanged]);
+        assert_eq!(
+            kinds(&moves),
+            vec![
+                EditorEventKind::ModeChanged,
+                EditorEventKind::CursorMoved,
+                EditorEventKind::ModeChanged,
+            ]
+        );
+        assert_eq!(kinds(&input), vec![EditorEventKind::Input; 3]);
+        assert_eq!(kinds(&none), vec![]);
+    }
+
+    #[test]
+    fn text_changes_replay_to_the_buffer() {
+        let original = "one\ntwo\nthree";
+        let (mut editor, _) = editor_with_text("text_changes_replay", original);
+        let rx = fake_client(
+            &mut editor,
+            "text",
+            &[EditorEventKind::TextChanged, EditorEventKind::BufferSaved],
+        );
+
+        let mut shadow = TextRope::from_text(original);
+
+        type_keys(&mut editor, "ix<Enter>y<Esc>jddkyyGp");
+        type_keys(&mut editor, ":%s/o/0/g<Enter>u");
+        replay(&rx, &mut shadow);
+        assert_eq!(shadow.to_lines().join("\n"), buffer_text(&editor));
+
+        // Changes made before a save are sent before it.
+        type_keys(&mut editor, "iz<Esc>:w<Enter>");
+        let kinds = replay(&rx, &mut shadow);
+        assert_eq!(
+            kinds,
+            vec![EditorEventKind::TextChanged, EditorEventKind::BufferSaved]
+        );
+        assert_eq!(shadow.to_lines().join("\n"), buffer_text(&editor));
+    }
+}
@@ -0,0 +1,106 @@
+use std::io;
+
+use serde::Serialize;
+
+// Messages are newline-delimited JSON: compact JSON never contains a raw
+// newline, so each line is exactly one message.
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+pub fn encode_frame(message: &impl Serialize) -> io::Result<Vec<u8>> {
+    let mut bytes = serde_json::to_vec(message)?;
+    bytes.push(b'\n');
+    Ok(bytes)
+}
+
+// Collects bytes as they arrive and hands out whole lines, however the
+// reads split them.
+#[derive(Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    // How much of `buffer` is known to hold no newline, so a long message
+    // arriving in many reads isn't searched again from the start each time.
+    scanned: usize,
+    // Dropping the rest of a line that grew past `MAX_FRAME_LEN`.
+    discarding: bool,
+}
+
+impl FrameDecoder {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn next_frame(&mut self) -> Option<Result<Vec<u8>, String>> {
+        loop {
+            let newline = self.buffer[self.scanned..]
+                .iter()
+                .position(|&byte| byte == b'\n');
+            let Some(end) = newline.map(|offset| self.scanned + offset) else {
+                self.scanned = self.buffer.len();
+                if !self.discarding && self.buffer.len() > MAX_FRAME_LEN {
+                    self.buffer.clear();
+                    self.scanned = 0;
+                    self.discarding = true;
+                    return Some(Err(format!("Message longer than {} bytes", MAX_FRAME_LEN)));
+                }
+                if self.discarding {
+                    self.buffer.clear();
+                    self.scanned = 0;
+                }
+                return None;
+            };
+
+            let mut frame: Vec<u8> = self.buffer.drain(..=end).collect();
+            self.scanned = 0;
+            frame.pop();
+            if frame.last() == Some(&b'\r') {
+                frame.pop();
+            }
+            if std::mem::take(&mut self.discarding) || frame.is_empty() {
+                continue;
+            }
+            if frame.len() > MAX_FRAME_LEN {
+                return Some(Err(format!("Message longer than {} bytes", MAX_FRAME_LEN)));
+            }
+            return Some(Ok(frame));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(decoder: &mut FrameDecoder) -> Vec<Result<Vec<u8>, String>> {
+        std::iter::from_fn(|| decoder.next_frame()).collect()
+    }
+
+    #[test]
+    fn frames_split_across_reads() {
+        let mut decoder = FrameDecoder::default();
+        decoder.push(b"{\"a\"");
+        assert!(frames(&mut decoder).is_empty());
+        decoder.push(b":1}");
+        assert!(frames(&mut decoder).is_empty());
+        decoder.push(b"\r\n\n{}\n{\"b\"");
+        assert_eq!(
+            frames(&mut decoder),
+            vec![Ok(b"{\"a\":1}".to_vec()), Ok(b"{}".to_vec())]
+        );
+        decoder.push(b":2}\n");
+        assert_eq!(frames(&mut decoder), vec![Ok(b"{\"b\":2}".to_vec())]);
+    }
+
+    #[test]
+    fn long_lines_are_dropped() {
+        let mut decoder = FrameDecoder::default();
+        let chunk = vec![b'x'; MAX_FRAME_LEN / 2];
+        decoder.push(&chunk);
+        decoder.push(&chunk);
+        assert!(frames(&mut decoder).is_empty());
+        decoder.push(b"x");
+        assert_eq!(frames(&mut decoder).len(), 1);
+        decoder.push(&chunk);
+        decoder.push(b"tail\n{}\n");
+        assert_eq!(frames(&mut decoder), vec![Ok(b"{}".to_vec())]);
+    }
+}
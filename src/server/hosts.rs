@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{fmt, mem};
+
+use serde::Deserialize;
+
+use crate::{
+    buffers::{adapt_pivot_from_cursor, editor_buffer::match_editor_mode},
+    command::{parser::ExCommand, push_message},
+    config::config_dir,
+    editor::{CursorForm, Editor, EditorBuffer, EditorCursor, EditorWindow},
+    layout::show_buffer,
+    text::{to_coord, TextRope},
+    EditorMessage,
+};
+
+pub const MESSAGES_BUFFER: &str = "messages";
+
+// Older lines are dropped once the log grows past this.
+const MAX_LOG_LINES: usize = 1000;
+
+// A host that stays up this long is considered healthy again.
+const HEALTHY_AFTER: Duration = Duration::from_secs(10);
+const MAX_FAILURES: u32 = 5;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// How long a stopped host gets to exit before it is killed.
+const STOP_GRACE: Duration = Duration::from_secs(1);
+
+// The first wait before a restart, doubled for each failure after.
+#[cfg(not(test))]
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+#[cfg(test)]
+const BACKOFF_BASE: Duration = Duration::from_millis(10);
+
+// One `[[plugins]]` entry of the config file. Any executable works, as long
+// as it connects to `$RUSTPEN_SOCKET` and speaks the plugin protocol.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PluginHostConfig {
+    pub name: String,
+    // The program and its arguments, run without a shell.
+    pub command: Vec<String>,
+    // Relative to the config directory.
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum HostStatus {
+    #[default]
+    Stopped,
+    Starting,
+    Running(u32),
+    Waiting,
+    Failed,
+}
+
+impl fmt::Display for HostStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HostStatus::Stopped => write!(f, "stopped"),
+            HostStatus::Starting => write!(f, "starting"),
+            HostStatus::Running(pid) => write!(f, "running (pid {})", pid),
+            HostStatus::Waiting => write!(f, "waiting to restart"),
+            HostStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct HostControl {
+    child: Option<Child>,
+    status: HostStatus,
+    // Quick exits in a row, reset once the host stays up.
+    failures: u32,
+    stopping: bool,
+    restart: bool,
+    supervised: bool,
+}
+
+struct PluginHost {
+    config: PluginHostConfig,
+    control: Arc<Mutex<HostControl>>,
+}
+
+#[derive(Default)]
+pub struct PluginHosts {
+    hosts: Vec<PluginHost>,
+    tx: Option<Sender<EditorMessage>>,
+}
+
+impl PluginHosts {
+    pub fn new(configs: Vec<PluginHostConfig>) -> Self {
+        PluginHosts {
+            hosts: configs
+                .into_iter()
+                .map(|config| PluginHost {
+                    config,
+                    control: Arc::default(),
+                })
+                .collect(),
+            tx: None,
+        }
+    }
+
+    pub fn start(&mut self, tx: Sender<EditorMessage>) {
+        self.tx = Some(tx);
+        for host in self.hosts.iter() {
+            self.supervise(host);
+        }
+    }
+
+    fn supervise(&self, host: &PluginHost) {
+        let Some(tx) = self.tx.clone() else {
+            return;
+        };
+        let mut control = host.control.lock().unwrap();
+        if control.supervised {
+            return;
+        }
+        control.supervised = true;
+        control.stopping = false;
+        control.restart = false;
+        control.failures = 0;
+        let config = host.config.clone();
+        let control = Arc::clone(&host.control);
+        thread::spawn(move || supervise(config, control, tx));
+    }
+
+    pub fn status(&self) -> Vec<(String, HostStatus)> {
+        self.hosts
+            .iter()
+            .map(|host| {
+                let status = host.control.lock().unwrap().status;
+                (host.config.name.clone(), status)
+            })
+            .collect()
+    }
+
+    // Restarts the named hosts, or all of them.
+    pub fn restart(&self, names: &[String]) -> Result<(), String> {
+        if let Some(name) = names
+            .iter()
+            .find(|name| !self.hosts.iter().any(|host| &host.config.name == *name))
+        {
+            return Err(format!("No plugin host named \"{}\"", name));
+        }
+        for host in self
+            .hosts
+            .iter()
+            .filter(|host| names.is_empty() || names.contains(&host.config.name))
+        {
+            {
+                let mut control = host.control.lock().unwrap();
+                control.restart = true;
+                // The new host starts without waiting for the old one to go.
+                if let Some(child) = control.child.take() {
+                    thread::spawn(move || terminate(vec![child]));
+                }
+            }
+            self.supervise(host);
+        }
+        Ok(())
+    }
+
+    pub fn stop_all(&self) {
+        let children = self
+            .hosts
+            .iter()
+            .filter_map(|host| {
+                let mut control = host.control.lock().unwrap();
+                control.stopping = true;
+                control.status = HostStatus::Stopped;
+                control.child.take()
+            })
+            .collect();
+        terminate(children);
+    }
+}
+
+fn signal_group(child: &Child, signal: libc::c_int) {
+    // SAFETY: kill only sends a signal. Hosts lead their own process group,
+    // see `spawn_host`, so this reaches whatever they started too.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), signal);
+    }
+}
+
+// Asks the hosts and everything they started to exit, and kills what is
+// still there after `STOP_GRACE`.
+fn terminate(mut children: Vec<Child>) {
+    for child in children.iter() {
+        signal_group(child, libc::SIGTERM);
+    }
+    let deadline = Instant::now() + STOP_GRACE;
+    while !children.is_empty() && Instant::now() < deadline {
+        children.retain_mut(|child| !matches!(child.try_wait(), Ok(Some(_))));
+        thread::sleep(Duration::from_millis(20));
+    }
+    for mut child in children {
+        signal_group(&child, libc::SIGKILL);
+        let _ = child.wait();
+    }
+}
+
+fn log(tx: &Sender<EditorMessage>, name: &str, line: &str) {
+    let _ = tx.send(EditorMessage::Log(format!("[{}] {}", name, line)));
+}
+
+fn spawn_host(config: &PluginHostConfig) -> std::io::Result<Child> {
+    let mut command = Command::new(&config.command[0]);
+    command
+        .args(&config.command[1..])
+        .envs(&config.env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .process_group(0);
+    if let Some(cwd) = config.cwd.as_ref() {
+        command.current_dir(config_dir().unwrap_or_default().join(cwd));
+    }
+    command.spawn()
+}
+
+fn backoff(failures: u32) -> Duration {
+    BACKOFF_BASE * 2u32.pow(failures.saturating_sub(1).min(6))
+}
+
+// Runs the host, restarting it when it exits, until the editor closes or it
+// keeps failing.
+fn supervise(
+    config: PluginHostConfig,
+    control: Arc<Mutex<HostControl>>,
+    tx: Sender<EditorMessage>,
+) {
+    loop {
+        {
+            let mut control = control.lock().unwrap();
+            if control.stopping {
+                control.supervised = false;
+                return;
+            }
+            control.status = HostStatus::Starting;
+        }
+        let started = Instant::now();
+        match spawn_host(&config) {
+            Ok(mut child) => {
+                if let Some(stderr) = child.stderr.take() {
+                    let (tx, name) = (tx.clone(), config.name.clone());
+                    thread::spawn(move || {
+                        for line in BufReader::new(stderr).lines() {
+                            let Ok(line) = line else { break };
+                            log(&tx, &name, &line);
+                        }
+                    });
+                }
+                log(&tx, &config.name, &format!("Started, pid {}", child.id()));
+                {
+                    let mut control = control.lock().unwrap();
+                    // The editor may have closed while the host was starting.
+                    if control.stopping {
+                        terminate(vec![child]);
+                        control.status = HostStatus::Stopped;
+                        control.supervised = false;
+                        return;
+                    }
+                    control.status = HostStatus::Running(child.id());
+                    control.child = Some(child);
+                }
+                let exit = loop {
+                    thread::sleep(POLL_INTERVAL);
+                    let mut control = control.lock().unwrap();
+                    let Some(child) = control.child.as_mut() else {
+                        break None;
+                    };
+                    if let Ok(Some(status)) = child.try_wait() {
+                        control.child = None;
+                        break Some(status);
+                    }
+                };
+                if let Some(status) = exit {
+                    log(&tx, &config.name, &format!("Exited, {}", status));
+                }
+            }
+            Err(e) => log(&tx, &config.name, &format!("Failed to start: {}", e)),
+        }
+
+        let failures = {
+            let mut control = control.lock().unwrap();
+            if control.stopping {
+                control.supervised = false;
+                return;
+            }
+            if mem::take(&mut control.restart) {
+                control.failures = 0;
+                continue;
+            }
+            if started.elapsed() >= HEALTHY_AFTER {
+                control.failures = 0;
+            }
+            control.failures += 1;
+            if control.failures > MAX_FAILURES {
+                control.status = HostStatus::Failed;
+                control.supervised = false;
+                drop(control);
+                let message = format!("Giving up after {} failed starts", MAX_FAILURES);
+                log(&tx, &config.name, &message);
+                let _ = tx.send(EditorMessage::Render(String::new()));
+                return;
+            }
+            control.status = HostStatus::Waiting;
+            control.failures
+        };
+
+        // `:PluginRestart` skips the wait.
+        let resume = Instant::now() + backoff(failures);
+        while Instant::now() < resume {
+            let mut control = control.lock().unwrap();
+            if control.stopping {
+                control.supervised = false;
+                return;
+            }
+            if mem::take(&mut control.restart) {
+                control.failures = 0;
+                break;
+            }
+            drop(control);
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+// Keeps the log, and the messages buffer when one is open, up to date.
+pub fn append_log(editor: &mut Editor, line: String) {
+    editor.log.push(line);
+    let excess = editor.log.len().saturating_sub(MAX_LOG_LINES);
+    editor.log.drain(..excess);
+    if editor.buffers.contains_key(MESSAGES_BUFFER) {
+        refresh_messages_buffer(editor);
+    }
+}
+
+fn refresh_messages_buffer(editor: &mut Editor) {
+    let lines = editor.log.clone();
+    let Some(buffer) = editor.get_buffer_mut(MESSAGES_BUFFER) else {
+        return;
+    };
+    // Following the end of the log, unless the cursor was moved away.
+    let follow = buffer
+        .cursors
+        .first()
+        .is_some_and(|cursor| cursor.position.1 as usize + 1 >= buffer.content.len_lines());
+    buffer.content = TextRope::from_lines(lines);
+    buffer.is_modified = false;
+    if follow {
+        let last = to_coord(buffer.content.len_lines().saturating_sub(1));
+        buffer.cursors = vec![EditorCursor {
+            position: (0, last),
+            form: CursorForm::SteadyBlock,
+        }];
+    }
+    buffer.clamp_cursors();
+    if let Some(cursor) = buffer.cursors.first().cloned() {
+        adapt_pivot_from_cursor(&cursor, buffer);
+    }
+}
+
+pub fn messages_command(editor: &mut Editor, _command: &ExCommand) -> Result<(), String> {
+    if editor.log.is_empty() {
+        return Err("No messages".to_string());
+    }
+    if !editor.buffers.contains_key(MESSAGES_BUFFER) {
+        let mut buffer = EditorBuffer::new(
+            Arc::new(|editor: &mut Editor, key: &str| {
+                match_editor_mode(editor, key);
+            }),
+            EditorWindow {
+                start: (0, 0),
+                end: editor.terminal_size,
+            },
+            editor.config.editor.tab_width,
+        );
+        buffer.read_only = true;
+        editor.add_buffer(MESSAGES_BUFFER.to_string(), buffer);
+    }
+    show_buffer(editor, MESSAGES_BUFFER);
+    refresh_messages_buffer(editor);
+    Ok(())
+}
+
+// `:PluginRestart` restarts every host, `:PluginRestart name` only that one.
+pub fn plugin_restart_command(editor: &mut Editor, command: &ExCommand) -> Result<(), String> {
+    if editor.hosts.status().is_empty() {
+        return Err("No plugin hosts are configured".to_string());
+    }
+    editor.hosts.restart(&command.args)?;
+    let restarted = if command.args.is_empty() {
+        "all plugin hosts".to_string()
+    } else {
+        command.args.join(", ")
+    };
+    push_message(editor, format!("Restarting {}", restarted));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::mpsc::{self, Receiver};
+
+    use super::*;
+
+    fn host(name: &str, script: &str) -> PluginHostConfig {
+        PluginHostConfig {
+            name: name.to_string(),
+            command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+            cwd: None,
+            env: HashMap::new(),
+        }
+    }
+
+    fn wait_for(hosts: &PluginHosts, done: impl Fn(HostStatus) -> bool) -> HostStatus {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let status = hosts.status()[0].1;
+            if done(status) || Instant::now() > deadline {
+                return status;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn logged(rx: &Receiver<EditorMessage>) -> Vec<String> {
+        rx.try_iter()
+            .filter_map(|message| match message {
+                EditorMessage::Log(line) => Some(line),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Signals take a moment to land. Zombies still have a /proc entry until
+    // someone reaps them.
+    fn has_exited(pid: &str) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            let alive = fs::read_to_string(format!("/proc/{}/stat", pid)).is_ok_and(|stat| {
+                !stat
+                    .rsplit(')')
+                    .next()
+                    .unwrap_or("")
+                    .trim()
+                    .starts_with('Z')
+            });
+            if !alive {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    // The pid of the `sleep` a host started, from the line it logged.
+    fn sleep_pid(rx: &Receiver<EditorMessage>) -> String {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            if let Some(line) = logged(rx).into_iter().find(|line| line.contains("sleep ")) {
+                return line.rsplit(' ').next().unwrap().to_string();
+            }
+            assert!(Instant::now() < deadline, "host never logged its sleep");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_limit() {
+        assert_eq!(backoff(1), BACKOFF_BASE);
+        assert_eq!(backoff(2), BACKOFF_BASE * 2);
+        assert_eq!(backoff(4), BACKOFF_BASE * 8);
+        assert_eq!(backoff(7), BACKOFF_BASE * 64);
+        assert_eq!(backoff(100), BACKOFF_BASE * 64);
+    }
+
+    #[test]
+    fn hosts_that_keep_failing_are_given_up() {
+        let mut hosts = PluginHosts::new(vec![host("failing", "exit 1")]);
+        let (tx, rx) = mpsc::channel();
+        hosts.start(tx);
+
+        assert_eq!(
+            wait_for(&hosts, |status| status == HostStatus::Failed),
+            HostStatus::Failed
+        );
+        let lines = logged(&rx);
+        let starts = lines.iter().filter(|line| line.contains("Started")).count();
+        assert_eq!(starts as u32, MAX_FAILURES + 1);
+        assert_eq!(
+            lines.last().unwrap(),
+            &format!("[failing] Giving up after {} failed starts", MAX_FAILURES)
+        );
+
+        // A restart gives it another round.
+        hosts.restart(&[]).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut restarts = 0;
+        while restarts < MAX_FAILURES + 1 && Instant::now() < deadline {
+            restarts += logged(&rx)
+                .iter()
+                .filter(|line| line.contains("Started"))
+                .count() as u32;
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(restarts, MAX_FAILURES + 1);
+        hosts.stop_all();
+    }
+
+    #[test]
+    fn restart_and_stop_end_the_whole_process_group() {
+        // The shell ignores SIGTERM, only SIGKILL ends it, its sleep goes
+        // with the group.
+        let script = "trap '' TERM; sleep 60 & echo sleep $! >&2; wait";
+        let mut hosts = PluginHosts::new(vec![host("sleeper", script)]);
+        let (tx, rx) = mpsc::channel();
+        hosts.start(tx);
+        let first = sleep_pid(&rx);
+        let HostStatus::Running(pid) =
+            wait_for(&hosts, |status| matches!(status, HostStatus::Running(_)))
+        else {
+            panic!("host did not start");
+        };
+
+        assert_eq!(
+            hosts.restart(&["other".to_string()]),
+            Err("No plugin host named \"other\"".to_string())
+        );
+        hosts.restart(&["sleeper".to_string()]).unwrap();
+        let second = sleep_pid(&rx);
+        assert_ne!(first, second);
+        let restarted = wait_for(
+            &hosts,
+            |status| matches!(status, HostStatus::Running(new) if new != pid),
+        );
+        assert!(matches!(restarted, HostStatus::Running(new) if new != pid));
+        thread::sleep(STOP_GRACE);
+        assert!(has_exited(&first));
+
+        hosts.stop_all();
+        assert_eq!(hosts.status()[0].1, HostStatus::Stopped);
+        assert!(has_exited(&second));
+    }
+}
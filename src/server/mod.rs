@@ -1,25 +1,76 @@
-use server_messages::{ContextMessage, ServerMessages};
-use std::io::{Read, Write};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::Path;
-use std::process::{Command, Stdio};
 use std::sync::mpsc::{self};
 use std::sync::{Arc, Mutex};
-use std::{env, fs, thread};
+use std::{env, process, thread};
 
+use crate::command::{error_message, push_message};
 use crate::editor::Editor;
 use crate::EditorMessage;
+use framing::encode_frame;
+use server_messages::ServerMessages;
 
+pub mod client_messages;
+pub mod connection;
+pub mod events;
+pub mod framing;
+pub mod hosts;
+pub mod plugin_buffers;
+pub mod plugins;
 pub mod server_messages;
 
+// Bumped whenever a message changes in a way old plugins can't read.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// Plugins started by the editor find its socket here.
+pub const SOCKET_ENV: &str = "RUSTPEN_SOCKET";
+
+// Each instance gets its own socket, in the runtime dir or else in a dir of
+// the user's own under /tmp.
+pub fn default_socket_path() -> String {
+    let dir = env::var_os("XDG_RUNTIME_DIR")
+        .map(|dir| Path::new(&dir).join("rustpen"))
+        .unwrap_or_else(|| Path::new("/tmp").join(format!("rustpen-{}", current_uid())));
+    dir.join(format!("rustpen-{}.sock", process::id()))
+        .to_string_lossy()
+        .to_string()
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and can't fail.
+    unsafe { libc::getuid() }
+}
+
+// Other users must not be able to reach the socket, or swap it for their own.
+pub fn prepare_socket_dir(dir: &Path) -> io::Result<()> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        Err(_) => {}
+    }
+
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != current_uid() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a directory of our own", dir.display()),
+        ));
+    }
+    if metadata.permissions().mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
 pub struct UnixServer {
     socket_path: String,
 }
 
-pub fn send_message<T>(stream: &mut UnixStream, message: ServerMessages) {
-    let message_str = serde_json::to_vec_pretty(&message).unwrap();
-
-    stream.write_all(&message_str).unwrap();
+pub fn send_message(stream: &mut UnixStream, message: ServerMessages) -> io::Result<()> {
+    stream.write_all(&encode_frame(&message)?)
 }
 
 impl UnixServer {
@@ -29,12 +80,27 @@ impl UnixServer {
         }
     }
 
-    pub fn start(&self, tx: mpsc::Sender<EditorMessage>, editor_ref: Arc<Mutex<Editor>>) {
-        if Path::new(&self.socket_path).exists() {
-            fs::remove_file(&self.socket_path).expect("Failed to remove existing socket.");
+    fn bind(&self) -> io::Result<UnixListener> {
+        let path = Path::new(&self.socket_path);
+        if let Some(dir) = path.parent() {
+            prepare_socket_dir(dir)?;
+        }
+        if path.exists() {
+            fs::remove_file(path)?;
         }
+        UnixListener::bind(path)
+    }
 
-        let listener = UnixListener::bind(&self.socket_path).expect("Failed to bind to socket.");
+    pub fn start(&self, tx: mpsc::Sender<EditorMessage>, editor_ref: Arc<Mutex<Editor>>) {
+        let listener = match self.bind() {
+            Ok(listener) => listener,
+            Err(e) => {
+                let message = format!("Plugins are off, no socket at {}: {}", self.socket_path, e);
+                push_message(&mut editor_ref.lock().unwrap(), error_message(&message));
+                let _ = tx.send(EditorMessage::Render(String::new()));
+                return;
+            }
+        };
 
         tx.send(EditorMessage::Render(format!(
             "Listening on socket: {}",
@@ -42,60 +108,76 @@ impl UnixServer {
         )))
         .unwrap();
 
-        // thread::spawn(move || {
-        //     let shell_path = env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string());
-
-        //     let home_dir = env::var("HOME").expect("HOME directory not found");
-        //     let config_dir = format!("{}/.config/rustpen/ts-plugins", home_dir);
-
-        //     Command::new(&shell_path)
-        //         .current_dir(&config_dir)
-        //         .arg("-c")
-        //         .arg("npm start")
-        //         .stdout(Stdio::piped())
-        //         .status()
-        //         .unwrap();
-        // });
-
-        match listener.accept() {
-            Ok((mut stream, _)) => {
-                let mut buffer = [0; 512];
-                let editor = editor_ref.lock().unwrap();
-                send_message::<ServerMessages>(
-                    &mut stream,
-                    ServerMessages::Context(ContextMessage {
-                        editor: editor.to_editor_context(),
-                    }),
-                );
-                loop {
-                    match stream.read(&mut buffer) {
-                        Ok(n) => {
-                            if n == 0 {
-                                tx.send(EditorMessage::Render(
-                                    "Plugin connection closed".to_string(),
-                                ))
-                                .unwrap();
-                                break;
-                            } else {
-                                let received = String::from_utf8(buffer[0..n].to_vec()).unwrap();
-                                tx.send(EditorMessage::Render(received)).unwrap();
-                            }
-                        }
-                        Err(e) => {
-                            tx.send(EditorMessage::Render(format!(
-                                "Error reading plugins: {}",
-                                e
-                            )))
-                            .unwrap();
-                            break;
-                        }
-                    }
+        // Hosts start once the socket exists, so they can connect right away.
+        editor_ref.lock().unwrap().hosts.start(tx.clone());
+
+        // One thread per plugin, for as long as the editor runs.
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let tx = tx.clone();
+                    let editor_ref = Arc::clone(&editor_ref);
+                    thread::spawn(move || connection::serve(stream, tx, editor_ref));
+                }
+                Err(e) => {
+                    let _ = tx.send(EditorMessage::Render(format!("Connection failed: {}", e)));
                 }
             }
-            Err(e) => {
-                tx.send(EditorMessage::Render(format!("Connection failed: {}", e)))
-                    .unwrap();
-            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::{chown, symlink};
+
+    use super::*;
+    use crate::testing::{temp_dir, test_editor};
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn socket_dirs_are_private() {
+        let root = temp_dir("socket_dirs_are_private");
+        let dir = root.join("rustpen");
+        prepare_socket_dir(&dir).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        prepare_socket_dir(&dir).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+
+        // A link planted where the dir should be is not followed.
+        let link = root.join("link");
+        symlink(&dir, &link).unwrap();
+        assert!(prepare_socket_dir(&link).is_err());
+
+        // Only root can give a dir away to check someone else's.
+        let other = root.join("other");
+        fs::create_dir(&other).unwrap();
+        if chown(&other, Some(65534), Some(65534)).is_ok() {
+            let error = prepare_socket_dir(&other).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        }
+    }
+
+    #[test]
+    fn socket_errors_reach_the_status_line() {
+        let root = temp_dir("socket_errors_reach_the_status_line");
+        fs::write(root.join("file"), "").unwrap();
+        let (editor, _) = test_editor(root.to_str().unwrap(), (80, 24));
+        let editor_ref = Arc::new(Mutex::new(editor));
+        let (tx, rx) = mpsc::channel();
+
+        let socket = root.join("file").join("rustpen.sock");
+        UnixServer::new(socket.to_str().unwrap()).start(tx, Arc::clone(&editor_ref));
+        assert!(matches!(rx.try_recv(), Ok(EditorMessage::Render(_))));
+        let editor = editor_ref.lock().unwrap();
+        assert!(editor
+            .commands_hist
+            .iter()
+            .any(|entry| entry.contains("Plugins are off")));
+    }
+}
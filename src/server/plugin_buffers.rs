@@ -0,0 +1,184 @@
+use std::sync::Arc;
+
+use crate::{
+    buffers::adapt_pivot_from_cursor,
+    editor::{CursorForm, Editor, EditorBuffer, EditorCursor, EditorWindow, HandleKeysFn},
+    layout::{floating::FloatingWindow, Layout},
+    server::server_messages::{BufferInputMessage, BufferMessage, ServerMessages},
+    text::TextRope,
+};
+
+// Editor keys never contain ':', so a prefix keeps plugin buffers apart.
+fn check_name(name: &str) -> Result<(), String> {
+    match name.split_once(':') {
+        Some((prefix, rest)) if !prefix.is_empty() && !rest.is_empty() => Ok(()),
+        _ => Err(format!(
+            "Invalid buffer name: {} (use a prefix, like \"plugin:name\")",
+            name
+        )),
+    }
+}
+
+fn check_owner(editor: &Editor, client_id: usize, name: &str) -> Result<(), String> {
+    match editor.plugins.owner_of(name) {
+        Some(owner) if owner == client_id => Ok(()),
+        Some(_) => Err(format!("Buffer {} belongs to another plugin", name)),
+        None => Err(format!("No such plugin buffer: {}", name)),
+    }
+}
+
+// Keys typed while the buffer floats with focus go to its plugin.
+fn forward_key(editor: &mut Editor, name: &str, key: &str) {
+    let sent = editor
+        .plugins
+        .owner_of(name)
+        .and_then(|owner| editor.plugins.get(owner))
+        .is_some_and(|client| {
+            client.notify(ServerMessages::BufferInput(BufferInputMessage {
+                buffer_name: name.to_string(),
+                input: key.to_string(),
+            }))
+        });
+    if !sent {
+        editor.floating.focus = None;
+    }
+}
+
+// Creates the plugin buffer or replaces what it shows. Content, colors,
+// cursors and tab width come from the message, floats are placed by
+// `openFloat` instead of `bufferWindow`, and registers are ignored.
+pub fn set_plugin_buffer(
+    editor: &mut Editor,
+    client_id: usize,
+    message: BufferMessage,
+) -> Result<(), String> {
+    let name = message.buffer_name;
+    check_name(&name)?;
+    if editor.buffers.contains_key(&name) {
+        check_owner(editor, client_id, &name)?;
+    } else {
+        let forwarded = name.clone();
+        let handle_keys: HandleKeysFn =
+            Arc::new(move |editor: &mut Editor, key: &str| forward_key(editor, &forwarded, key));
+        let window = editor
+            .floating
+            .get(&name)
+            .map(|float| float.text_window())
+            .unwrap_or(EditorWindow {
+                start: (0, 0),
+                end: (0, 0),
+            });
+        editor.add_buffer(
+            name.clone(),
+            EditorBuffer::new(handle_keys, window, message.buffer.tab_width),
+        );
+        editor.plugins.claim_buffer(client_id, &name);
+    }
+
+    let Some(buffer) = editor.get_buffer_mut(&name) else {
+        return Ok(());
+    };
+    let context = message.buffer;
+    buffer.content = TextRope::from_text(&context.content.join("\n"));
+    buffer.colors = context.colors;
+    buffer.tab_width = context.tab_width.max(1);
+    if !context.cursors.is_empty() {
+        buffer.cursors = context
+            .cursors
+            .iter()
+            .map(|cursor| EditorCursor {
+                position: cursor.position,
+                form: CursorForm::SteadyBlock,
+            })
+            .collect();
+    }
+    buffer.clamp_cursors();
+    if editor.floating.get(&name).is_some() {
+        show_cursor(editor, &name);
+    }
+    Ok(())
+}
+
+// Scrolls a floating buffer so its first cursor is in view.
+fn show_cursor(editor: &mut Editor, name: &str) {
+    if let Some(buffer) = editor.get_buffer_mut(name) {
+        if let Some(cursor) = buffer.cursors.first().cloned() {
+            adapt_pivot_from_cursor(&cursor, buffer);
+        }
+    }
+}
+
+pub fn destroy_plugin_buffer(
+    editor: &mut Editor,
+    client_id: usize,
+    name: &str,
+) -> Result<(), String> {
+    check_owner(editor, client_id, name)?;
+    remove_plugin_buffer(editor, name);
+    Ok(())
+}
+
+pub fn remove_plugin_buffer(editor: &mut Editor, name: &str) {
+    editor.floating.close(name);
+    editor.buffers.remove(name);
+    editor.plugins.release_buffer(name);
+}
+
+pub fn open_float(
+    editor: &mut Editor,
+    client_id: usize,
+    float: FloatingWindow,
+    focus: bool,
+) -> Result<(), String> {
+    check_owner(editor, client_id, &float.buffer)?;
+    let area = Layout::area(editor.terminal_size);
+    if float.window.start.0 < area.start.0
+        || float.window.start.1 < area.start.1
+        || float.window.end.0 <= float.window.start.0
+        || float.window.end.1 <= float.window.start.1
+    {
+        return Err("Invalid floating window".to_string());
+    }
+
+    let name = float.buffer.clone();
+    if let Some(buffer) = editor.get_buffer_mut(&name) {
+        buffer.buffer_window = float.text_window();
+        buffer.pivot = (0, 0);
+    }
+    if focus {
+        editor.floating.focus = Some(name.clone());
+    }
+    editor.floating.open(float);
+    show_cursor(editor, &name);
+    Ok(())
+}
+
+pub fn close_float(editor: &mut Editor, client_id: usize, name: &str) -> Result<(), String> {
+    check_owner(editor, client_id, name)?;
+    editor.floating.close(name);
+    Ok(())
+}
+
+// `None` gives the keys back to the focused pane.
+pub fn focus_float(
+    editor: &mut Editor,
+    client_id: usize,
+    name: Option<String>,
+) -> Result<(), String> {
+    match name {
+        Some(name) => {
+            check_owner(editor, client_id, &name)?;
+            if editor.floating.get(&name).is_none() {
+                return Err(format!("Buffer {} is not floating", name));
+            }
+            editor.floating.focus = Some(name);
+        }
+        None => {
+            if let Some(focused) = editor.floating.focus.clone() {
+                check_owner(editor, client_id, &focused)?;
+            }
+            editor.floating.focus = None;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,331 @@
+use std::sync::mpsc::Sender;
+
+use crate::{
+    command::{parser::ExCommand, push_message},
+    editor::Editor,
+    editor_modes::EditorMode,
+    is_single_key,
+    server::{
+        events::ObservedState,
+        plugin_buffers::remove_plugin_buffer,
+        server_messages::{CommandMessage, EditorEventKind, KeymapMessage, ServerMessages},
+    },
+};
+
+// A plugin that connected at some point. Its entry outlives the connection
+// so `:plugins` can tell it went away, and a reconnect reuses it.
+pub struct PluginClient {
+    pub id: usize,
+    pub name: String,
+    pub connections: usize,
+    pub commands: Vec<String>,
+    pub keymaps: Vec<(EditorMode, String)>,
+    pub subscriptions: Vec<EditorEventKind>,
+    pub buffers: Vec<String>,
+    sender: Option<Sender<ServerMessages>>,
+}
+
+impl PluginClient {
+    pub fn is_connected(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    pub fn notify(&self, message: ServerMessages) -> bool {
+        self.sender
+            .as_ref()
+            .is_some_and(|sender| sender.send(message).is_ok())
+    }
+
+    fn describe(&self) -> String {
+        let mut description = format!("{} \"{}\"", self.id, self.name);
+        if !self.is_connected() {
+            description.push_str(" disconnected");
+            return description;
+        }
+        description.push_str(" connected");
+        if self.connections > 1 {
+            description.push_str(&format!(" (reconnected {}x)", self.connections - 1));
+        }
+        if !self.commands.is_empty() {
+            description.push_str(&format!(" :{}", self.commands.join(" :")));
+        }
+        if !self.keymaps.is_empty() {
+            let keys: Vec<String> = self
+                .keymaps
+                .iter()
+                .map(|(mode, keys)| format!("{}{}", mode, keys))
+                .collect();
+            description.push_str(&format!(" {}", keys.join(" ")));
+        }
+        description
+    }
+}
+
+#[derive(Default)]
+pub struct Plugins {
+    clients: Vec<PluginClient>,
+    pub observed: ObservedState,
+}
+
+impl Plugins {
+    pub fn clients(&self) -> &[PluginClient] {
+        &self.clients
+    }
+
+    pub fn get(&self, id: usize) -> Option<&PluginClient> {
+        self.clients.iter().find(|client| client.id == id)
+    }
+
+    fn get_mut(&mut self, id: usize) -> Result<&mut PluginClient, String> {
+        self.clients
+            .iter_mut()
+            .find(|client| client.id == id)
+            .ok_or(format!("Unknown plugin: {}", id))
+    }
+
+    // Returns the id the plugin is known by from now on.
+    pub fn connect(&mut self, name: &str, sender: Sender<ServerMessages>) -> usize {
+        if let Some(client) = self
+            .clients
+            .iter_mut()
+            .find(|client| client.name == name && !client.is_connected())
+        {
+            client.sender = Some(sender);
+            client.connections += 1;
+            return client.id;
+        }
+
+        let id = self
+            .clients
+            .iter()
+            .map(|client| client.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        self.clients.push(PluginClient {
+            id,
+            name: name.to_string(),
+            connections: 1,
+            commands: vec![],
+            keymaps: vec![],
+            subscriptions: vec![],
+            buffers: vec![],
+            sender: Some(sender),
+        });
+        id
+    }
+
+    // Whatever a plugin registered goes away with it. Returns the buffers it
+    // owned, for the caller to remove.
+    pub fn disconnect(&mut self, id: usize) -> Vec<String> {
+        let Ok(client) = self.get_mut(id) else {
+            return vec![];
+        };
+        client.sender = None;
+        client.commands.clear();
+        client.keymaps.clear();
+        client.subscriptions.clear();
+        client.buffers.clone()
+    }
+
+    pub fn owner_of(&self, buffer: &str) -> Option<usize> {
+        self.clients
+            .iter()
+            .find(|client| client.buffers.iter().any(|owned| owned == buffer))
+            .map(|client| client.id)
+    }
+
+    pub fn claim_buffer(&mut self, id: usize, buffer: &str) {
+        if let Ok(client) = self.get_mut(id) {
+            client.buffers.push(buffer.to_string());
+        }
+    }
+
+    pub fn release_buffer(&mut self, buffer: &str) {
+        for client in self.clients.iter_mut() {
+            client.buffers.retain(|owned| owned != buffer);
+        }
+    }
+
+    pub fn subscribe(&mut self, id: usize, events: &[EditorEventKind]) -> Result<(), String> {
+        let client = self.get_mut(id)?;
+        for event in events {
+            if !client.subscriptions.contains(event) {
+                client.subscriptions.push(*event);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, id: usize, events: &[EditorEventKind]) -> Result<(), String> {
+        self.get_mut(id)?
+            .subscriptions
+            .retain(|event| !events.contains(event));
+        Ok(())
+    }
+
+    // Sends an event to the plugins that subscribed to it.
+    pub fn publish(&self, message: ServerMessages) {
+        let Some(kind) = message.event_kind() else {
+            return;
+        };
+        for client in self
+            .clients
+            .iter()
+            .filter(|client| client.subscriptions.contains(&kind))
+        {
+            client.notify(message.clone());
+        }
+    }
+
+    pub fn register_command(&mut self, id: usize, name: &str) -> Result<(), String> {
+        // Like vim user commands, so they never shadow a builtin one.
+        if !name.starts_with(|ch: char| ch.is_ascii_uppercase())
+            || !name.chars().all(|ch| ch.is_ascii_alphanumeric())
+        {
+            return Err(format!(
+                "Invalid command name: {} (must start with an uppercase letter)",
+                name
+            ));
+        }
+        if let Some(owner) = self
+            .clients
+            .iter()
+            .find(|client| client.id != id && client.commands.iter().any(|c| c == name))
+        {
+            return Err(format!(
+                "Command :{} already registered by \"{}\"",
+                name, owner.name
+            ));
+        }
+
+        let client = self.get_mut(id)?;
+        if !client.commands.iter().any(|command| command == name) {
+            client.commands.push(name.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn register_keymap(
+        &mut self,
+        id: usize,
+        mode: EditorMode,
+        keys: &str,
+    ) -> Result<(), String> {
+        if mode == EditorMode::Command {
+            return Err("Keymaps are not supported in Command mode".to_string());
+        }
+        if !is_single_key(keys) {
+            return Err(format!("Invalid key: {}", keys));
+        }
+        let keymap = (mode, keys.to_string());
+        if let Some(owner) = self
+            .clients
+            .iter()
+            .find(|client| client.id != id && client.keymaps.contains(&keymap))
+        {
+            return Err(format!("Key {} already mapped by \"{}\"", keys, owner.name));
+        }
+
+        let client = self.get_mut(id)?;
+        if !client.keymaps.contains(&keymap) {
+            client.keymaps.push(keymap);
+        }
+        Ok(())
+    }
+
+    // Returns false when the key isn't mapped, so the editor handles it.
+    pub fn trigger_keymap(&self, mode: EditorMode, keys: &str) -> bool {
+        self.clients
+            .iter()
+            .find(|client| {
+                client
+                    .keymaps
+                    .iter()
+                    .any(|keymap| keymap.0 == mode && keymap.1 == keys)
+            })
+            .is_some_and(|client| {
+                client.notify(ServerMessages::Keymap(KeymapMessage {
+                    mode,
+                    keys: keys.to_string(),
+                }))
+            })
+    }
+}
+
+// Hands `:Name` over to the plugin that registered it.
+pub fn run_plugin_command(editor: &mut Editor, command: &ExCommand) -> Option<Result<(), String>> {
+    let owner = editor
+        .plugins
+        .clients()
+        .iter()
+        .find(|client| client.commands.contains(&command.name))?
+        .id;
+
+    let line_range = match command.range {
+        Some(_) => {
+            let focus_buffer = editor.focus_buffer.clone();
+            let Some(buffer) = editor.get_buffer(&focus_buffer) else {
+                return Some(Err("No buffer to edit".to_string()));
+            };
+            match command.lines(buffer) {
+                Ok(lines) => Some(lines),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None => None,
+    };
+
+    let sent = editor.plugins.get(owner).is_some_and(|client| {
+        client.notify(ServerMessages::Command(CommandMessage {
+            name: command.name.clone(),
+            bang: command.bang,
+            args: command.args.clone(),
+            line_range,
+        }))
+    });
+    Some(if sent {
+        Ok(())
+    } else {
+        Err(format!("Plugin for :{} is not connected", command.name))
+    })
+}
+
+pub fn list_plugins_command(editor: &mut Editor, _command: &ExCommand) -> Result<(), String> {
+    let mut listed: Vec<String> = editor
+        .plugins
+        .clients()
+        .iter()
+        .map(|client| client.describe())
+        .collect();
+    listed.extend(
+        editor
+            .hosts
+            .status()
+            .into_iter()
+            .map(|(name, status)| format!("host \"{}\" {}", name, status)),
+    );
+
+    if listed.is_empty() {
+        return Err("No plugins have connected".to_string());
+    }
+    editor.commands_hist.push(listed.join("  "));
+    Ok(())
+}
+
+// Lifecycle notices show up like any other message.
+pub fn plugin_connected(editor: &mut Editor, name: &str, sender: Sender<ServerMessages>) -> usize {
+    let id = editor.plugins.connect(name, sender);
+    push_message(editor, format!("Plugin \"{}\" connected", name));
+    id
+}
+
+pub fn plugin_disconnected(editor: &mut Editor, id: usize) {
+    for buffer in editor.plugins.disconnect(id) {
+        remove_plugin_buffer(editor, &buffer);
+    }
+    if let Some(client) = editor.plugins.get(id) {
+        let message = format!("Plugin \"{}\" disconnected", client.name);
+        push_message(editor, message);
+    }
+}
@@ -6,14 +6,15 @@ use crate::{
     editor_modes::EditorMode,
 };
 
-#[derive(Serialize, Deserialize, TS)]
+#[derive(Clone, Serialize, Deserialize, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
 pub struct ContextMessage {
     pub editor: EditorContext,
 }
 
-#[derive(Serialize, Deserialize, TS)]
+// Events plugins receive once they subscribed to them, see `EditorEventKind`.
+#[derive(Clone, Serialize, Deserialize, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
 pub struct InputMessage {
@@ -21,20 +22,164 @@ pub struct InputMessage {
     pub editor_mode: EditorMode,
 }
 
-#[derive(Serialize, Deserialize, TS)]
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ModeChangedMessage {
+    pub from: EditorMode,
+    pub to: EditorMode,
+}
+
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct BufferEventMessage {
+    pub buffer_name: String,
+    pub file_name: Option<String>,
+}
+
+// Replaces the text between `start` and `end` (exclusive) with `text`, as
+// (x, y) positions in the buffer left by the change before it.
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct TextChange {
+    pub start: (u16, u16),
+    pub end: (u16, u16),
+    pub text: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct TextChangedMessage {
+    pub buffer_name: String,
+    pub changes: Vec<TextChange>,
+}
+
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct CursorMovedMessage {
+    pub buffer_name: String,
+    pub positions: Vec<(u16, u16)>,
+}
+
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ResizedMessage {
+    pub terminal_size: (u16, u16),
+}
+
+// A key typed while a plugin buffer floats with focus, sent to its owner.
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct BufferInputMessage {
+    pub buffer_name: String,
+    pub input: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
 pub struct BufferMessage {
-    pub request_id: String,
+    // Not needed when sent as a request, which has its own id.
+    #[serde(default)]
+    #[ts(optional)]
+    pub request_id: Option<String>,
     pub buffer_name: String,
     pub buffer: EditorBufferContext,
 }
 
-#[derive(Serialize, Deserialize, TS)]
+// Sent when `:Name` runs a command the plugin registered. The range is
+// zero-based and inclusive, and only present when one was given.
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct CommandMessage {
+    pub name: String,
+    pub bang: bool,
+    pub args: Vec<String>,
+    pub line_range: Option<(usize, usize)>,
+}
+
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct KeymapMessage {
+    pub mode: EditorMode,
+    pub keys: String,
+}
+
+// Answers every `ClientRequest`, `error` is None when it was applied. The id
+// is null when the request was too malformed to read it.
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ResponseMessage {
+    pub request_id: Option<String>,
+    pub error: Option<String>,
+}
+
+// First message on every connection. The plugin answers with a `hello`
+// request naming the version it speaks.
+#[derive(Clone, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct HelloMessage {
+    pub protocol_version: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize, TS)]
 #[serde(tag = "message_type", content = "info", rename_all = "camelCase")]
 #[ts(export)]
 pub enum ServerMessages {
+    Hello(HelloMessage),
     Context(ContextMessage),
     Input(InputMessage),
     Buffer(BufferMessage),
+    Response(ResponseMessage),
+    Command(CommandMessage),
+    Keymap(KeymapMessage),
+    BufferInput(BufferInputMessage),
+    ModeChanged(ModeChangedMessage),
+    BufferOpened(BufferEventMessage),
+    BufferSaved(BufferEventMessage),
+    BufferClosed(BufferEventMessage),
+    TextChanged(TextChangedMessage),
+    CursorMoved(CursorMovedMessage),
+    Resized(ResizedMessage),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub enum EditorEventKind {
+    Input,
+    ModeChanged,
+    BufferOpened,
+    BufferSaved,
+    BufferClosed,
+    TextChanged,
+    CursorMoved,
+    Resized,
+}
+
+impl ServerMessages {
+    // None for messages every plugin gets, like replies.
+    pub fn event_kind(&self) -> Option<EditorEventKind> {
+        match self {
+            ServerMessages::Input(_) => Some(EditorEventKind::Input),
+            ServerMessages::ModeChanged(_) => Some(EditorEventKind::ModeChanged),
+            ServerMessages::BufferOpened(_) => Some(EditorEventKind::BufferOpened),
+            ServerMessages::BufferSaved(_) => Some(EditorEventKind::BufferSaved),
+            ServerMessages::BufferClosed(_) => Some(EditorEventKind::BufferClosed),
+            ServerMessages::TextChanged(_) => Some(EditorEventKind::TextChanged),
+            ServerMessages::CursorMoved(_) => Some(EditorEventKind::CursorMoved),
+            ServerMessages::Resized(_) => Some(EditorEventKind::Resized),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,268 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::{config_dir, read_config, Config, CONFIG_FILE};
+use crate::server::default_socket_path;
+use crate::server::hosts::PluginHostConfig;
+use crate::theme::{Theme, DEFAULT_THEME};
+
+// Installed by `rustpen setup`, never over files that already exist.
+const DEFAULT_FILES: [(&str, &str); 6] = [
+    (CONFIG_FILE, include_str!("../../config.toml")),
+    ("themes/default.toml", DEFAULT_THEME),
+    (
+        "ts-plugins/package.json",
+        include_str!("../../ts-plugins/package.json"),
+    ),
+    (
+        "ts-plugins/package-lock.json",
+        include_str!("../../ts-plugins/package-lock.json"),
+    ),
+    (
+        "ts-plugins/tsconfig.json",
+        include_str!("../../ts-plugins/tsconfig.json"),
+    ),
+    (
+        "ts-plugins/src/index.ts",
+        include_str!("../../ts-plugins/src/index.ts"),
+    ),
+];
+
+fn host_dir(dir: &Path, host: &PluginHostConfig) -> PathBuf {
+    match host.cwd.as_ref() {
+        Some(cwd) => dir.join(cwd),
+        None => dir.to_path_buf(),
+    }
+}
+
+// Node hosts need `npm install` before they can start.
+fn needs_install(dir: &Path) -> bool {
+    dir.join("package.json").exists() && !dir.join("node_modules").exists()
+}
+
+fn find_executable(program: &str, cwd: &Path) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        fs::metadata(path)
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    };
+    if program.contains('/') {
+        let path = cwd.join(program);
+        return is_executable(&path).then_some(path);
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+// Writes the default config and plugin skeleton. Only `install_deps` runs
+// package managers, which may use the network.
+pub fn setup(install_deps: bool) -> Result<(), String> {
+    install(install_deps, &mut |line| println!("{}", line))
+}
+
+fn install(install_deps: bool, log: &mut dyn FnMut(String)) -> Result<(), String> {
+    let dir = config_dir().ok_or("Failed to find the config directory")?;
+    for (name, contents) in DEFAULT_FILES {
+        let path = dir.join(name);
+        if path.exists() {
+            log(format!("Kept {}", path.display()));
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        log(format!("Wrote {}", path.display()));
+    }
+
+    let hosts = read_config(&dir.join(CONFIG_FILE))?.plugins;
+    let pending: Vec<PathBuf> = hosts
+        .iter()
+        .map(|host| host_dir(&dir, host))
+        .filter(|host_dir| needs_install(host_dir))
+        .collect();
+    if pending.is_empty() {
+        return Ok(());
+    }
+    if !install_deps {
+        log(
+            "Plugin dependencies are not installed, run `rustpen setup --install-deps`".to_string(),
+        );
+        return Ok(());
+    }
+
+    find_executable("npm", &dir).ok_or("npm is not installed, see https://nodejs.org")?;
+    for host_dir in pending {
+        log(format!("Running npm install in {}", host_dir.display()));
+        let status = Command::new("npm")
+            .arg("install")
+            .current_dir(&host_dir)
+            .status()
+            .map_err(|e| format!("Failed to run npm: {}", e))?;
+        if !status.success() {
+            return Err(format!("npm install failed in {}", host_dir.display()));
+        }
+    }
+    Ok(())
+}
+
+// Reports what the editor needs and whether it's there. Returns false when
+// something is missing.
+pub fn doctor() -> bool {
+    let mut healthy = true;
+    diagnose(&mut |ok, message| {
+        println!("{} {}", if ok { "[ok]" } else { "[!!]" }, message);
+        healthy &= ok;
+    });
+    healthy
+}
+
+fn diagnose(report: &mut dyn FnMut(bool, String)) {
+    let Some(dir) = config_dir() else {
+        report(false, "No config directory, is $HOME set?".to_string());
+        return;
+    };
+    let config = dir.join(CONFIG_FILE);
+    if !config.exists() {
+        report(
+            false,
+            format!("{} is missing, run `rustpen setup`", config.display()),
+        );
+    }
+
+    match read_config(&config) {
+        Ok(Config { plugins, theme, .. }) => {
+            if config.exists() {
+                report(true, format!("{} is valid", config.display()));
+            }
+            match Theme::load(&theme.colorscheme, &theme.groups) {
+                Ok(_) => report(true, format!("Colorscheme \"{}\" loads", theme.colorscheme)),
+                Err(e) => report(false, e),
+            }
+            for host in plugins {
+                let host_dir = host_dir(&dir, &host);
+                if !host_dir.is_dir() {
+                    report(
+                        false,
+                        format!(
+                            "Plugin host \"{}\": {} does not exist, run `rustpen setup`",
+                            host.name,
+                            host_dir.display()
+                        ),
+                    );
+                    continue;
+                }
+                match find_executable(&host.command[0], &host_dir) {
+                    Some(path) => report(
+                        true,
+                        format!("Plugin host \"{}\" runs {}", host.name, path.display()),
+                    ),
+                    None => report(
+                        false,
+                        format!(
+                            "Plugin host \"{}\": {} is not installed",
+                            host.name, host.command[0]
+                        ),
+                    ),
+                }
+                if needs_install(&host_dir) {
+                    report(
+                        false,
+                        format!(
+                            "Plugin host \"{}\": dependencies are not installed, run `rustpen setup --install-deps`",
+                            host.name
+                        ),
+                    );
+                }
+            }
+        }
+        Err(e) => report(false, e),
+    }
+
+    let socket_path = default_socket_path();
+    let socket_dir = Path::new(&socket_path).parent().unwrap_or(Path::new("/"));
+    report(
+        true,
+        format!("Plugin sockets are created in {}", socket_dir.display()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    fn problems() -> Vec<String> {
+        let mut problems = vec![];
+        diagnose(&mut |ok, message| {
+            if !ok {
+                problems.push(message);
+            }
+        });
+        problems
+    }
+
+    #[test]
+    fn setup_installs_defaults_and_doctor_checks_them() {
+        let home = temp_dir("setup_installs_defaults");
+        // Records that it ran, the way `npm install` leaves node_modules.
+        let bin = home.join("bin");
+        fs::create_dir(&bin).unwrap();
+        let npm = bin.join("npm");
+        fs::write(&npm, "#!/bin/sh\nmkdir node_modules\n").unwrap();
+        fs::set_permissions(&npm, fs::Permissions::from_mode(0o755)).unwrap();
+        let path = env::var_os("PATH").unwrap_or_default();
+        let mut paths = vec![bin];
+        paths.extend(env::split_paths(&path));
+        env::set_var("PATH", env::join_paths(paths).unwrap());
+        env::set_var("XDG_CONFIG_HOME", home.join("config"));
+        let dir = config_dir().unwrap();
+        assert_eq!(dir, home.join("config").join("rustpen"));
+
+        assert_eq!(
+            problems()[0],
+            format!(
+                "{} is missing, run `rustpen setup`",
+                dir.join(CONFIG_FILE).display()
+            )
+        );
+
+        // Files already there are kept as they are.
+        fs::create_dir_all(dir.join("themes")).unwrap();
+        fs::write(dir.join("themes/default.toml"), "# mine\n").unwrap();
+        let mut lines = vec![];
+        install(false, &mut |line| lines.push(line)).unwrap();
+        assert!(lines.contains(&format!(
+            "Kept {}",
+            dir.join("themes/default.toml").display()
+        )));
+        assert!(lines.contains(&format!("Wrote {}", dir.join(CONFIG_FILE).display())));
+        assert_eq!(
+            fs::read_to_string(dir.join("themes/default.toml")).unwrap(),
+            "# mine\n"
+        );
+
+        // Nothing runs without --install-deps, doctor says what is missing.
+        let host_dir = dir.join("ts-plugins");
+        assert!(!host_dir.join("node_modules").exists());
+        assert_eq!(
+            lines.last().unwrap(),
+            "Plugin dependencies are not installed, run `rustpen setup --install-deps`"
+        );
+        assert_eq!(
+            problems(),
+            vec!["Plugin host \"ts-plugins\": dependencies are not installed, run `rustpen setup --install-deps`".to_string()]
+        );
+
+        let mut lines = vec![];
+        install(true, &mut |line| lines.push(line)).unwrap();
+        assert!(lines.contains(&format!("Running npm install in {}", host_dir.display())));
+        assert!(host_dir.join("node_modules").is_dir());
+        assert_eq!(problems(), Vec::<String>::new());
+    }
+}
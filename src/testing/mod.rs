@@ -0,0 +1,70 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use crate::{
+    buffers::buffer_list::open_file_buffer,
+    editor::Editor,
+    layout::{sync_layout, Layout},
+    match_char_with_special_keys,
+    screen::backend::TestBackend,
+};
+
+// An empty directory of its own for each test.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rustpen-test-{}-{}", process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn test_editor(root: &str, size: (u16, u16)) -> (Editor, TestBackend) {
+    let backend = TestBackend::new(size);
+    let mut editor = Editor::new(Box::new(backend.clone()), root.to_string());
+    editor.config.editor.swap_file = false;
+    (editor, backend)
+}
+
+// An editor on an 80x24 terminal showing a file with `text` in it.
+pub fn editor_with_text(name: &str, text: &str) -> (Editor, TestBackend) {
+    let dir = temp_dir(name);
+    let path = dir.join("file.txt");
+    fs::write(&path, text).unwrap();
+    let (mut editor, backend) = test_editor(dir.to_str().unwrap(), (80, 24));
+    let key = open_file_buffer(&mut editor, path.to_str());
+    editor.layout = Layout::new(&key);
+    editor.focus_buffer = key;
+    sync_layout(&mut editor);
+    (editor, backend)
+}
+
+// Each character is typed as its own key, `<Esc>` and the like are taken whole.
+pub fn type_keys(editor: &mut Editor, keys: &str) {
+    let mut rest = keys;
+    while let Some(ch) = rest.chars().next() {
+        if ch == '<' {
+            if let Some(end) = rest.find('>').filter(|end| *end > 1) {
+                editor.execute_key(&rest[..=end]);
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        editor.execute_key(&match_char_with_special_keys(&ch.to_string(), "<", ">"));
+        rest = &rest[ch.len_utf8()..];
+    }
+}
+
+pub fn buffer_text(editor: &Editor) -> String {
+    editor
+        .get_buffer(&editor.focus_buffer)
+        .map(|buffer| buffer.content.to_lines().join("\n"))
+        .unwrap_or_default()
+}
+
+pub fn cursor(editor: &Editor) -> (u16, u16) {
+    editor
+        .get_buffer(&editor.focus_buffer)
+        .and_then(|buffer| buffer.cursors.first())
+        .map(|cursor| cursor.position)
+        .unwrap()
+}
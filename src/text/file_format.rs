@@ -0,0 +1,181 @@
+use std::io;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Latin1,
+}
+
+// How a file was stored on disk, so saving it writes back the same bytes for
+// the parts that weren't edited.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub bom: bool,
+    pub encoding: Encoding,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        FileFormat {
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            bom: false,
+            encoding: Encoding::Utf8,
+        }
+    }
+}
+
+impl FileFormat {
+    // Returns the text with '\n' separated lines and no trailing newline.
+    pub fn decode(bytes: &[u8]) -> (String, FileFormat) {
+        let (bytes, bom) = match bytes.strip_prefix(UTF8_BOM) {
+            Some(rest) => (rest, true),
+            None => (bytes, false),
+        };
+
+        let (text, encoding) = match std::str::from_utf8(bytes) {
+            Ok(text) => (text.to_string(), Encoding::Utf8),
+            // Every byte is a valid Latin-1 char, so nothing is lost.
+            Err(_) => (
+                bytes.iter().map(|&byte| byte as char).collect(),
+                Encoding::Latin1,
+            ),
+        };
+
+        // Like vim, a file is CRLF only if every line is; stray '\r's stay in the text.
+        let newlines = text.matches('\n').count();
+        let line_ending = if newlines > 0 && text.matches("\r\n").count() == newlines {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        };
+
+        let mut text = match line_ending {
+            LineEnding::CrLf => text.replace("\r\n", "\n"),
+            LineEnding::Lf => text,
+        };
+        let final_newline = text.ends_with('\n');
+        if final_newline {
+            text.pop();
+        }
+
+        (
+            text,
+            FileFormat {
+                line_ending,
+                final_newline,
+                bom,
+                encoding,
+            },
+        )
+    }
+
+    pub fn encode<I, S>(&self, lines: I) -> io::Result<Vec<u8>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut text = String::new();
+        for (idx, line) in lines.into_iter().enumerate() {
+            if idx > 0 {
+                text.push_str(self.line_ending.as_str());
+            }
+            text.push_str(line.as_ref());
+        }
+        if self.final_newline {
+            text.push_str(self.line_ending.as_str());
+        }
+
+        let mut bytes = if self.bom { UTF8_BOM.to_vec() } else { vec![] };
+        match self.encoding {
+            Encoding::Utf8 => bytes.extend_from_slice(text.as_bytes()),
+            Encoding::Latin1 => {
+                for ch in text.chars() {
+                    let byte = u8::try_from(ch as u32).map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("Cannot write {:?} as latin1", ch),
+                        )
+                    })?;
+                    bytes.push(byte);
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Decodes `bytes`, checks the text, then encodes its lines back.
+    fn round_trip(bytes: &[u8], text: &str) -> FileFormat {
+        let (decoded, format) = FileFormat::decode(bytes);
+        assert_eq!(decoded, text);
+        assert_eq!(format.encode(decoded.split('\n')).unwrap(), bytes);
+        format
+    }
+
+    #[test]
+    fn crlf_files_stay_crlf() {
+        let format = round_trip(b"one\r\ntwo\r\n", "one\ntwo");
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert!(format.final_newline);
+    }
+
+    #[test]
+    fn mixed_line_endings_stay_lf() {
+        let format = round_trip(b"one\r\ntwo\nthree\r\n", "one\r\ntwo\nthree\r");
+        assert_eq!(format.line_ending, LineEnding::Lf);
+    }
+
+    #[test]
+    fn missing_final_newline_is_kept() {
+        let format = round_trip(b"one\ntwo", "one\ntwo");
+        assert!(!format.final_newline);
+        let format = round_trip(b"one\r\ntwo", "one\ntwo");
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert!(!format.final_newline);
+    }
+
+    #[test]
+    fn bom_is_kept_out_of_the_text() {
+        let format = round_trip(b"\xEF\xBB\xBFh\xC3\xA9\n", "hé");
+        assert!(format.bom);
+        assert_eq!(format.encoding, Encoding::Utf8);
+    }
+
+    #[test]
+    fn latin1_bytes_survive() {
+        let bytes: Vec<u8> = (0x20..=0xFF).chain([b'\n']).collect();
+        let (text, format) = FileFormat::decode(&bytes);
+        assert_eq!(format.encoding, Encoding::Latin1);
+        assert!(text.contains('é'));
+        assert_eq!(format.encode(text.split('\n')).unwrap(), bytes);
+
+        // Text typed since can't always go back to latin1.
+        assert!(format.encode(["界"]).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use ropey::{Rope, RopeSlice};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::undo::TextPosition;
+
+pub mod file_format;
+
+// Cursors hold u16 coordinates, so text past that is reached as far as they go.
+pub fn to_coord(value: usize) -> u16 {
+    u16::try_from(value).unwrap_or(u16::MAX)
+}
+
+// A `(line, col)` text position as an `(x, y)` cursor position.
+pub fn to_cursor(position: TextPosition) -> (u16, u16) {
+    (to_coord(position.1), to_coord(position.0))
+}
+
+// Cells a grapheme takes on screen. Control characters and lone combining
+// marks are drawn as a placeholder, so they still take one.
+pub fn grapheme_width(grapheme: &str) -> usize {
+    if grapheme.starts_with(char::is_control) {
+        return 1;
+    }
+    grapheme.width().max(1)
+}
+
+// Cells a grapheme starting at screen column `display` takes, with tabs
+// reaching the next multiple of `tab_width`.
+pub fn cell_width(grapheme: &str, display: usize, tab_width: u16) -> usize {
+    if grapheme == "\t" {
+        let tab_width = tab_width.max(1) as usize;
+        return tab_width - display % tab_width;
+    }
+    grapheme_width(grapheme)
+}
+
+// Graphemes of `text` with the char column they start at.
+pub fn grapheme_columns(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.graphemes(true).scan(0, |col, grapheme| {
+        let start = *col;
+        *col += grapheme.chars().count();
+        Some((start, grapheme))
+    })
+}
+
+// Lines are separated by '\n' only and the last line has no terminator, so a
+// `TextRope` always has at least one (possibly empty) line.
+#[derive(Clone, Default)]
+pub struct TextRope {
+    rope: Rope,
+}
+
+impl TextRope {
+    pub fn new() -> Self {
+        TextRope { rope: Rope::new() }
+    }
+
+    pub fn from_lines<I, S>(lines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut text = String::new();
+        for (idx, line) in lines.into_iter().enumerate() {
+            if idx > 0 {
+                text.push('\n');
+            }
+            text.push_str(line.as_ref());
+        }
+
+        TextRope {
+            rope: Rope::from_str(&text),
+        }
+    }
+
+    pub fn from_text(text: &str) -> Self {
+        TextRope {
+            rope: Rope::from_str(text),
+        }
+    }
+
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    pub fn char_at(&self, char_idx: usize) -> Option<char> {
+        if char_idx < self.rope.len_chars() {
+            Some(self.rope.char(char_idx))
+        } else {
+            None
+        }
+    }
+
+    pub fn line(&self, idx: usize) -> Option<RopeSlice<'_>> {
+        if idx >= self.rope.len_lines() {
+            return None;
+        }
+
+        let line = self.rope.line(idx);
+        let len = line.len_chars();
+        if len > 0 && line.char(len - 1) == '\n' {
+            Some(line.slice(..len - 1))
+        } else {
+            Some(line)
+        }
+    }
+
+    pub fn line_string(&self, idx: usize) -> String {
+        self.line(idx)
+            .map(|line| line.to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn line_len(&self, idx: usize) -> usize {
+        self.line(idx).map(|line| line.len_chars()).unwrap_or(0)
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = RopeSlice<'_>> {
+        (0..self.len_lines()).filter_map(|idx| self.line(idx))
+    }
+
+    pub fn to_lines(&self) -> Vec<String> {
+        self.lines().map(|line| line.to_string()).collect()
+    }
+
+    pub fn pos_to_char(&self, position: TextPosition) -> usize {
+        let line = position.0.min(self.len_lines().saturating_sub(1));
+        self.rope.line_to_char(line) + position.1.min(self.line_len(line))
+    }
+
+    pub fn char_to_pos(&self, char_idx: usize) -> TextPosition {
+        let char_idx = char_idx.min(self.len_chars());
+        let line = self.rope.char_to_line(char_idx);
+        (line, char_idx - self.rope.line_to_char(line))
+    }
+
+    pub fn insert(&mut self, position: TextPosition, text: &str) -> TextPosition {
+        let char_idx = self.pos_to_char(position);
+        self.rope.insert(char_idx, text);
+        self.char_to_pos(char_idx + text.chars().count())
+    }
+
+    pub fn remove(&mut self, start: TextPosition, end: TextPosition) -> String {
+        let from = self.pos_to_char(start);
+        let to = self.pos_to_char(end);
+        if from >= to {
+            return String::new();
+        }
+
+        let removed = self.rope.slice(from..to).to_string();
+        self.rope.remove(from..to);
+        removed
+    }
+
+    pub fn slice(&self, start: TextPosition, end: TextPosition) -> String {
+        let from = self.pos_to_char(start);
+        let to = self.pos_to_char(end);
+        if from >= to {
+            return String::new();
+        }
+        self.rope.slice(from..to).to_string()
+    }
+
+    // Cursor columns are char indices that always sit on a grapheme boundary.
+    pub fn grapheme_start(&self, line: usize, col: usize) -> usize {
+        let text = self.line_string(line);
+        for (start, grapheme) in grapheme_columns(&text) {
+            if col < start + grapheme.chars().count() {
+                return start;
+            }
+        }
+        col.min(self.line_len(line))
+    }
+
+    pub fn next_grapheme(&self, line: usize, col: usize) -> usize {
+        let text = self.line_string(line);
+        let next = grapheme_columns(&text)
+            .map(|(start, grapheme)| start + grapheme.chars().count())
+            .find(|end| *end > col);
+        next.unwrap_or(self.line_len(line))
+    }
+
+    pub fn prev_grapheme(&self, line: usize, col: usize) -> usize {
+        let text = self.line_string(line);
+        let prev = grapheme_columns(&text)
+            .map(|(start, _)| start)
+            .take_while(|start| *start < col)
+            .last();
+        prev.unwrap_or(0)
+    }
+
+    // Screen column of a char column, counting past the end of the line as
+    // one cell per column.
+    pub fn display_col(&self, line: usize, col: usize, tab_width: u16) -> usize {
+        let text = self.line_string(line);
+        let mut display = 0;
+        let mut end = 0;
+        for (start, grapheme) in grapheme_columns(&text) {
+            if start >= col {
+                return display;
+            }
+            display += cell_width(grapheme, display, tab_width);
+            end = start + grapheme.chars().count();
+        }
+        display + col.saturating_sub(end)
+    }
+
+    // Char column of the grapheme covering `display`, clamped to the line length.
+    pub fn col_at_display(&self, line: usize, display: usize, tab_width: u16) -> usize {
+        let text = self.line_string(line);
+        let mut width = 0;
+        for (start, grapheme) in grapheme_columns(&text) {
+            width += cell_width(grapheme, width, tab_width);
+            if width > display {
+                return start;
+            }
+        }
+        self.line_len(line)
+    }
+
+    pub fn end_of_text(position: TextPosition, text: &str) -> TextPosition {
+        match text.rsplit_once('\n') {
+            Some((head, last)) => (
+                position.0 + head.matches('\n').count() + 1,
+                last.chars().count(),
+            ),
+            None => (position.0, position.1 + text.chars().count()),
+        }
+    }
+}
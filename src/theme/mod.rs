@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fs;
+
+use serde::Deserialize;
+
+use crate::{
+    config::{config_dir, error_at},
+    editor::Rgb,
+    screen::{CellColor, CellStyle},
+};
+
+pub const DEFAULT_COLORSCHEME: &str = "default";
+// Colorschemes are read from `<config dir>/themes/<name>.toml`.
+pub const THEMES_DIR: &str = "themes";
+pub const DEFAULT_THEME: &str = include_str!("../../themes/default.toml");
+
+// `[r, g, b]` or an ANSI color number.
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Color {
+    Ansi(u8),
+    Rgb(Rgb),
+}
+
+impl From<Color> for CellColor {
+    fn from(color: Color) -> Self {
+        match color {
+            Color::Ansi(value) => CellColor::Ansi(value),
+            Color::Rgb(rgb) => CellColor::Rgb(rgb),
+        }
+    }
+}
+
+// What a group leaves unset is taken from the style it's drawn over.
+#[derive(Clone, PartialEq, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Highlight {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Highlight {
+    pub fn over(&self, base: CellStyle) -> CellStyle {
+        CellStyle {
+            fg: self.fg.map(CellColor::from).unwrap_or(base.fg),
+            bg: self.bg.map(CellColor::from).unwrap_or(base.bg),
+            bold: base.bold || self.bold,
+            italic: base.italic || self.italic,
+            underline: base.underline || self.underline,
+            reverse: base.reverse,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub name: String,
+    groups: HashMap<String, Highlight>,
+}
+
+// The built-in default, without looking at the themes directory.
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            name: DEFAULT_COLORSCHEME.to_string(),
+            groups: parse_colorscheme(DEFAULT_THEME).expect("The default colorscheme is valid"),
+        }
+    }
+}
+
+impl Theme {
+    // Groups in `overrides` replace the colorscheme's.
+    pub fn load(name: &str, overrides: &HashMap<String, Highlight>) -> Result<Theme, String> {
+        Ok(Theme::with_groups(name, read_colorscheme(name)?, overrides))
+    }
+
+    fn with_groups(
+        name: &str,
+        mut groups: HashMap<String, Highlight>,
+        overrides: &HashMap<String, Highlight>,
+    ) -> Theme {
+        groups.extend(overrides.clone());
+        Theme {
+            name: name.to_string(),
+            groups,
+        }
+    }
+
+    // `keyword.control` falls back to `keyword`.
+    pub fn get(&self, group: &str) -> Option<&Highlight> {
+        let mut group = group;
+        loop {
+            if let Some(highlight) = self.groups.get(group) {
+                return Some(highlight);
+            }
+            group = &group[..group.rfind('.')?];
+        }
+    }
+
+    pub fn apply(&self, group: &str, base: CellStyle) -> CellStyle {
+        match self.get(group) {
+            Some(highlight) => highlight.over(base),
+            None => base,
+        }
+    }
+
+    pub fn normal(&self) -> CellStyle {
+        self.apply("Normal", CellStyle::default())
+    }
+
+    // `group` drawn over `Normal`.
+    pub fn style(&self, group: &str) -> CellStyle {
+        self.apply(group, self.normal())
+    }
+}
+
+fn parse_colorscheme(text: &str) -> Result<HashMap<String, Highlight>, toml::de::Error> {
+    toml::from_str(text)
+}
+
+// A file in the themes directory comes before the built-in scheme of the same
+// name, so `default` can be edited too.
+pub fn read_colorscheme(name: &str) -> Result<HashMap<String, Highlight>, String> {
+    // A name is a file in the themes directory, never a path out of it.
+    if name.is_empty() || name.contains('/') || name.contains("..") {
+        return Err(format!("Invalid colorscheme name: {}", name));
+    }
+    let path = config_dir().map(|dir| dir.join(THEMES_DIR).join(format!("{}.toml", name)));
+    match path.filter(|path| path.exists()) {
+        Some(path) => {
+            let text = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            parse_colorscheme(&text).map_err(|e| error_at(&path, &text, &e))
+        }
+        None if name == DEFAULT_COLORSCHEME => Ok(Theme::default().groups),
+        None => Err(format!("Unknown colorscheme: {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::editor::Rgb;
+
+    fn highlight(text: &str) -> Highlight {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn groups_fall_back_to_their_parent() {
+        let groups = HashMap::from([
+            ("keyword".to_string(), highlight("fg = 1")),
+            ("keyword.control.flow".to_string(), highlight("fg = 2")),
+        ]);
+        let theme = Theme::with_groups("test", groups, &HashMap::new());
+        let fg = |group: &str| theme.get(group).and_then(|highlight| highlight.fg);
+
+        assert_eq!(fg("keyword"), Some(Color::Ansi(1)));
+        assert_eq!(fg("keyword.control"), Some(Color::Ansi(1)));
+        assert_eq!(fg("keyword.control.flow"), Some(Color::Ansi(2)));
+        assert_eq!(fg("keyword.control.flow.loop"), Some(Color::Ansi(2)));
+        assert_eq!(fg("string"), None);
+        assert_eq!(fg("keywords"), None);
+        assert_eq!(
+            theme.apply("string", CellStyle::default()),
+            CellStyle::default()
+        );
+    }
+
+    #[test]
+    fn highlights_keep_what_they_leave_unset() {
+        let base = CellStyle {
+            fg: CellColor::Ansi(7),
+            bg: CellColor::Rgb(Rgb(1, 2, 3)),
+            bold: true,
+            reverse: true,
+            ..CellStyle::default()
+        };
+        let style = highlight("fg = [10, 20, 30]\nitalic = true").over(base);
+        assert_eq!(
+            style,
+            CellStyle {
+                fg: CellColor::Rgb(Rgb(10, 20, 30)),
+                italic: true,
+                ..base
+            }
+        );
+        // Unset attributes can't turn the base's off.
+        assert_eq!(Highlight::default().over(base), base);
+    }
+
+    #[test]
+    fn config_groups_replace_the_colorscheme() {
+        let config: Config = toml::from_str(
+            "[theme]\ncolorscheme = \"default\"\n\n\
+             [theme.groups]\nkeyword = { fg = 3, bold = true }\nNormal = { bg = 0 }\n",
+        )
+        .unwrap();
+        let scheme = parse_colorscheme(DEFAULT_THEME).unwrap();
+        let theme = Theme::with_groups("default", scheme.clone(), &config.theme.groups);
+
+        // Replaced whole, not merged with the scheme's group.
+        assert_eq!(
+            theme.get("keyword"),
+            Some(&highlight("fg = 3\nbold = true"))
+        );
+        assert_eq!(theme.get("LineNr"), scheme.get("LineNr"));
+        assert_eq!(theme.normal().bg, CellColor::Ansi(0));
+        assert_eq!(theme.style("keyword").bg, CellColor::Ansi(0));
+    }
+
+    #[test]
+    fn colorscheme_names_stay_in_the_themes_dir() {
+        for name in ["../config", "a/b", "/etc/passwd", "..", ""] {
+            assert_eq!(
+                read_colorscheme(name),
+                Err(format!("Invalid colorscheme name: {}", name))
+            );
+        }
+        assert!(Theme::load("../../x", &HashMap::new()).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+
+pub type TextPosition = (usize, usize);
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum EditOperation {
+    Insert {
+        position: TextPosition,
+        text: String,
+    },
+    Delete {
+        position: TextPosition,
+        text: String,
+    },
+}
+
+impl EditOperation {
+    pub fn inverse(&self) -> EditOperation {
+        match self {
+            EditOperation::Insert { position, text } => EditOperation::Delete {
+                position: *position,
+                text: text.clone(),
+            },
+            EditOperation::Delete { position, text } => EditOperation::Insert {
+                position: *position,
+                text: text.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UndoTransaction {
+    pub operations: Vec<EditOperation>,
+    pub cursors_before: Vec<(u16, u16)>,
+    pub cursors_after: Vec<(u16, u16)>,
+}
+
+#[derive(Clone)]
+struct UndoNode {
+    parent: usize,
+    redo_child: Option<usize>,
+    transaction: UndoTransaction,
+}
+
+// Every committed transaction becomes a child of the current node, so undoing
+// and then editing again starts a new branch instead of dropping the old one.
+#[derive(Clone)]
+pub struct UndoTree {
+    nodes: Vec<UndoNode>,
+    current: usize,
+    saved: Option<usize>,
+    pending: Option<UndoTransaction>,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoTree {
+    pub fn new() -> Self {
+        UndoTree {
+            nodes: vec![UndoNode {
+                parent: 0,
+                redo_child: None,
+                transaction: UndoTransaction::default(),
+            }],
+            current: 0,
+            saved: Some(0),
+            pending: None,
+        }
+    }
+
+    pub fn record(&mut self, operation: EditOperation, cursors: Vec<(u16, u16)>) {
+        let pending = self.pending.get_or_insert_with(|| UndoTransaction {
+            operations: vec![],
+            cursors_before: cursors,
+            cursors_after: vec![],
+        });
+        pending.operations.push(operation);
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.pending
+            .as_ref()
+            .is_some_and(|pending| !pending.operations.is_empty())
+    }
+
+    pub fn commit(&mut self, cursors: Vec<(u16, u16)>) {
+        if let Some(mut transaction) = self.pending.take() {
+            if transaction.operations.is_empty() {
+                return;
+            }
+            transaction.cursors_after = cursors;
+
+            let idx = self.nodes.len();
+            self.nodes.push(UndoNode {
+                parent: self.current,
+                redo_child: None,
+                transaction,
+            });
+            self.nodes[self.current].redo_child = Some(idx);
+            self.current = idx;
+        }
+    }
+
+    pub fn undo(&mut self) -> Option<UndoTransaction> {
+        if self.current == 0 {
+            return None;
+        }
+
+        let node = &self.nodes[self.current];
+        let transaction = node.transaction.clone();
+        let parent = node.parent;
+
+        self.nodes[parent].redo_child = Some(self.current);
+        self.current = parent;
+
+        Some(transaction)
+    }
+
+    pub fn redo(&mut self) -> Option<UndoTransaction> {
+        let child = self.nodes[self.current].redo_child?;
+        self.current = child;
+
+        Some(self.nodes[child].transaction.clone())
+    }
+
+    // Moves to the state after change `target`, 0 being the text before any
+    // change: up to the branch point, then down the branch holding `target`.
+    // Returns the transactions to undo, then the ones to redo, in order.
+    pub fn jump(&mut self, target: usize) -> Option<(Vec<UndoTransaction>, Vec<UndoTransaction>)> {
+        if target >= self.nodes.len() {
+            return None;
+        }
+        let mut branch = vec![target];
+        while let Some(&node) = branch.last().filter(|&&node| node != 0) {
+            branch.push(self.nodes[node].parent);
+        }
+
+        let mut undone = vec![];
+        while !branch.contains(&self.current) {
+            undone.push(self.nodes[self.current].transaction.clone());
+            let parent = self.nodes[self.current].parent;
+            self.nodes[parent].redo_child = Some(self.current);
+            self.current = parent;
+        }
+
+        let mut redone = vec![];
+        let common = branch.iter().position(|&node| node == self.current)?;
+        for &node in branch[..common].iter().rev() {
+            self.nodes[self.current].redo_child = Some(node);
+            self.current = node;
+            redone.push(self.nodes[node].transaction.clone());
+        }
+        Some((undone, redone))
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.current);
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.has_pending() || self.saved != Some(self.current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{buffer_text, cursor, editor_with_text, type_keys};
+
+    fn insert(tree: &mut UndoTree, text: &str) {
+        let operation = EditOperation::Insert {
+            position: (0, 0),
+            text: text.to_string(),
+        };
+        tree.record(operation, vec![(0, 0)]);
+        tree.commit(vec![(text.len() as u16, 0)]);
+    }
+
+    fn inserted(transaction: Option<UndoTransaction>) -> String {
+        match transaction.map(|transaction| transaction.operations) {
+            Some(operations) => match operations.as_slice() {
+                [EditOperation::Insert { text, .. }] => text.clone(),
+                _ => panic!("Expected a single insert"),
+            },
+            None => String::new(),
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_across_branches() {
+        let mut tree = UndoTree::new();
+        insert(&mut tree, "a");
+        insert(&mut tree, "b");
+        assert_eq!(inserted(tree.undo()), "b");
+        insert(&mut tree, "c");
+        assert!(tree.is_modified());
+
+        // Redo follows the branch visited last.
+        assert_eq!(inserted(tree.undo()), "c");
+        assert_eq!(inserted(tree.redo()), "c");
+        assert_eq!(inserted(tree.redo()), "");
+
+        let (undone, redone) = tree.jump(2).unwrap();
+        assert_eq!(undone.len(), 1);
+        assert_eq!(inserted(redone.into_iter().next()), "b");
+        assert_eq!(inserted(tree.undo()), "b");
+        assert_eq!(inserted(tree.redo()), "b");
+
+        let (undone, redone) = tree.jump(0).unwrap();
+        assert_eq!((undone.len(), redone.len()), (2, 0));
+        assert!(!tree.is_modified());
+        assert!(tree.undo().is_none());
+        assert!(tree.jump(4).is_none());
+    }
+
+    #[test]
+    fn undo_number_moves_between_branches() {
+        let (mut editor, _) = editor_with_text("undo_number_moves_between_branches", "x");
+        type_keys(&mut editor, "i1<Esc>i2<Esc>ui3<Esc>");
+        assert_eq!(buffer_text(&editor), "13x");
+
+        type_keys(&mut editor, ":undo 2<Enter>");
+        assert_eq!(buffer_text(&editor), "12x");
+        type_keys(&mut editor, ":undo 0<Enter>");
+        assert_eq!(buffer_text(&editor), "x");
+        assert!(!editor.get_buffer(&editor.focus_buffer).unwrap().is_modified);
+        type_keys(&mut editor, ":undo 3<Enter>");
+        assert_eq!(buffer_text(&editor), "13x");
+        type_keys(&mut editor, ":undo 9<Enter>");
+        assert_eq!(buffer_text(&editor), "13x");
+        type_keys(&mut editor, "uu<C-r>");
+        assert_eq!(buffer_text(&editor), "1x");
+    }
+
+    #[test]
+    fn undo_restores_the_cursor() {
+        let (mut editor, _) = editor_with_text("undo_restores_the_cursor", "one\ntwo\nthree");
+        type_keys(&mut editor, "jdd");
+        assert_eq!(buffer_text(&editor), "one\nthree");
+        type_keys(&mut editor, "ggu");
+        assert_eq!(buffer_text(&editor), "one\ntwo\nthree");
+        assert_eq!(cursor(&editor), (0, 1));
+        type_keys(&mut editor, "G<C-r>");
+        assert_eq!(buffer_text(&editor), "one\nthree");
+        assert_eq!(cursor(&editor), (0, 1));
+    }
+}
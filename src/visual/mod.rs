@@ -0,0 +1,262 @@
+use crate::{
+    command::{error_message, push_message, CommandPrompt},
+    editor::{ColorRange, Editor, EditorBuffer},
+    editor_modes::EditorMode,
+    normal::pending::handle_visual_pending_key,
+    operator::{apply_operator, block_line_range, Operator, OperatorRange},
+    text::to_coord,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VisualKind {
+    Charwise,
+    Linewise,
+    Blockwise,
+}
+
+impl VisualKind {
+    pub fn from_key(key: &str) -> Option<VisualKind> {
+        match key {
+            "<v>" => Some(VisualKind::Charwise),
+            "<V>" => Some(VisualKind::Linewise),
+            "<C-v>" => Some(VisualKind::Blockwise),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            VisualKind::Charwise => "--VISUAL--",
+            VisualKind::Linewise => "--VISUAL LINE--",
+            VisualKind::Blockwise => "--VISUAL BLOCK--",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Selection {
+    pub anchor: (u16, u16),
+    pub kind: VisualKind,
+}
+
+pub fn start_visual(editor: &mut Editor, buffer: &mut EditorBuffer, kind: VisualKind) {
+    if let Some(cursor) = buffer.cursors.first() {
+        buffer.selection = Some(Selection {
+            anchor: cursor.position,
+            kind,
+        });
+        editor.editor_mode = EditorMode::Visual;
+    }
+}
+
+// Screen columns the grapheme at `col` covers, one past the end of the line.
+fn grapheme_cells(buffer: &EditorBuffer, line: usize, col: usize) -> (usize, usize) {
+    let content = &buffer.content;
+    let start = content.display_col(line, col, buffer.tab_width);
+    let end = match col < content.line_len(line) {
+        true => content.display_col(line, content.next_grapheme(line, col), buffer.tab_width),
+        false => start + 1,
+    };
+    (start, end)
+}
+
+pub fn selection_range(buffer: &EditorBuffer) -> Option<OperatorRange> {
+    let selection = buffer.selection?;
+    let cursor = buffer.cursors.first()?.position;
+
+    let (anchor_x, anchor_y) = (selection.anchor.0 as usize, selection.anchor.1 as usize);
+    let (cursor_x, cursor_y) = (cursor.0 as usize, cursor.1 as usize);
+
+    let range = match selection.kind {
+        VisualKind::Charwise => {
+            let (start, end) = if (anchor_y, anchor_x) <= (cursor_y, cursor_x) {
+                ((anchor_y, anchor_x), (cursor_y, cursor_x))
+            } else {
+                ((cursor_y, cursor_x), (anchor_y, anchor_x))
+            };
+
+            let end = if end.1 < buffer.line_len(end.0) {
+                (end.0, buffer.content.next_grapheme(end.0, end.1))
+            } else if end.0 + 1 < buffer.content.len_lines() {
+                (end.0 + 1, 0)
+            } else {
+                (end.0, buffer.line_len(end.0))
+            };
+
+            OperatorRange::Chars { start, end }
+        }
+        VisualKind::Linewise => OperatorRange::Lines {
+            start: anchor_y.min(cursor_y),
+            end: anchor_y.max(cursor_y),
+        },
+        VisualKind::Blockwise => {
+            let (anchor_cols, cursor_cols) = (
+                grapheme_cells(buffer, anchor_y, anchor_x),
+                grapheme_cells(buffer, cursor_y, cursor_x),
+            );
+            OperatorRange::Block {
+                lines: (anchor_y.min(cursor_y), anchor_y.max(cursor_y)),
+                cols: (
+                    anchor_cols.0.min(cursor_cols.0),
+                    anchor_cols.1.max(cursor_cols.1),
+                ),
+            }
+        }
+    };
+
+    Some(range)
+}
+
+pub fn selection_color_ranges(buffer: &EditorBuffer, line: usize) -> Vec<ColorRange> {
+    let Some(range) = selection_range(buffer) else {
+        return vec![];
+    };
+
+    let highlight = |from: usize, to: usize| ColorRange {
+        range: (to_coord(from), to_coord(to.max(from + 1))),
+        bg_color: None,
+        fg_color: None,
+        group: Some("Visual".to_string()),
+    };
+
+    match range {
+        OperatorRange::Chars { start, end } if start.0 <= line && line <= end.0 => {
+            let from = if line == start.0 { start.1 } else { 0 };
+            let to = if line == end.0 {
+                end.1
+            } else {
+                buffer.line_len(line) + 1
+            };
+            if from < to {
+                vec![highlight(from, to)]
+            } else {
+                vec![]
+            }
+        }
+        OperatorRange::Lines { start, end } if start <= line && line <= end => {
+            vec![highlight(0, buffer.line_len(line))]
+        }
+        OperatorRange::Block { lines, cols } if lines.0 <= line && line <= lines.1 => {
+            let (from, to) = block_line_range(buffer, line, cols);
+            if from < to {
+                vec![highlight(from, to)]
+            } else {
+                vec![]
+            }
+        }
+        _ => vec![],
+    }
+}
+
+fn exit_visual(editor: &mut Editor, buffer: &mut EditorBuffer) {
+    if let (Some(selection), Some(cursor)) = (buffer.selection.take(), buffer.cursors.first()) {
+        let (anchor, cursor) = (selection.anchor.1 as usize, cursor.position.1 as usize);
+        buffer.visual_marks = Some((anchor.min(cursor), anchor.max(cursor)));
+    }
+    editor.pending_command = Default::default();
+    editor.editor_mode = EditorMode::Normal;
+}
+
+fn apply_to_selection(
+    editor: &mut Editor,
+    buffer: &mut EditorBuffer,
+    register: Option<char>,
+    operator: Operator,
+) {
+    let applied = selection_range(buffer)
+        .map(|range| apply_operator(buffer, &mut editor.registers, register, operator, range));
+    exit_visual(editor, buffer);
+    match applied {
+        Some(Err(message)) => push_message(editor, error_message(&message)),
+        _ if operator == Operator::Change => editor.editor_mode = EditorMode::Insert,
+        _ => {}
+    }
+}
+
+// Counts, `"x` and motions go through the same grammar as in Normal mode,
+// what's left acts on the selection.
+pub fn match_keys_visual(editor: &mut Editor, buffer: &mut EditorBuffer, key: &str) {
+    if handle_visual_pending_key(editor, buffer, key) {
+        return;
+    }
+    let register = std::mem::take(&mut editor.pending_command).register();
+
+    if let Some(operator) = Operator::from_key(key) {
+        apply_to_selection(editor, buffer, register, operator);
+        return;
+    }
+
+    if let Some(kind) = VisualKind::from_key(key) {
+        match buffer.selection.as_mut() {
+            Some(selection) if selection.kind != kind => selection.kind = kind,
+            _ => exit_visual(editor, buffer),
+        }
+        return;
+    }
+
+    match key {
+        "<Esc>" | "<C-c>" => exit_visual(editor, buffer),
+        "<:>" => {
+            exit_visual(editor, buffer);
+            editor.editor_mode = EditorMode::Command;
+            editor.command_prompt = CommandPrompt::Ex;
+            editor.commands_hist.push("'<,'>".to_string());
+        }
+        "<o>" => {
+            if let (Some(selection), Some(cursor)) =
+                (buffer.selection.as_mut(), buffer.cursors.first_mut())
+            {
+                std::mem::swap(&mut selection.anchor, &mut cursor.position);
+            }
+        }
+        "<x>" => apply_to_selection(editor, buffer, register, Operator::Delete),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor_modes::EditorMode;
+    use crate::testing::{buffer_text, cursor, editor_with_text, type_keys};
+
+    #[test]
+    fn motions_counts_and_registers() {
+        let text = "one two three\nfour\nfive\nsix";
+        let (mut editor, _) = editor_with_text("visual_motions_counts_and_registers", text);
+        type_keys(&mut editor, "v2wd");
+        assert_eq!(buffer_text(&editor), "hree\nfour\nfive\nsix");
+
+        type_keys(&mut editor, "jv$\"ay");
+        assert_eq!(editor.editor_mode, EditorMode::Normal);
+        assert_eq!(editor.registers.get(Some('a')).unwrap().content, "four");
+
+        type_keys(&mut editor, "GVgg\"bd");
+        assert_eq!(buffer_text(&editor), "");
+        let deleted = editor.registers.get(Some('b')).unwrap().content;
+        assert_eq!(deleted, "hree\nfour\nfive\nsix\n");
+
+        type_keys(&mut editor, "\"bPjvG0y");
+        assert_eq!(cursor(&editor), (0, 1));
+        assert_eq!(
+            editor.registers.get(None).unwrap().content,
+            "four\nfive\nsix\n"
+        );
+    }
+
+    #[test]
+    fn blocks_span_screen_columns() {
+        let text = "a\tbc\nabcdefghij\nab界cd\nabcdef";
+        let (mut editor, _) = editor_with_text("visual_blocks_span_screen_columns", text);
+        type_keys(&mut editor, "2l<C-v>jd");
+        assert_eq!(buffer_text(&editor), "a\tc\nabcdfghij\nab界cd\nabcdef");
+
+        type_keys(&mut editor, "2j0l<C-v>jly");
+        let yanked = editor.registers.get(None).unwrap().content;
+        assert_eq!(yanked, "b界\nbc");
+        type_keys(&mut editor, "l<C-v>jd");
+        assert_eq!(buffer_text(&editor), "a\tc\nabcdfghij\nabcd\nabef");
+
+        type_keys(&mut editor, "gg$P");
+        assert_eq!(buffer_text(&editor), "a\t界c\nabcdcdfghij\nabcd\nabef");
+    }
+}